@@ -1,10 +1,19 @@
+use std::{
+    fmt::{self, Display},
+    path::Path,
+};
+
 use anyhow::{anyhow, bail, Error};
+use chrono_tz::Tz;
 use clap::ValueEnum;
+use encoding_rs::{Encoding as Charset, REPLACEMENT, UTF_16BE, UTF_16LE};
 use parquet::{
     basic::{BrotliLevel, Compression, Encoding, GzipLevel, ZstdLevel},
     errors::ParquetError,
 };
 
+use crate::query::{JsonType, QuirksFile, PROVENANCE_KEY_PREFIX};
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum EncodingArgument {
     System,
@@ -29,6 +38,57 @@ impl EncodingArgument {
     }
 }
 
+/// Output format of the `describe` subcommand.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DescribeFormat {
+    /// Aligned columns, intended to be read by humans.
+    Table,
+    /// Intended to be processed by other tools.
+    Json,
+}
+
+/// How to handle values of JSON columns, which are not valid JSON or do not match the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JsonInvalid {
+    /// Fail the query.
+    Error,
+    /// Write NULL instead. If a value does not match its type in the schema, only the value itself
+    /// is replaced with NULL, not the entire document.
+    Null,
+}
+
+/// How to handle text which is not valid UTF-8, if it is fetched using the system encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvalidUtf8 {
+    /// Fail the query.
+    Error,
+    /// Replace invalid sequences with the unicode replacement character.
+    Replace,
+    /// Write NULL instead.
+    Null,
+    /// Write the column as binary without UTF-8 annotation, keeping the bytes as they are.
+    Binary,
+}
+
+/// Time unit of timestamps in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimestampUnit {
+    Millis,
+    Micros,
+    Nanos,
+}
+
+/// Engine the output is intended to be read with. Selects a profile of settings known to work with
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compat {
+    Spark,
+    Hive,
+    Duckdb,
+    Bigquery,
+    Snowflake,
+}
+
 /// Mirrors parquets `Compression` enum in order to parse it from the command line
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum CompressionVariants {
@@ -95,3 +155,299 @@ pub fn column_encoding_from_str(source: &str) -> Result<(String, Encoding), Erro
     let (name, encoding) = source.split_at(pos);
     Ok((name.to_owned(), encoding_from_str(&encoding[1..])?))
 }
+
+pub fn column_compression_from_str(source: &str) -> Result<(String, Compression), Error> {
+    let mut parts = source.rsplitn(3, ':');
+    let (name, codec, level) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(level), Some(codec), Some(name)) if level.parse::<u32>().is_ok() => {
+            (name.to_owned(), codec, Some(level.parse::<u32>().unwrap()))
+        }
+        // The last part is not a level, so it must be the codec. Everything before is the name.
+        (Some(codec), Some(_), _) => {
+            let name = &source[..source.len() - codec.len() - 1];
+            (name.to_owned(), codec, None)
+        }
+        _ => bail!("Column compression must be parsed in format: 'COLUMN_NAME:CODEC[:LEVEL]'"),
+    };
+    let variant = CompressionVariants::from_str(codec, true)
+        .map_err(|_| anyhow!("Sorry, I do not know a compression codec called '{codec}'."))?;
+    Ok((name, variant.to_compression(level)?))
+}
+
+/// Bloom filter written for a column of the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    pub column: String,
+    /// False positive probability. Uses the default of the parquet writer, if `None`.
+    pub fpp: Option<f64>,
+    /// Expected number of distinct values. Uses the default of the parquet writer, if `None`.
+    pub ndv: Option<u64>,
+}
+
+pub fn bloom_filter_from_str(source: &str) -> Result<BloomFilter, Error> {
+    let parts: Vec<&str> = source.rsplitn(3, ':').collect();
+    let (column, fpp, ndv) = match parts.as_slice() {
+        [ndv, fpp, column] if fpp.parse::<f64>().is_ok() && ndv.parse::<u64>().is_ok() => {
+            (*column, Some(fpp.parse().unwrap()), Some(ndv.parse()?))
+        }
+        [fpp, ..] if fpp.parse::<f64>().is_ok() => {
+            let column = &source[..source.len() - fpp.len() - 1];
+            (column, Some(fpp.parse()?), None)
+        }
+        _ => (source, None, None),
+    };
+    if column.is_empty() {
+        bail!("Bloom filter must be parsed in format: 'COLUMN_NAME[:FPP[:NDV]]'")
+    }
+    if let Some(fpp) = fpp {
+        if !(fpp > 0. && fpp < 1.) {
+            bail!("False positive probability of bloom filter must be between 0 and 1. Got {fpp}.")
+        }
+    }
+    Ok(BloomFilter {
+        column: column.to_owned(),
+        fpp,
+        ndv,
+    })
+}
+
+/// Parquet type a column is mapped to, overriding the type inferred from the column description
+/// reported by the ODBC driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float,
+    Double,
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+    Date,
+    /// Precision is the number of fractional digits of the seconds, i.e. 3, 6 or 9.
+    Timestamp {
+        precision: u8,
+    },
+    Text,
+    Binary,
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::Boolean => write!(f, "boolean"),
+            ColumnType::Int8 => write!(f, "int8"),
+            ColumnType::Int16 => write!(f, "int16"),
+            ColumnType::Int32 => write!(f, "int32"),
+            ColumnType::Int64 => write!(f, "int64"),
+            ColumnType::Float => write!(f, "float"),
+            ColumnType::Double => write!(f, "double"),
+            ColumnType::Decimal { precision, scale } => write!(f, "decimal({precision},{scale})"),
+            ColumnType::Date => write!(f, "date"),
+            ColumnType::Timestamp { precision: 0..=3 } => write!(f, "timestamp-millis"),
+            ColumnType::Timestamp { precision: 4..=6 } => write!(f, "timestamp-micros"),
+            ColumnType::Timestamp { precision: _ } => write!(f, "timestamp-nanos"),
+            ColumnType::Text => write!(f, "text"),
+            ColumnType::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+pub fn column_type_from_str(source: &str) -> Result<ColumnType, Error> {
+    let column_type = match source {
+        "boolean" => ColumnType::Boolean,
+        "int8" => ColumnType::Int8,
+        "int16" => ColumnType::Int16,
+        "int32" => ColumnType::Int32,
+        "int64" => ColumnType::Int64,
+        "float" => ColumnType::Float,
+        "double" => ColumnType::Double,
+        "date" => ColumnType::Date,
+        "timestamp-millis" => ColumnType::Timestamp { precision: 3 },
+        "timestamp-micros" => ColumnType::Timestamp { precision: 6 },
+        "timestamp-nanos" => ColumnType::Timestamp { precision: 9 },
+        "text" => ColumnType::Text,
+        "binary" => ColumnType::Binary,
+        _ => {
+            let Some((precision, scale)) = source
+                .strip_prefix("decimal(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .and_then(|args| args.split_once(','))
+            else {
+                bail!(
+                    "Sorry, I do not know a column type called '{source}'. Known types are: \
+                    boolean, int8, int16, int32, int64, float, double, decimal(PRECISION,SCALE), \
+                    date, timestamp-millis, timestamp-micros, timestamp-nanos, text and binary."
+                )
+            };
+            let precision: u8 = precision.trim().parse()?;
+            let scale: u8 = scale.trim().parse()?;
+            if !(1..=38).contains(&precision) || scale > precision {
+                bail!(
+                    "Decimal precision must be between 1 and 38 and scale must not be larger than \
+                    precision. Got '{source}'."
+                )
+            }
+            ColumnType::Decimal { precision, scale }
+        }
+    };
+    Ok(column_type)
+}
+
+pub fn column_type_override_from_str(source: &str) -> Result<(String, ColumnType), Error> {
+    let pos = source
+        .rfind(':')
+        .ok_or_else(|| anyhow!("Column type must be parsed in format: 'COLUMN_NAME:TYPE'"))?;
+    let (name, column_type) = source.split_at(pos);
+    Ok((name.to_owned(), column_type_from_str(&column_type[1..])?))
+}
+
+/// Name of a JSON column and the schema read from the file, e.g. `payload:payload.json`.
+pub fn json_column_from_str(source: &str) -> Result<(String, JsonType), Error> {
+    let (name, path) = source.split_once(':').ok_or_else(|| {
+        anyhow!("JSON column must be parsed in format: 'COLUMN_NAME:SCHEMA_FILE'")
+    })?;
+    Ok((name.to_owned(), JsonType::from_file(Path::new(path))?))
+}
+
+/// Quirk definitions from a TOML file.
+pub fn quirks_file_from_str(source: &str) -> Result<QuirksFile, Error> {
+    QuirksFile::from_file(Path::new(source))
+}
+
+/// Time zone from the IANA time zone database, e.g. `Europe/Berlin`.
+pub fn time_zone_from_str(source: &str) -> Result<Tz, Error> {
+    source.parse().map_err(|_| {
+        anyhow!(
+            "Sorry, I do not know a time zone called '{source}'. Expected a name like \
+            'Europe/Berlin'."
+        )
+    })
+}
+
+/// Character set by one of its WHATWG labels, e.g. `windows-1252`, `latin1` or `shift_jis`.
+pub fn charset_from_str(source: &str) -> Result<&'static Charset, Error> {
+    let charset = Charset::for_label(source.as_bytes())
+        .ok_or_else(|| anyhow!("Sorry, I do not know a character set called '{source}'."))?;
+    // Narrow text can not be UTF-16 and the replacement encoding can not decode anything.
+    if charset == UTF_16LE || charset == UTF_16BE || charset == REPLACEMENT {
+        bail!(
+            "Character set '{source}' is not supported. Use `--encoding Utf16` to fetch text as \
+            UTF-16."
+        )
+    }
+    Ok(charset)
+}
+
+/// Custom key value metadata for the footer of the output files.
+pub fn key_value_from_str(source: &str) -> Result<(String, String), Error> {
+    let (key, value) = source
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Metadata must be parsed in format: 'KEY=VALUE'"))?;
+    if key.is_empty() {
+        bail!("Metadata key must not be empty.")
+    }
+    if key.starts_with(PROVENANCE_KEY_PREFIX) {
+        bail!("Metadata keys starting with '{PROVENANCE_KEY_PREFIX}' are reserved.")
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::basic::{Compression, ZstdLevel};
+
+    use super::{
+        bloom_filter_from_str, charset_from_str, column_compression_from_str,
+        column_type_override_from_str, key_value_from_str, BloomFilter, ColumnType,
+    };
+
+    #[test]
+    fn parse_key_value() {
+        assert_eq!(
+            ("owner".to_owned(), "team=data".to_owned()),
+            key_value_from_str("owner=team=data").unwrap()
+        );
+        assert_eq!(
+            ("empty".to_owned(), "".to_owned()),
+            key_value_from_str("empty=").unwrap()
+        );
+        assert!(key_value_from_str("owner").is_err());
+        assert!(key_value_from_str("=value").is_err());
+        assert!(key_value_from_str("odbc2parquet.query=SELECT 1").is_err());
+    }
+
+    #[test]
+    fn parse_charset() {
+        assert_eq!("windows-1252", charset_from_str("latin1").unwrap().name());
+        assert_eq!(
+            "windows-1252",
+            charset_from_str("windows-1252").unwrap().name()
+        );
+        assert_eq!("Shift_JIS", charset_from_str("shift_jis").unwrap().name());
+        assert!(charset_from_str("utf-16le").is_err());
+        assert!(charset_from_str("klingon").is_err());
+    }
+
+    #[test]
+    fn parse_bloom_filter() {
+        let bloom_filter = |column: &str, fpp, ndv| BloomFilter {
+            column: column.to_owned(),
+            fpp,
+            ndv,
+        };
+        assert_eq!(
+            bloom_filter("id", None, None),
+            bloom_filter_from_str("id").unwrap()
+        );
+        assert_eq!(
+            bloom_filter("id", Some(0.01), None),
+            bloom_filter_from_str("id:0.01").unwrap()
+        );
+        assert_eq!(
+            bloom_filter("id", Some(0.01), Some(1000)),
+            bloom_filter_from_str("id:0.01:1000").unwrap()
+        );
+        assert!(bloom_filter_from_str("id:2").is_err());
+    }
+
+    #[test]
+    fn parse_column_compression() {
+        assert_eq!(
+            (
+                "text".to_owned(),
+                Compression::ZSTD(ZstdLevel::try_new(19).unwrap())
+            ),
+            column_compression_from_str("text:zstd:19").unwrap()
+        );
+        assert_eq!(
+            ("a:b".to_owned(), Compression::SNAPPY),
+            column_compression_from_str("a:b:snappy").unwrap()
+        );
+        assert!(column_compression_from_str("snappy").is_err());
+        assert!(column_compression_from_str("a:unknown").is_err());
+    }
+
+    #[test]
+    fn parse_column_type_override() {
+        assert_eq!(
+            (
+                "price".to_owned(),
+                ColumnType::Decimal {
+                    precision: 18,
+                    scale: 4
+                }
+            ),
+            column_type_override_from_str("price:decimal(18,4)").unwrap()
+        );
+        assert_eq!(
+            ("ts".to_owned(), ColumnType::Timestamp { precision: 6 }),
+            column_type_override_from_str("ts:timestamp-micros").unwrap()
+        );
+        assert!(column_type_override_from_str("id:integer").is_err());
+        assert!(column_type_override_from_str("price:decimal(4,5)").is_err());
+    }
+}
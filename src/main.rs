@@ -2,18 +2,31 @@ mod enum_args;
 mod insert;
 mod parquet_buffer;
 mod query;
+mod run;
 
-use crate::enum_args::{column_encoding_from_str, EncodingArgument};
+use crate::enum_args::{
+    bloom_filter_from_str, charset_from_str, column_compression_from_str, column_encoding_from_str,
+    column_type_override_from_str, json_column_from_str, key_value_from_str, quirks_file_from_str,
+    time_zone_from_str, BloomFilter, ColumnType, Compat, DescribeFormat, EncodingArgument,
+    InvalidUtf8, JsonInvalid, TimestampUnit,
+};
 use anyhow::{bail, Error};
 use bytesize::ByteSize;
+use chrono_tz::Tz;
+use encoding_rs::Encoding as Charset;
 use enum_args::CompressionVariants;
 use io_arg::IoArg;
 use odbc_api::{
     escape_attribute_value, handles::OutputStringBuffer, Connection, ConnectionOptions,
     DriverCompleteOption, Environment,
 };
-use parquet::basic::Encoding;
-use std::{fs::File, path::PathBuf};
+use parquet::basic::{Compression, Encoding};
+use query::{JsonType, QuirksFile};
+use std::{
+    fs::File,
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
+};
 use stderrlog::ColorChoice;
 
 use clap::{ArgAction, Args, CommandFactory, Parser};
@@ -45,6 +58,8 @@ struct Cli {
     command: Command,
 }
 
+// Only ever constructed once while parsing the command line, so its size does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser)]
 enum Command {
     /// Query a data source and write the result as parquet.
@@ -52,10 +67,35 @@ enum Command {
         #[clap(flatten)]
         query_opt: QueryOpt,
     },
+    /// Execute all queries listed in a manifest file. All queries share the same connection to the
+    /// data source.
+    Run {
+        #[clap(flatten)]
+        run_opt: RunOpt,
+    },
+    /// Explain how each column of a query is mapped onto parquet, without fetching the result set.
+    /// Prints the ODBC column description, the chosen conversion strategy, the buffer bound to fetch
+    /// the column and the resulting parquet type. Also reports the batch size `query` would use with
+    /// the same options.
+    Describe {
+        #[clap(flatten)]
+        describe_opt: DescribeOpt,
+    },
     /// List available drivers and their attributes.
     ListDrivers,
     /// List preconfigured data sources. Useful to find data source name to connect to database.
     ListDataSources,
+    /// Print the workarounds applied to known data sources, identified by the name of their
+    /// database management system. The output can be used as a starting point for
+    /// `--quirks-file`.
+    ListQuirks {
+        /// Include the definitions from this file, as they would be applied with `--quirks-file`.
+        #[arg(long, value_parser = quirks_file_from_str)]
+        quirks_file: Option<QuirksFile>,
+        /// Omit the built-in definitions.
+        #[arg(long)]
+        ignore_built_in_quirks: bool,
+    },
     /// Read the content of a parquet and insert it into a table.
     Insert {
         #[clap(flatten)]
@@ -72,7 +112,7 @@ enum Command {
 }
 
 /// Command line arguments used to establish a connection with the ODBC data source
-#[derive(Args)]
+#[derive(Args, Clone)]
 struct ConnectOpts {
     #[arg(long, conflicts_with = "dsn")]
     /// Prompts the user for missing information from the connection string. Only supported on
@@ -98,10 +138,237 @@ struct ConnectOpts {
     password: Option<String>,
 }
 
+/// Command line arguments used to write the output into S3 compatible object storage. They are only
+/// used if the output starts with `s3://`.
+#[derive(Args, Clone)]
+pub struct S3Opts {
+    /// URL of the S3 compatible endpoint, e.g. `http://localhost:9000` for a local MinIO instance.
+    /// Defaults to the Amazon S3 endpoint of the region.
+    #[arg(long, env = "AWS_ENDPOINT_URL")]
+    s3_endpoint: Option<String>,
+    /// Region of the bucket the output is written to.
+    #[arg(long, env = "AWS_REGION", default_value = "us-east-1")]
+    s3_region: String,
+    /// Access key id used to authenticate against S3. Requests are not signed if neither access
+    /// key id nor secret access key are specified.
+    #[arg(long, env = "AWS_ACCESS_KEY_ID")]
+    s3_access_key_id: Option<String>,
+    /// Secret access key used to authenticate against S3.
+    #[arg(long, env = "AWS_SECRET_ACCESS_KEY", hide_env_values = true)]
+    s3_secret_access_key: Option<String>,
+    /// Session token, in case temporary credentials are used.
+    #[arg(long, env = "AWS_SESSION_TOKEN", hide_env_values = true)]
+    s3_session_token: Option<String>,
+    /// Address the bucket as part of the path (`http://endpoint/bucket/key`), rather than as part
+    /// of the host name (`http://bucket.endpoint/key`). Most self hosted S3 compatible storages,
+    /// like MinIO, require this.
+    #[arg(long)]
+    s3_path_style: bool,
+    /// Each output file is uploaded in parts of this size. Only one part per file is held in memory
+    /// at once. S3 limits the number of parts of an upload to 10000, so this also limits the
+    /// maximum file size. Must be at least 5MiB. Values can be specified in SI units, e.g.
+    /// `--s3-part-size 128MiB`.
+    #[arg(long, default_value = "64MiB")]
+    s3_part_size: ByteSize,
+}
+
 #[derive(Args)]
 pub struct QueryOpt {
     #[clap(flatten)]
     connect_opts: ConnectOpts,
+    #[clap(flatten)]
+    batch_size_opts: BatchSizeOpts,
+    /// Maximum number of row groups in a single output parquet file. If this option is omitted or 0
+    /// a single output file is produces. Otherwise each output file is closed after the maximum
+    /// number of row groups have been written and a new one with the suffix `_n` is started. There n
+    /// is the of the produced output file starting at one for the first one. E.g. `out_01.par`,
+    /// `out_2.par`, ...
+    #[arg(long, default_value = "0")]
+    row_groups_per_file: u32,
+    /// Then the size of the currently written parquet files goes beyond this threshold the current
+    /// row group will be finished and then the file will be closed. So the file will be somewhat
+    /// larger than the threshold. All furthrer row groups will be written into new files to which
+    /// the threshold size limit is applied as well. If this option is not set, no size threshold is
+    /// applied. If the threshold is applied the first file name will have the suffix `_01`, the
+    /// second the suffix `_2` and so on. Therfore the first resulting file will be called e.g.
+    /// `out_1.par`, if `out.par` has been specified as the output argument.
+    /// Also note that this option will not act as an upper bound. It will act as a lower bound for
+    /// all but the last file, all others however will not be larger than this threshold by more
+    /// than the size of one row group. You can use the `batch_size_row` and `batch_size_memory`
+    /// options, or `row_group_size_rows` and `row_group_size_bytes`, to control the size of the row
+    /// groups. Do not expect the `batch_size_memory` however to be equal to the row group size. The
+    /// row group size depends on the actual data in the database, and is due to compression likely
+    /// much smaller. Values of this option can be specified in SI units. E.g.
+    /// `--file-size-threshold 1GiB`.
+    #[arg(long)]
+    file_size_threshold: Option<ByteSize>,
+    /// Maximum number of rows in a row group. By default every batch fetched from the data source
+    /// is written as its own row group. With this option batches are accumulated into row groups
+    /// of this many rows, or split into several row groups if they are larger. This way small
+    /// batches can be used to keep memory usage low, without producing many tiny row groups.
+    /// Files are only split in between batches, so a file may hold more row groups than specified
+    /// by `--row-groups-per-file`, if a single batch spans several row groups.
+    #[arg(long)]
+    row_group_size_rows: Option<NonZeroUsize>,
+    /// A row group is completed, once the size of its encoded and compressed values passes this
+    /// threshold. Like `--row-group-size-rows` this decouples the row group size from the batch
+    /// size. Values are accounted for once a page is written, so the row groups are somewhat larger
+    /// than the threshold. Values of this option can be specified in SI units. E.g.
+    /// `--row-group-size-bytes 128MiB`.
+    #[arg(long)]
+    row_group_size_bytes: Option<ByteSize>,
+    #[clap(flatten)]
+    mapping_opts: MappingOpts,
+    /// Default compression used by the parquet file writer.
+    #[arg(long, value_enum, default_value = "zstd")]
+    column_compression_default: CompressionVariants,
+    /// The `gzip`, `zstd` and `brotli` compression variants allow for specifying an explicit
+    /// compression level. If the selected compression variant does not support an explicit
+    /// compression level this option is ignored.
+    ///
+    /// Default compression level for `zstd` is 3
+    #[arg(long)]
+    column_compression_level_default: Option<u32>,
+    /// Compression used for an individual column, overriding `--column-compression-default`. You
+    /// can pass multiple values in format `COLUMN:CODEC[:LEVEL]`, e.g. `description:zstd:19` or
+    /// `id:snappy`. `CODEC` may be any of the variants of `--column-compression-default`. The
+    /// level is ignored for codecs which do not support one.
+    #[arg(
+        long,
+        value_parser=column_compression_from_str,
+        action = ArgAction::Append
+    )]
+    column_compression: Vec<(String, Compression)>,
+    /// Write a bloom filter for the specified column, allowing readers to skip row groups which do
+    /// not contain a value they are looking for. Format is `COLUMN[:FPP[:NDV]]`, with the false
+    /// positive probability `FPP` and the expected number of distinct values `NDV` per row group,
+    /// e.g. `customer_id:0.01:100000`. Can be specified multiple times.
+    #[arg(
+        long,
+        value_parser=bloom_filter_from_str,
+        action = ArgAction::Append
+    )]
+    bloom_filter: Vec<BloomFilter>,
+    /// Specify the fallback encoding of the parquet output column. You can parse mutliple values
+    /// in format `COLUMN:ENCODING`. `ENCODING` must be one of: `plain`, `delta-binary-packed`,
+    /// `delta-byte-array`, `delta-length-byte-array` or `rle`.
+    #[arg(
+        long,
+        value_parser=column_encoding_from_str,
+        action = ArgAction::Append
+    )]
+    parquet_column_encoding: Vec<(String, Encoding)>,
+    /// In case fetch results gets split into multiple files a suffix with a number will be appended
+    /// to each file name. Default suffix length is 2 leading to suffixes like e.g. `_03`. In case
+    /// you would expect thousands of files in your output you may want to set this to say `4` so
+    /// the zeros pad this to a 4 digit number in order to make the filenames more friendly for
+    /// lexical sorting.
+    #[clap(long, default_value = "2")]
+    suffix_length: usize,
+    /// In case the query comes back with a result set, but now rows, by default a file with only
+    /// schema information is still created. If you do not want to create any file in case the
+    /// result set is empty you can set this flag.
+    #[clap(long)]
+    no_empty_file: bool,
+    /// Name of an integer column used to split the result set into multiple partitions. Each
+    /// partition is fetched in parallel over its own connection and written into its own output
+    /// file. The files are named after the output, with the suffix `_part_n` appended, e.g.
+    /// `out_part_01.par`, `out_part_02.par`, ... . The query is wrapped in a subquery, so it must
+    /// be valid as such. E.g. many databases do not allow an `ORDER BY` clause in subqueries. All
+    /// partitions must be fetched with the same parquet schema.
+    #[arg(long, requires = "partition_count")]
+    partition_column: Option<String>,
+    /// Number of partitions the result set is split into, if `--partition-column` is specified.
+    /// This is also the number of connections opened to the data source in parallel.
+    #[arg(long, requires = "partition_column")]
+    partition_count: Option<NonZeroU32>,
+    /// Smallest value in the partition column to consider for splitting the result set into
+    /// partitions. Rows with smaller values, as well as NULLs, are fetched by the first partition.
+    /// If omitted, the minimum value of the partition column in the result set is queried.
+    #[arg(long, requires = "partition_column")]
+    partition_lower_bound: Option<i64>,
+    /// Largest value in the partition column to consider for splitting the result set into
+    /// partitions. Rows with larger values are fetched by the last partition. If omitted, the
+    /// maximum value of the partition column in the result set is queried.
+    #[arg(long, requires = "partition_column")]
+    partition_upper_bound: Option<i64>,
+    /// Name of a column whose values only ever increase for new or updated rows, e.g. a timestamp
+    /// of the last modification. Together with `--state-file` this only fetches rows with values
+    /// larger than the largest value written in the previous run. The query is wrapped in a
+    /// subquery to apply this filter, so it must be valid as such. Integer, date, timestamp and
+    /// text columns are supported. Text is compared byte by byte, so numbers fetched as text, e.g.
+    /// decimals, are rejected. Since only strictly larger values are fetched, rows inserted after a
+    /// run with a value equal to the watermark are not picked up by the next run. Choose a column
+    /// whose values are unique for new rows, or precise enough for this not to happen.
+    #[arg(
+        long,
+        requires = "state_file",
+        conflicts_with_all = ["partition_column", "partition_by"]
+    )]
+    incremental_column: Option<String>,
+    /// Path to a JSON file holding the largest value of `--incremental-column` written so far
+    /// (the high-water mark). If the file does not exist all rows are fetched. The file is only
+    /// updated after the output has been written completely and successfully. So should a run
+    /// fail, the next one will fetch the same rows again.
+    #[arg(long, requires = "incremental_column")]
+    state_file: Option<PathBuf>,
+    /// Comma separated names of columns used to split the output into Hive style partition
+    /// directories. If specified, the output is interpreted as a directory and each row is written
+    /// into `<output>/<column>=<value>/part-<n>.parquet`, e.g. `out/country=DE/year=2024/part-01.parquet`.
+    /// The partition columns are not part of the parquet files themselves, as their values are
+    /// encoded in the directory names. NULL values are written into a directory named
+    /// `__HIVE_DEFAULT_PARTITION__`. `--row-groups-per-file` and `--file-size-threshold` apply to
+    /// the files within each partition directory.
+    #[arg(long, value_delimiter = ',', conflicts_with = "partition_column")]
+    partition_by: Vec<String>,
+    #[clap(flatten)]
+    s3_opts: S3Opts,
+    /// Path to a JSON file, which is written after all output files are complete. It lists every
+    /// file written, together with its number of rows, size in bytes, number of row groups and
+    /// SHA-256 checksum. It also contains the query text and the parquet schema of the result set.
+    /// Since it is only written if the query succeeds, its presence can be used to tell whether
+    /// the output is complete.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// Additional key value metadata written into the footer of every output file, in format
+    /// `KEY=VALUE`. Can be specified multiple times. Independent of this option every file
+    /// contains the query text, parameters, DBMS name, extraction start and end time, the version
+    /// of odbc2parquet and the ODBC column types, using keys starting with `odbc2parquet.`.
+    #[arg(
+        long,
+        value_parser=key_value_from_str,
+        action = ArgAction::Append
+    )]
+    metadata: Vec<(String, String)>,
+    /// Write all files into a staging area first and only move them into place once the entire
+    /// result set has been fetched. If the query fails, all staged files are deleted, so no partial
+    /// output is left behind. Local files are staged in a hidden directory next to the output.
+    /// Uploads to S3 are only completed once everything has been written.
+    #[arg(long)]
+    transactional: bool,
+    /// Write an empty `_SUCCESS` file into the output directory, after all files have been moved
+    /// into place.
+    #[arg(long, requires = "transactional")]
+    success_marker: bool,
+    /// Name of the output parquet file. Use `-` to indicate that the output should be written to
+    /// standard out instead. Paths starting with `s3://`, e.g. `s3://bucket/prefix/out.par`, are
+    /// uploaded into S3 compatible object storage. See the `--s3-*` options for how to configure
+    /// endpoint and credentials. This option does nothing if the output is written to standard out.
+    output: IoArg,
+    /// Query executed against the ODBC data source. Question marks (`?`) can be used as
+    /// placeholders for positional parameters. E.g. "SELECT Name FROM Employees WHERE salary > ?;".
+    /// Instead of passing a query verbatum, you may pass a plain dash (`-`), to indicate that the
+    /// query should be read from standard input. In this case the entire input until EOF will be
+    /// considered the query.
+    query: String,
+    /// For each placeholder question mark (`?`) in the query text one parameter must be passed at
+    /// the end of the command line.
+    parameters: Vec<String>,
+}
+
+/// Command line arguments limiting the size of the batches fetched from the data source.
+#[derive(Args, Clone, Copy)]
+pub struct BatchSizeOpts {
     /// Size of a single batch in rows. The content of the data source is written into the output
     /// parquet files in batches. This way the content does never need to be materialized completely
     /// in memory at once. If `--batch-size-memory` is not specified this value defaults to 65535.
@@ -117,36 +384,20 @@ pub struct QueryOpt {
     /// not specified. If `--batch-size-row` is not specified no memory limit is applied by default.
     /// If both option are specified the batch size is the largest possible which satisfies both
     /// constraints. This option controls the size of the buffers of data in transit, and therefore
-    /// the memory usage of this tool. It indirectly controls the size of the row groups written to
-    /// parquet (since each batch is written as one row group). It is hard to make a generic
-    /// statement about how much smaller the average row group will be.
+    /// the memory usage of this tool. Unless `--row-group-size-rows` or `--row-group-size-bytes`
+    /// is specified, each batch is written as one row group, so this option indirectly controls
+    /// the size of the row groups written to parquet. It is hard to make a generic statement about
+    /// how much smaller the average row group will be.
     /// This options allows you to specify the memory usage using SI units. So you can pass `2Gib`,
     /// `600Mb` and so on.
     #[arg(long)]
     batch_size_memory: Option<ByteSize>,
-    /// Maximum number of batches in a single output parquet file. If this option is omitted or 0 a
-    /// single output file is produces. Otherwise each output file is closed after the maximum
-    /// number of batches have been written and a new one with the suffix `_n` is started. There n
-    /// is the of the produced output file starting at one for the first one. E.g. `out_01.par`,
-    /// `out_2.par`, ...
-    #[arg(long, default_value = "0")]
-    row_groups_per_file: u32,
-    /// Then the size of the currently written parquet files goes beyond this threshold the current
-    /// row group will be finished and then the file will be closed. So the file will be somewhat
-    /// larger than the threshold. All furthrer row groups will be written into new files to which
-    /// the threshold size limit is applied as well. If this option is not set, no size threshold is
-    /// applied. If the threshold is applied the first file name will have the suffix `_01`, the
-    /// second the suffix `_2` and so on. Therfore the first resulting file will be called e.g.
-    /// `out_1.par`, if `out.par` has been specified as the output argument.
-    /// Also note that this option will not act as an upper bound. It will act as a lower bound for
-    /// all but the last file, all others however will not be larger than this threshold by more
-    /// than the size of one row group. You can use the `batch_size_row` and `batch_size_memory`
-    /// options to control the size of the row groups. Do not expect the `batch_size_memory` however
-    /// to be equal to the row group size. The row group size depends on the actual data in the
-    /// database, and is due to compression likely much smaller. Values of this option can be
-    /// specified in SI units. E.g. `--file-size-threshold 1GiB`.
-    #[arg(long)]
-    file_size_threshold: Option<ByteSize>,
+}
+
+/// Command line arguments influencing how the columns of the result set are mapped onto parquet
+/// columns.
+#[derive(Args, Clone)]
+pub struct MappingOpts {
     /// You can use this to limit the transfer buffer size which is used for an individual variadic
     /// sized column.
     ///
@@ -166,16 +417,6 @@ pub struct QueryOpt {
     /// setting or determining buffer sizes.
     #[arg(long)]
     column_length_limit: Option<usize>,
-    /// Default compression used by the parquet file writer.
-    #[arg(long, value_enum, default_value = "zstd")]
-    column_compression_default: CompressionVariants,
-    /// The `gzip`, `zstd` and `brotli` compression variants allow for specifying an explicit
-    /// compression level. If the selected compression variant does not support an explicit
-    /// compression level this option is ignored.
-    ///
-    /// Default compression level for `zstd` is 3
-    #[arg(long)]
-    column_compression_level_default: Option<u32>,
     /// Encoding used for character data requested from the data source.
     ///
     /// `Utf16`: The tool will use 16Bit characters for requesting text from the data source,
@@ -195,15 +436,11 @@ pub struct QueryOpt {
     /// been introduced in an effort to increase the compatibility of the output with Apache Spark.
     #[clap(long)]
     prefer_varbinary: bool,
-    /// Specify the fallback encoding of the parquet output column. You can parse mutliple values
-    /// in format `COLUMN:ENCODING`. `ENCODING` must be one of: `plain`, `delta-binary-packed`,
-    /// `delta-byte-array`, `delta-length-byte-array` or `rle`.
-    #[arg(
-        long,
-        value_parser=column_encoding_from_str,
-        action = ArgAction::Append
-    )]
-    parquet_column_encoding: Vec<(String, Encoding)>,
+    /// Map GUID columns (e.g. `uniqueidentifier` on Microsoft SQL Server) to text, instead of
+    /// `FIXED_LEN_BYTE_ARRAY(16)` with logical type `UUID`. Useful if the driver does not support
+    /// fetching GUIDs as binary, or if the readers of the output do not understand UUIDs.
+    #[clap(long)]
+    uuid_as_text: bool,
     /// Tells the odbc2parquet, that the ODBC driver does not support binding 64 Bit integers (aka
     /// S_C_BIGINT in ODBC speak). This will cause the odbc2parquet to query large integers as text
     /// instead and convert them to 64 Bit integers itself. Setting this flag will not affect the
@@ -211,40 +448,173 @@ pub struct QueryOpt {
     /// can make queries work which did not before, because Oracle does not support 64 Bit integers.
     #[clap(long)]
     driver_does_not_support_64bit_integers: bool,
+    /// Avoid the logical type `DECIMAL` in the output, e.g. because it is processed by a tool
+    /// which does not support it. Decimals with scale 0 are written as 32 Bit or 64 Bit integers,
+    /// depending on their precision. All other decimals are written as text.
+    #[clap(long)]
+    avoid_decimal: bool,
     /// The IBM DB2 Linux ODBC drivers have been reported to return memory garbage instead of
     /// indicators for the string length. Setting this flag will cause `odbc2parquet` to rely on
     /// terminating zeroes, instead of indicators. This prevents `odbc2parquet` from disambiguating
-    /// between empty strings and `NULL``. As a side effect of this workaround empty might be mapped
-    /// to NULL. Currently this workaround is only active if UTF-8 is used. This should be the case
-    /// on non-window platforms by default, or if the `System` encoding is active.
+    /// between empty strings and `NULL`. As a side effect of this workaround empty strings in
+    /// nullable columns are mapped to NULL. The workaround applies to text fetched using either
+    /// encoding. If available, prefer a version of the IBM driver compiled with a 64 Bit `SQLLEN`
+    /// (its name ends in `o`), which does not suffer from this issue.
     #[clap(long)]
-    avoid_decimal: bool,
-    /// In case fetch results gets split into multiple files a suffix with a number will be appended
-    /// to each file name. Default suffix length is 2 leading to suffixes like e.g. `_03`. In case
-    /// you would expect thousands of files in your output you may want to set this to say `4` so
-    /// the zeros pad this to a 4 digit number in order to make the filenames more friendly for
-    /// lexical sorting.
-    #[clap(long, default_value = "2")]
-    suffix_length: usize,
-    /// In case the query comes back with a result set, but now rows, by default a file with only
-    /// schema information is still created. If you do not want to create any file in case the
-    /// result set is empty you can set this flag.
-    #[clap(long)]
-    no_empty_file: bool,
-    /// Name of the output parquet file. Use `-` to indicate that the output should be written to
-    /// standard out instead. This option does nothing if the output is written to standard out.
-    output: IoArg,
-    /// Query executed against the ODBC data source. Question marks (`?`) can be used as
-    /// placeholders for positional parameters. E.g. "SELECT Name FROM Employees WHERE salary > ?;".
-    /// Instead of passing a query verbatum, you may pass a plain dash (`-`), to indicate that the
-    /// query should be read from standard input. In this case the entire input until EOF will be
-    /// considered the query.
+    driver_returns_memory_garbage_for_indicators: bool,
+    /// TOML file with workarounds to apply for specific data sources, in addition to the built-in
+    /// ones. Each `[[quirk]]` table names a `dbms-name`, as reported by the driver, and the
+    /// workarounds to apply, e.g. `driver-does-not-support-64bit-integers = true`. A trailing `*` in
+    /// the name matches any suffix. Definitions in the file take precedence over built-in ones for
+    /// the same data source. Use `list-quirks` to print the built-in definitions.
+    #[arg(long, value_parser = quirks_file_from_str)]
+    quirks_file: Option<QuirksFile>,
+    /// Do not apply the built-in workarounds for known data sources, e.g. fetching 64 Bit integers
+    /// as text from Oracle. Definitions from `--quirks-file` are still applied.
+    #[arg(long)]
+    ignore_built_in_quirks: bool,
+    /// Map a column onto the specified parquet type, instead of inferring the type from the column
+    /// description reported by the ODBC driver. Useful if the driver reports wrong or overly
+    /// generic types, without resorting to a dialect specific `CAST` in the query. Format is
+    /// `COLUMN:TYPE`, e.g. `id:int64`. `TYPE` must be one of: `boolean`, `int8`, `int16`, `int32`,
+    /// `int64`, `float`, `double`, `decimal(PRECISION,SCALE)`, `date`, `timestamp-millis`,
+    /// `timestamp-micros`, `timestamp-nanos`, `text` or `binary`. The values are converted by the
+    /// ODBC driver, so the query fails if the driver can not convert them. Values of `int8` and
+    /// `int16` columns are fetched as 32 Bit integers, the query fails if one of them does not fit
+    /// into the narrower type. The unit of timestamp column types takes precedence over
+    /// `--timestamp-unit` and `--int96-timestamps`. Can be specified multiple times.
+    #[arg(
+        long,
+        value_parser=column_type_override_from_str,
+        action = ArgAction::Append
+    )]
+    column_type: Vec<(String, ColumnType)>,
+    /// Parse the JSON text of a column and write it as a nested parquet group, rather than as
+    /// text. Format is `COLUMN:SCHEMA_FILE`. The schema file describes the type of the JSON value,
+    /// which must be a `struct`, `list` or `map`, e.g.
+    /// `{"struct": [{"name": "id", "type": "int64"}, {"name": "tags", "type": {"list": "string"}}]}`.
+    /// Primitive types are `boolean`, `int32`, `int64`, `float`, `double` and `string`. Map keys
+    /// are always strings. Fields not part of the schema are dropped. Can be specified multiple
+    /// times.
+    #[arg(
+        long,
+        value_parser=json_column_from_str,
+        action = ArgAction::Append
+    )]
+    json_column: Vec<(String, JsonType)>,
+    /// How to handle values of `--json-column` columns, which are not valid JSON or do not match
+    /// the schema.
+    #[arg(long, value_enum, default_value = "error")]
+    json_invalid: JsonInvalid,
+    /// Time zone of the wall clock time stored in timestamp columns without time zone, e.g.
+    /// `Europe/Berlin`. If specified, these timestamps are converted to UTC and written with
+    /// instant semantics (`isAdjustedToUTC`). Wall clock times occurring twice, because clocks are
+    /// turned back at the end of daylight saving time, are mapped to the earlier instant. Wall
+    /// clock times which do not exist, because clocks are turned forward, are shifted later by the
+    /// length of the gap.
+    #[arg(long, value_parser = time_zone_from_str)]
+    source_timezone: Option<Tz>,
+    /// Time unit of all timestamps, instead of deriving it from the precision of each column. E.g.
+    /// `datetime2(7)` on Microsoft SQL Server is written with nanoseconds precision by default,
+    /// which some older readers do not support. Additional fraction digits are truncated.
+    /// Timestamps outside the range representable with nanoseconds precision cause an error.
+    #[arg(long, value_enum)]
+    timestamp_unit: Option<TimestampUnit>,
+    /// Write timestamps as legacy `INT96` values, as expected by older versions of Apache Hive and
+    /// Impala. `INT96` timestamps always have nanoseconds precision and no logical type, so readers
+    /// can not tell whether they are adjusted to UTC.
+    #[arg(long, conflicts_with = "timestamp_unit")]
+    int96_timestamps: bool,
+    /// Apply the settings required by the specified engine to read the output. Explicitly
+    /// specified options take precedence.
+    ///
+    /// `spark`: Implies `--prefer-varbinary`, `--uuid-as-text` and `--timestamp-unit micros`.
+    ///
+    /// `hive`: Implies `--prefer-varbinary`, `--uuid-as-text` and `--int96-timestamps`. Decimals
+    /// are always written as `FIXED_LEN_BYTE_ARRAY`.
+    ///
+    /// `duckdb`: Same as the defaults.
+    ///
+    /// `bigquery`: Implies `--uuid-as-text` and `--timestamp-unit micros`.
+    ///
+    /// `snowflake`: Implies `--uuid-as-text`.
+    ///
+    /// All profiles except `duckdb` write parquet format version 1 data pages. `spark`, `hive`
+    /// and `bigquery` replace characters other than ASCII letters, digits and underscores in
+    /// column names with underscores. Options referring to columns of the output, like
+    /// `--column-compression` or `--partition-by`, must use the replaced names.
+    #[arg(long, value_enum)]
+    compat: Option<Compat>,
+    /// Remove the trailing blanks padding the values of fixed length `CHAR` and `NCHAR` columns.
+    /// Variable length columns are not affected.
+    #[arg(long)]
+    trim_char_padding: bool,
+    /// Remove the trailing blanks padding the values of the fixed length `CHAR` or `NCHAR` column
+    /// with the specified name. Can be specified multiple times.
+    #[arg(long, action = ArgAction::Append)]
+    trim_char_padding_column: Vec<String>,
+    /// How to handle text which is not valid UTF-8. This can only happen if text is fetched using
+    /// the system encoding (see `--encoding`) and the data source does not return UTF-8, e.g.
+    /// because it holds Latin-1 encoded text. If `--source-charset` is specified, this applies to
+    /// text which is not valid in that character set instead. `replace` substitutes invalid
+    /// sequences with the unicode replacement character and logs a warning. `error` fails the
+    /// query, reporting the column and row. `null` writes NULL instead. `binary` writes all text
+    /// columns as binary without UTF-8 annotation, keeping the bytes of invalid values as they
+    /// are.
+    #[arg(long, value_enum, default_value = "replace")]
+    invalid_utf8: InvalidUtf8,
+    /// Character set of the text returned by the data source, e.g. `windows-1252`, `latin1` or
+    /// `shift_jis`. Text is fetched using narrow characters and transcoded to UTF-8. Useful for
+    /// drivers which return text in the codepage of the database, independent of the system locale
+    /// or binding wide character buffers. Accepts the labels defined by the WHATWG encoding
+    /// standard. Values which are not valid in this character set are handled according to
+    /// `--invalid-utf8`.
+    #[arg(long, value_parser = charset_from_str, conflicts_with = "encoding")]
+    source_charset: Option<&'static Charset>,
+}
+
+#[derive(Args)]
+pub struct DescribeOpt {
+    #[clap(flatten)]
+    connect_opts: ConnectOpts,
+    #[clap(flatten)]
+    batch_size_opts: BatchSizeOpts,
+    #[clap(flatten)]
+    mapping_opts: MappingOpts,
+    /// Print the description as aligned table or as JSON.
+    #[arg(long, value_enum, default_value = "table", ignore_case = true)]
+    format: DescribeFormat,
+    /// Execute the query instead of only preparing it. Some drivers can not describe the result
+    /// set of a statement, before it is executed. The result set is not fetched either way.
+    #[arg(long)]
+    execute: bool,
+    /// Query to describe. Question marks (`?`) can be used as placeholders for positional
+    /// parameters. Pass a plain dash (`-`) to read the query from standard input.
     query: String,
     /// For each placeholder question mark (`?`) in the query text one parameter must be passed at
-    /// the end of the command line.
+    /// the end of the command line. Only bound if `--execute` is specified.
     parameters: Vec<String>,
 }
 
+#[derive(Args)]
+pub struct RunOpt {
+    #[clap(flatten)]
+    connect_opts: ConnectOpts,
+    /// Stop at the first job which fails. By default all jobs in the manifest are executed, even
+    /// if some of them fail. Jobs which are not executed due to this option are reported as skipped.
+    #[arg(long)]
+    fail_fast: bool,
+    /// Path to a TOML file describing the jobs to execute. Each job is declared in a `[[job]]`
+    /// table with a `name`, an `output` path and either a `query` or a `query-file`. Optionally it
+    /// may also specify a list of `parameters` and an `options` table. The keys of the `options`
+    /// table are the long names of the options of the `query` subcommand, e.g.
+    /// `file-size-threshold = "1GiB"`. Options in a top level `options` table apply to every job,
+    /// unless overwritten by the job itself. Files ending in `.yaml` or `.yml` are parsed as YAML
+    /// with the same structure, using a `job` list instead of `[[job]]` tables. Relative paths
+    /// to query files are resolved against the directory containing the manifest.
+    manifest: PathBuf,
+}
+
 #[derive(Args)]
 pub struct InsertOpt {
     #[clap(flatten)]
@@ -277,13 +647,33 @@ impl Cli {
     /// clap.
     pub fn perform_extra_validation(&self) -> Result<(), Error> {
         if let Command::Query { query_opt } = &self.command {
-            if !query_opt.output.is_file() {
-                if query_opt.file_size_threshold.is_some() {
-                    bail!("file-size-threshold conflicts with specifying stdout ('-') as output.")
-                }
-                if query_opt.row_groups_per_file != 0 {
-                    bail!("row-groups-per-file conflicts with specifying stdout ('-') as output.")
-                }
+            query_opt.perform_extra_validation()?;
+        }
+        Ok(())
+    }
+}
+
+impl QueryOpt {
+    /// Validation of the query options which can not be expressed directly with clap.
+    pub fn perform_extra_validation(&self) -> Result<(), Error> {
+        if !self.output.is_file() {
+            if self.file_size_threshold.is_some() {
+                bail!("file-size-threshold conflicts with specifying stdout ('-') as output.")
+            }
+            if self.row_groups_per_file != 0 {
+                bail!("row-groups-per-file conflicts with specifying stdout ('-') as output.")
+            }
+            if self.partition_column.is_some() {
+                bail!("partition-column conflicts with specifying stdout ('-') as output.")
+            }
+            if !self.partition_by.is_empty() {
+                bail!("partition-by conflicts with specifying stdout ('-') as output.")
+            }
+            if self.manifest.is_some() {
+                bail!("manifest conflicts with specifying stdout ('-') as output.")
+            }
+            if self.transactional {
+                bail!("transactional conflicts with specifying stdout ('-') as output.")
             }
         }
         Ok(())
@@ -319,6 +709,15 @@ fn main() -> Result<(), Error> {
         .init()
         .unwrap();
 
+    // Listing quirks does not access a data source, so it must also work without a driver manager.
+    if let Command::ListQuirks {
+        quirks_file,
+        ignore_built_in_quirks,
+    } = &opt.command
+    {
+        return query::list_quirks(quirks_file.as_ref(), *ignore_built_in_quirks);
+    }
+
     // Initialize ODBC environment used to create the connection to the Database
     let odbc_env = Environment::new()?;
 
@@ -326,6 +725,12 @@ fn main() -> Result<(), Error> {
         Command::Query { query_opt } => {
             query::query(&odbc_env, query_opt)?;
         }
+        Command::Describe { describe_opt } => {
+            query::describe(&odbc_env, describe_opt)?;
+        }
+        Command::Run { run_opt } => {
+            run::run(&odbc_env, &run_opt)?;
+        }
         Command::Insert { insert_opt } => {
             insert::insert(&odbc_env, &insert_opt)?;
         }
@@ -351,6 +756,9 @@ fn main() -> Result<(), Error> {
                 println!("Driver: {}", data_source_info.driver);
             }
         }
+        Command::ListQuirks { .. } => {
+            unreachable!("Quirks are listed before allocating the environment.")
+        }
         Command::Completions { shell, output } => {
             let mut output = File::create(output)?;
             generate(shell, &mut Cli::command(), "odbc2parquet", &mut output);
@@ -1,9 +1,12 @@
 use anyhow::Error;
 use parquet::{
     column::{reader::ColumnReaderImpl, writer::ColumnWriterImpl},
-    data_type::{ByteArray, DataType, FixedLenByteArray, FixedLenByteArrayType},
+    data_type::{ByteArray, DataType, FixedLenByteArray, FixedLenByteArrayType, Int96},
+};
+use std::{
+    mem::{self, size_of},
+    sync::Arc,
 };
-use std::mem::size_of;
 
 /// Holds preallocated buffers for every possible physical parquet type. This way we do not need to
 /// reallocate them.
@@ -15,7 +18,38 @@ pub struct ParquetBuffer {
     pub values_bytes_array: Vec<ByteArray>,
     pub values_fixed_bytes_array: Vec<FixedLenByteArray>,
     pub values_bool: Vec<bool>,
+    pub values_int96: Vec<Int96>,
     pub def_levels: Vec<i16>,
+    /// Only required for list columns. Not accounted for in [`Self::MEMORY_USAGE_BYTES_PER_ROW`],
+    /// since it grows with the number of list elements, rather than with the number of rows.
+    pub rep_levels: Vec<i16>,
+    /// Position of the rows of the current batch within the result set. Used to point to invalid
+    /// values in error messages.
+    row_indices: RowIndices,
+}
+
+/// Position of each row of a batch within the entire result set.
+#[derive(Clone, Debug)]
+pub enum RowIndices {
+    /// Consecutive rows, starting with the specified index.
+    Consecutive(usize),
+    /// Rows selected from a batch, e.g. the ones belonging to one partition.
+    Selected(Arc<[usize]>),
+}
+
+impl RowIndices {
+    /// Index within the result set of the row with index `index` within the batch.
+    pub fn get(&self, index: usize) -> usize {
+        match self {
+            RowIndices::Consecutive(first) => first + index,
+            RowIndices::Selected(indices) => indices[index],
+        }
+    }
+
+    /// Position of the rows with the specified indices within the batch.
+    pub fn select(&self, rows: &[usize]) -> RowIndices {
+        RowIndices::Selected(rows.iter().map(|&index| self.get(index)).collect())
+    }
 }
 
 impl ParquetBuffer {
@@ -28,6 +62,7 @@ impl ParquetBuffer {
         + size_of::<ByteArray>()
         + size_of::<FixedLenByteArrayType>()
         + size_of::<bool>()
+        + size_of::<Int96>()
         + size_of::<i16>();
 
     pub fn new(batch_size: usize) -> ParquetBuffer {
@@ -39,10 +74,23 @@ impl ParquetBuffer {
             values_bytes_array: Vec::with_capacity(batch_size),
             values_fixed_bytes_array: Vec::with_capacity(batch_size),
             values_bool: Vec::with_capacity(batch_size),
+            values_int96: Vec::with_capacity(batch_size),
             def_levels: Vec::with_capacity(batch_size),
+            rep_levels: Vec::new(),
+            row_indices: RowIndices::Consecutive(0),
         }
     }
 
+    /// Position of the rows of the current batch within the result set.
+    pub fn set_row_indices(&mut self, row_indices: RowIndices) {
+        self.row_indices = row_indices;
+    }
+
+    /// Position of the rows of the current batch within the result set.
+    pub fn row_indices(&self) -> RowIndices {
+        self.row_indices.clone()
+    }
+
     pub fn set_num_rows_fetched(&mut self, num_rows: usize) {
         self.def_levels.resize(num_rows, 0);
         self.values_i32.resize(num_rows, 0);
@@ -53,6 +101,7 @@ impl ParquetBuffer {
         self.values_fixed_bytes_array
             .resize(num_rows, ByteArray::new().into());
         self.values_bool.resize(num_rows, false);
+        self.values_int96.resize(num_rows, Int96::new());
     }
 
     /// Writes an i128 twos complement representation into a fixed sized byte array
@@ -95,6 +144,13 @@ impl ParquetBuffer {
         Ok(())
     }
 
+    /// Byte arrays written by the last call to one of the `write_optional` methods, excluding NULL
+    /// values.
+    pub fn written_byte_arrays(&self) -> &[ByteArray] {
+        let num_values = self.def_levels.iter().filter(|&&level| level == 1).count();
+        &self.values_bytes_array[..num_values]
+    }
+
     /// Write to a parquet buffer using an iterator over optional source items. A default
     /// transformation, defined via the `IntoPhysical` trait is used to transform the items into
     /// buffer elements.
@@ -125,6 +181,92 @@ impl ParquetBuffer {
         self.write_optional_any_falliable(cw, source.map(Ok), |s| s)
     }
 
+    /// Write a list column with optional elements, using the standard three level list
+    /// representation. Each item of `source` holds the elements of the list in one row.
+    ///
+    /// * `list_is_optional`: Whether the list itself may be NULL.
+    pub fn write_list<T>(
+        &mut self,
+        cw: &mut ColumnWriterImpl<T>,
+        list_is_optional: bool,
+        source: impl Iterator<Item = Result<Option<Vec<Option<T::T>>>, Error>>,
+    ) -> Result<(), Error>
+    where
+        T: DataType,
+        T::T: BufferedDataType + Default,
+    {
+        // Definition level of a present element. One less for a NULL element, two less for an
+        // empty list and three less for a NULL list.
+        let max_def_level = if list_is_optional { 3 } else { 2 };
+        let mut rep_levels = mem::take(&mut self.rep_levels);
+        rep_levels.clear();
+        let (values, def_levels) = T::T::mut_buf(self);
+        let num_rows = def_levels.len();
+        values.clear();
+        def_levels.clear();
+        for list in source {
+            match list? {
+                None => {
+                    def_levels.push(max_def_level - 3);
+                    rep_levels.push(0);
+                }
+                Some(elements) if elements.is_empty() => {
+                    def_levels.push(max_def_level - 2);
+                    rep_levels.push(0);
+                }
+                Some(elements) => {
+                    for (index, element) in elements.into_iter().enumerate() {
+                        rep_levels.push(if index == 0 { 0 } else { 1 });
+                        if let Some(value) = element {
+                            values.push(value);
+                            def_levels.push(max_def_level);
+                        } else {
+                            def_levels.push(max_def_level - 1);
+                        }
+                    }
+                }
+            }
+        }
+        cw.write_batch(values, Some(def_levels), Some(&rep_levels))?;
+        // Other columns expect one element per row in the buffers.
+        values.resize_with(num_rows, Default::default);
+        def_levels.resize(num_rows, 0);
+        self.rep_levels = rep_levels;
+        Ok(())
+    }
+
+    /// Write a primitive column nested within groups, lists or maps. `source` yields the
+    /// definition and repetition level of every entry of the column, together with its value, if
+    /// it is defined.
+    pub fn write_nested<T>(
+        &mut self,
+        cw: &mut ColumnWriterImpl<T>,
+        source: impl Iterator<Item = Result<(i16, i16, Option<T::T>), Error>>,
+    ) -> Result<(), Error>
+    where
+        T: DataType,
+        T::T: BufferedDataType + Default,
+    {
+        let mut rep_levels = mem::take(&mut self.rep_levels);
+        rep_levels.clear();
+        let (values, def_levels) = T::T::mut_buf(self);
+        let num_rows = def_levels.len();
+        values.clear();
+        def_levels.clear();
+        for entry in source {
+            let (def_level, rep_level, value) = entry?;
+            def_levels.push(def_level);
+            rep_levels.push(rep_level);
+            values.extend(value);
+        }
+        cw.write_batch(values, Some(def_levels), Some(&rep_levels))?;
+        // Other columns expect one element per row in the buffers.
+        values.resize_with(num_rows, Default::default);
+        def_levels.resize(num_rows, 0);
+        self.rep_levels = rep_levels;
+        Ok(())
+    }
+
     /// Iterate over the elements of a column reader over an optional column.
     ///
     /// Be careful with calling this method on required columns as the bound definition buffer will
@@ -222,6 +364,12 @@ impl BufferedDataType for ByteArray {
     }
 }
 
+impl BufferedDataType for Int96 {
+    fn mut_buf(buffer: &mut ParquetBuffer) -> (&mut Vec<Self>, &mut Vec<i16>) {
+        (&mut buffer.values_int96, &mut buffer.def_levels)
+    }
+}
+
 impl BufferedDataType for FixedLenByteArray {
     fn mut_buf(buffer: &mut ParquetBuffer) -> (&mut Vec<Self>, &mut Vec<i16>) {
         (&mut buffer.values_fixed_bytes_array, &mut buffer.def_levels)
@@ -231,11 +379,24 @@ impl BufferedDataType for FixedLenByteArray {
 #[cfg(test)]
 mod test {
 
-    use super::ParquetBuffer;
+    use super::{ParquetBuffer, RowIndices};
 
     #[test]
     #[cfg(target_pointer_width = "64")] // Memory usage is platform dependent
     fn memory_usage() {
-        assert_eq!(59, ParquetBuffer::MEMORY_USAGE_BYTES_PER_ROW);
+        assert_eq!(71, ParquetBuffer::MEMORY_USAGE_BYTES_PER_ROW);
+    }
+
+    #[test]
+    fn row_indices_of_selected_rows() {
+        // Third batch of 10 rows
+        let batch = RowIndices::Consecutive(20);
+        // Rows of one partition, split again into row groups
+        let partition = batch.select(&[1, 4, 5, 8]);
+        let row_group = partition.select(&[2, 3]);
+
+        assert_eq!(23, batch.get(3));
+        assert_eq!(24, partition.get(1));
+        assert_eq!(vec![25, 28], vec![row_group.get(0), row_group.get(1)]);
     }
 }
@@ -2,104 +2,308 @@ mod batch_size_limit;
 mod binary;
 mod boolean;
 mod column_strategy;
+mod compat;
 mod current_file;
 mod date;
 mod decimal;
+mod describe;
+mod hive_partitioned;
 mod identical;
+mod incremental;
+mod json;
+mod list;
+mod manifest;
+mod narrow_integer;
 mod parquet_writer;
+mod partition;
+mod provenance;
+mod quirks;
+mod row_group;
+mod s3;
 mod table_strategy;
 mod text;
 mod time;
 mod timestamp;
 mod timestamp_precision;
 mod timestamp_tz;
+mod transaction;
+mod uuid;
 
-use anyhow::Error;
+use anyhow::{bail, Error};
+use chrono::Utc;
 use io_arg::IoArg;
 use log::info;
-use odbc_api::{Cursor, Environment, IntoParameter};
-use std::io::{stdin, Read};
+use odbc_api::{parameter::InputParameter, Connection, Cursor, Environment, IntoParameter};
+use parquet::file::metadata::KeyValue;
+use serde::Serialize;
+use std::{
+    io::{stdin, Read},
+    num::NonZeroUsize,
+    path::Path,
+    sync::Arc,
+};
+use tempfile::NamedTempFile;
+
+pub use self::{
+    describe::describe,
+    json::JsonType,
+    provenance::PROVENANCE_KEY_PREFIX,
+    quirks::{list_quirks, QuirksFile},
+};
 
 use self::{
-    batch_size_limit::{BatchSizeLimit, FileSizeLimit},
+    batch_size_limit::{BatchSizeLimit, FileSizeLimit, RowGroupSizeLimit},
     column_strategy::{ColumnStrategy, MappingOptions},
-    parquet_writer::{parquet_output, ParquetWriterOptions},
+    compat::CompatProfile,
+    incremental::Incremental,
+    manifest::{Manifest, WriteSummary},
+    parquet_writer::{output_storage, parquet_output, ParquetWriterOptions},
+    partition::PartitionedQuery,
+    provenance::{odbc_column_types, provenance},
+    quirks::{quirk_registry, quirks_for, Quirks},
     table_strategy::TableStrategy,
+    transaction::Transaction,
 };
 
-use crate::{open_connection, QueryOpt};
+use crate::{open_connection, BatchSizeOpts, MappingOpts, QueryOpt};
 
 /// Execute a query and writes the result to parquet.
 pub fn query(environment: &Environment, opt: QueryOpt) -> Result<(), Error> {
+    let odbc_conn = open_connection(environment, &opt.connect_opts)?;
+    query_with_connection(environment, &odbc_conn, opt)
+}
+
+/// Execute a query using an already established connection and write the result to parquet. The
+/// connection options in `opt` are only used to open additional connections, in case the result
+/// set is fetched in partitions.
+pub fn query_with_connection(
+    environment: &Environment,
+    odbc_conn: &Connection,
+    opt: QueryOpt,
+) -> Result<(), Error> {
     let QueryOpt {
         connect_opts,
         output,
         parameters,
         query,
-        batch_size_row,
-        batch_size_memory,
+        batch_size_opts,
         row_groups_per_file,
         file_size_threshold,
-        encoding,
-        prefer_varbinary,
+        row_group_size_rows,
+        row_group_size_bytes,
         column_compression_default,
         column_compression_level_default,
+        column_compression,
+        bloom_filter,
         parquet_column_encoding,
-        avoid_decimal,
-        driver_does_not_support_64bit_integers,
         suffix_length,
         no_empty_file,
-        column_length_limit,
+        mapping_opts,
+        partition_column,
+        partition_count,
+        partition_lower_bound,
+        partition_upper_bound,
+        incremental_column,
+        state_file,
+        partition_by,
+        s3_opts,
+        manifest,
+        metadata,
+        transactional,
+        success_marker,
     } = opt;
 
-    let batch_size = BatchSizeLimit::new(batch_size_row, batch_size_memory);
+    let extraction_start = Utc::now();
+    let batch_size = batch_size_opts.limit();
     let file_size = FileSizeLimit::new(row_groups_per_file, file_size_threshold);
     let query = query_statement_text(query)?;
 
-    // Convert the input strings into parameters suitable for use with ODBC.
-    let params: Vec<_> = parameters
-        .iter()
-        .map(|param| param.as_str().into_parameter())
-        .collect();
+    // Clap guarantees the state file is specified together with the incremental column.
+    let incremental = incremental_column
+        .map(|column| Incremental::load(column, state_file.unwrap()))
+        .transpose()?;
+
+    let mut params = input_parameters(&parameters);
 
-    let odbc_conn = open_connection(environment, &connect_opts)?;
     let db_name = odbc_conn.database_management_system_name()?;
     info!("Database Managment System Name: {db_name}");
+    let quirks = mapping_opts.quirks(&db_name);
+    prepare_session(odbc_conn, &quirks.session_statements)?;
+
+    let transaction = match &output {
+        IoArg::File(path) if transactional => {
+            let (storage, path) = output_storage(path.clone(), &s3_opts)?;
+            // Hive partitions are placed within the output directory, otherwise the files are
+            // next to the output path.
+            let root = if partition_by.is_empty() {
+                path.parent().map(Path::to_owned).unwrap_or_default()
+            } else {
+                path
+            };
+            Some(Arc::new(Transaction::begin(storage, root, success_marker)?))
+        }
+        _ => None,
+    };
+
+    let mut key_value_metadata = provenance(&query, &parameters, &db_name, extraction_start)?;
+    key_value_metadata.extend(
+        metadata
+            .into_iter()
+            .map(|(key, value)| KeyValue::new(key, value)),
+    );
 
     let parquet_format_options = ParquetWriterOptions {
         column_compression_default: column_compression_default
             .to_compression(column_compression_level_default)?,
+        column_compressions: column_compression,
+        bloom_filters: bloom_filter,
+        key_value_metadata,
         column_encodings: parquet_column_encoding,
         file_size,
+        row_group_size: RowGroupSizeLimit::new(
+            row_group_size_rows.map(NonZeroUsize::get),
+            row_group_size_bytes,
+        ),
         suffix_length,
         no_empty_file,
+        partition_by,
+        s3: s3_opts,
+        transaction: transaction.clone(),
+        writer_version: mapping_opts.compat_profile().writer_version,
     };
 
-    let mapping_options = MappingOptions {
-        db_name: &db_name,
-        use_utf16: encoding.use_utf16(),
-        prefer_varbinary,
-        avoid_decimal,
-        driver_does_support_i64: !driver_does_not_support_64bit_integers,
-        column_length_limit,
-    };
+    let mapping_options = mapping_opts.mapping_options(&quirks);
+
+    // Set, if the watermark of an incremental query must be persisted after writing the output.
+    let mut watermark = None;
 
-    if let Some(cursor) = odbc_conn.execute(&query, params.as_slice())? {
-        cursor_to_parquet(
+    let summary = if let Some(column) = partition_column {
+        let IoArg::File(path) = output else {
+            bail!("Partitioned output must be written to a file.")
+        };
+        let partitioned_query = PartitionedQuery {
+            environment,
+            connect_opts: &connect_opts,
+            query: &query,
+            parameters: &parameters,
+            column,
+            // Clap guarantees partition count is specified together with the column.
+            count: partition_count.unwrap(),
+            lower_bound: partition_lower_bound,
+            upper_bound: partition_upper_bound,
+            session_statements: &quirks.session_statements,
+        };
+        Some(partitioned_query.to_parquet(
+            odbc_conn,
+            &path,
+            batch_size,
+            mapping_options,
+            parquet_format_options,
+        )?)
+    } else if let Some(incremental) = incremental {
+        let incremental_query = incremental.query(&query);
+        params.extend(incremental.parameter());
+        let Some(mut cursor) = odbc_conn.execute(&incremental_query, params.as_slice())? else {
+            bail!("Incremental query must return a result set.")
+        };
+        let mut table_strategy = TableStrategy::new(&mut cursor, mapping_options)?;
+        watermark = Some(incremental.track(&mut cursor, &mut table_strategy)?);
+        Some(fetch_into_parquet(
+            cursor,
+            table_strategy,
+            output,
+            batch_size,
+            parquet_format_options,
+        )?)
+    } else if let Some(cursor) = odbc_conn.execute(&query, params.as_slice())? {
+        Some(cursor_to_parquet(
             cursor,
             output,
             batch_size,
             mapping_options,
             parquet_format_options,
-        )?;
+        )?)
     } else {
         eprintln!(
             "Query came back empty (not even a schema has been returned). No file has been created"
         );
+        None
+    };
+
+    if let Some(transaction) = transaction {
+        transaction.commit()?;
+    }
+    if let Some(path) = manifest {
+        Manifest::new(&query, summary.as_ref()).write(&path)?;
+    }
+    // Only now that the output is complete, we may remember how far we got.
+    if let Some(watermark) = watermark {
+        watermark.commit()?;
     }
     Ok(())
 }
 
+impl BatchSizeOpts {
+    fn limit(&self) -> BatchSizeLimit {
+        BatchSizeLimit::new(self.batch_size_row, self.batch_size_memory)
+    }
+}
+
+impl MappingOpts {
+    /// Workarounds applied for a data source with the specified DBMS name.
+    fn quirks(&self, db_name: &str) -> Quirks {
+        let registry = quirk_registry(self.quirks_file.as_ref(), self.ignore_built_in_quirks);
+        let quirks = quirks_for(db_name, &registry);
+        if quirks != Default::default() {
+            info!("Applying workarounds for '{db_name}': {quirks:?}");
+        }
+        quirks
+    }
+
+    /// Options for mapping the columns of a data source with the specified `quirks`.
+    fn mapping_options<'a>(&'a self, quirks: &'a Quirks) -> MappingOptions<'a> {
+        let compat = self.compat_profile();
+        // A timestamp unit specified explicitly takes precedence over INT96 timestamps implied by
+        // the profile.
+        let int96_timestamps =
+            self.int96_timestamps || (compat.int96_timestamps && self.timestamp_unit.is_none());
+        MappingOptions {
+            // Text in a different character set must be fetched as is, in order to transcode it.
+            use_utf16: self.encoding.use_utf16() && self.source_charset.is_none(),
+            prefer_varbinary: self.prefer_varbinary || compat.prefer_varbinary,
+            uuid_as_text: self.uuid_as_text || compat.uuid_as_text,
+            avoid_decimal: self.avoid_decimal,
+            fixed_len_decimals: compat.fixed_len_decimals,
+            driver_does_support_i64: !(self.driver_does_not_support_64bit_integers
+                || quirks.driver_does_not_support_64bit_integers),
+            column_length_limit: self.column_length_limit,
+            column_types: &self.column_type,
+            json_columns: &self.json_column,
+            json_invalid: self.json_invalid,
+            source_timezone: self.source_timezone,
+            timestamp_unit: self.timestamp_unit.or(compat.timestamp_unit),
+            int96_timestamps,
+            sanitize_column_names: compat.sanitize_column_names,
+            trim_char_padding: self.trim_char_padding,
+            trim_char_padding_columns: &self.trim_char_padding_column,
+            invalid_utf8: self.invalid_utf8,
+            source_charset: self.source_charset,
+            driver_returns_memory_garbage_for_indicators: self
+                .driver_returns_memory_garbage_for_indicators
+                || quirks.driver_returns_memory_garbage_for_indicators,
+            time_as_text: quirks.time_as_text,
+            datetimeoffset_as_text: quirks.datetimeoffset_as_text,
+            arrays_reported_as_text: quirks.arrays_reported_as_text,
+            time_zone_type_names: &quirks.time_zone_type_names,
+        }
+    }
+
+    /// Settings implied by `--compat`.
+    fn compat_profile(&self) -> CompatProfile {
+        CompatProfile::new(self.compat)
+    }
+}
+
 /// The query statement is either passed verbatim at the command line, or via stdin. The latter is
 /// indicated by passing `-` at the command line instead of the string. This method reads stdin
 /// until EOF if required and always returns the statement text.
@@ -113,18 +317,91 @@ fn query_statement_text(query: String) -> Result<String, Error> {
     })
 }
 
+/// Convert the parameters passed at the command line into parameters suitable for use with ODBC.
+fn input_parameters(parameters: &[String]) -> Vec<Box<dyn InputParameter>> {
+    parameters
+        .iter()
+        .map(|param| Box::new(param.clone().into_parameter()) as Box<dyn InputParameter>)
+        .collect()
+}
+
+/// Configures the session of a connection used to fetch data, so the text representation of values
+/// is understood by the column strategies. See [`Quirks::session_statements`].
+fn prepare_session(odbc_conn: &Connection, session_statements: &[String]) -> Result<(), Error> {
+    for statement in session_statements {
+        info!("Executing session statement: {statement}");
+        odbc_conn.execute(statement, ())?;
+    }
+    Ok(())
+}
+
+/// Index of the item named `name`. Names specified by the user are matched case insensitive, if no
+/// item with the exact name exists.
+fn position_by_name<T>(items: &[T], name: &str, item_name: impl Fn(&T) -> &str) -> Option<usize> {
+    items
+        .iter()
+        .position(|item| item_name(item) == name)
+        .or_else(|| {
+            items
+                .iter()
+                .position(|item| item_name(item).eq_ignore_ascii_case(name))
+        })
+}
+
+/// Query text without trailing semicolons, so it can be used as a subquery.
+fn subquery_text(query: &str) -> &str {
+    query.trim_end().trim_end_matches(';')
+}
+
 fn cursor_to_parquet(
     mut cursor: impl Cursor,
     path: IoArg,
     batch_size: BatchSizeLimit,
     mapping_options: MappingOptions,
     parquet_format_options: ParquetWriterOptions,
-) -> Result<(), Error> {
+) -> Result<WriteSummary, Error> {
     let table_strategy = TableStrategy::new(&mut cursor, mapping_options)?;
+    fetch_into_parquet(
+        cursor,
+        table_strategy,
+        path,
+        batch_size,
+        parquet_format_options,
+    )
+}
+
+/// Fetch the entire result set of `cursor` and write it to `path`, using the decisions in
+/// `table_strategy` of how to map each column.
+fn fetch_into_parquet(
+    mut cursor: impl Cursor,
+    table_strategy: TableStrategy,
+    path: IoArg,
+    batch_size: BatchSizeLimit,
+    mut parquet_format_options: ParquetWriterOptions,
+) -> Result<WriteSummary, Error> {
+    parquet_format_options
+        .key_value_metadata
+        .push(odbc_column_types(&mut cursor)?);
     let mut odbc_buffer = table_strategy.allocate_fetch_buffer(batch_size)?;
     let block_cursor = cursor.bind_buffer(&mut odbc_buffer)?;
     let parquet_schema = table_strategy.parquet_schema();
     let writer = parquet_output(path, parquet_schema.clone(), parquet_format_options)?;
-    table_strategy.block_cursor_to_parquet(block_cursor, writer)?;
+    let files = table_strategy.block_cursor_to_parquet(block_cursor, writer)?;
+    Ok(WriteSummary {
+        schema: parquet_schema,
+        files,
+    })
+}
+
+/// Write into a temporary file first and rename it afterwards, so the file is never left half
+/// written.
+fn write_json_atomically(path: &Path, value: &impl Serialize) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut file = NamedTempFile::new_in(dir)?;
+    serde_json::to_writer_pretty(&mut file, value)?;
+    file.persist(path)?;
     Ok(())
 }
@@ -15,6 +15,7 @@ const DEFAULT_BATCH_SIZE_BYTES: ByteSize = ByteSize::gib(1); // 1GB
 const DEFAULT_BATCH_SIZE_ROWS: usize = u16::MAX as usize; // 65535 rows
 
 /// Describes how we limit the size of individual parquet files.
+#[derive(Clone, Copy)]
 pub enum FileSizeLimit {
     /// No file size limit is applied. The entire output is written to one parquet file.
     None,
@@ -42,20 +43,67 @@ impl FileSizeLimit {
         !matches!(self, FileSizeLimit::None)
     }
 
-    pub fn should_start_new_file(&self, num_batch: u32, current_file_size: ByteSize) -> bool {
+    /// `num_row_groups` is the number of row groups written into the current file so far.
+    pub fn should_start_new_file(&self, num_row_groups: u32, current_file_size: ByteSize) -> bool {
         match self {
             FileSizeLimit::None => false,
-            FileSizeLimit::RowGroups(row_groups) => num_batch != 0 && num_batch % row_groups == 0,
+            FileSizeLimit::RowGroups(row_groups) => num_row_groups >= *row_groups,
             FileSizeLimit::Size(size) => &current_file_size >= size,
             FileSizeLimit::Both { row_groups, size } => {
-                (num_batch != 0 && num_batch % row_groups == 0) || &current_file_size >= size
+                num_row_groups >= *row_groups || &current_file_size >= size
             }
         }
     }
 }
 
+/// Describes how we limit the size of individual row groups. Independent of the size of the
+/// batches fetched from the data source.
+#[derive(Clone, Copy)]
+pub enum RowGroupSizeLimit {
+    /// Every batch fetched from the data source is written as one row group.
+    Batch,
+    Rows(usize),
+    /// Limits the size of the encoded and compressed values in the row group. Rows are only
+    /// accounted for once their page has been flushed, so row groups may grow larger than this.
+    Bytes(ByteSize),
+    Both {
+        rows: usize,
+        size: ByteSize,
+    },
+}
+
+impl RowGroupSizeLimit {
+    pub fn new(num_rows_limit: Option<usize>, size_limit: Option<ByteSize>) -> Self {
+        match (num_rows_limit, size_limit) {
+            (None, None) => Self::Batch,
+            (Some(rows), None) => Self::Rows(rows),
+            (None, Some(size)) => Self::Bytes(size),
+            (Some(rows), Some(size)) => Self::Both { rows, size },
+        }
+    }
+
+    /// Maximum number of rows in a row group. `None` if the number of rows is not limited.
+    pub fn max_rows(&self) -> Option<usize> {
+        match self {
+            RowGroupSizeLimit::Rows(rows) | RowGroupSizeLimit::Both { rows, .. } => Some(*rows),
+            RowGroupSizeLimit::Batch | RowGroupSizeLimit::Bytes(_) => None,
+        }
+    }
+
+    /// `true` if a row group with `num_rows` rows and `size` bytes must not grow any further.
+    pub fn is_full(&self, num_rows: usize, size: ByteSize) -> bool {
+        match self {
+            RowGroupSizeLimit::Batch => true,
+            RowGroupSizeLimit::Rows(rows) => num_rows >= *rows,
+            RowGroupSizeLimit::Bytes(limit) => &size >= limit,
+            RowGroupSizeLimit::Both { rows, size: limit } => num_rows >= *rows || &size >= limit,
+        }
+    }
+}
+
 /// Batches can be limitied by either number of rows or the total size of the rows in the batch in
 /// bytes.
+#[derive(Clone, Copy)]
 pub enum BatchSizeLimit {
     Rows(usize),
     Bytes(ByteSize),
@@ -1,10 +1,13 @@
-use std::{cmp::min, convert::TryInto, num::NonZeroUsize};
+use std::{any::type_name, cmp::min, convert::TryInto, num::NonZeroUsize, ptr::null_mut};
 
 use anyhow::{bail, Error};
+use chrono_tz::Tz;
+use encoding_rs::Encoding;
 use log::{debug, info};
 use odbc_api::{
     buffers::{AnySlice, BufferDesc},
-    sys::SqlDataType,
+    handles::Statement,
+    sys::{Desc, Pointer, SQLColAttribute, SqlDataType, SqlReturn},
     ColumnDescription, DataType, Nullability, ResultSetMetadata,
 };
 use parquet::{
@@ -17,17 +20,25 @@ use parquet::{
 };
 
 use crate::{
+    enum_args::{ColumnType, InvalidUtf8, JsonInvalid, TimestampUnit},
     parquet_buffer::ParquetBuffer,
     query::{
         binary::Binary,
         boolean::Boolean,
         date::Date,
-        decimal::decmial_fetch_strategy,
+        decimal::{decmial_fetch_strategy, int64_fetch_strategy},
         identical::{fetch_identical, fetch_identical_with_logical_type},
+        json::JsonType,
+        list::{list_strategy, postgres_array_element},
+        narrow_integer::NarrowInteger,
+        position_by_name,
+        quirks::is_time_zone_type_name,
         text::text_strategy,
         time::time_from_text,
         timestamp::timestamp_without_tz,
+        timestamp_precision::{TimestampOutput, TimestampPrecision},
         timestamp_tz::timestamp_tz,
+        uuid::Uuid,
     },
 };
 
@@ -46,17 +57,123 @@ pub trait ColumnStrategy {
         column_writer: &mut ColumnWriter,
         column_view: AnySlice,
     ) -> Result<(), Error>;
+    /// Number of primitive parquet columns written by this strategy. Larger than one for groups
+    /// with several fields.
+    fn num_leaves(&self) -> usize {
+        1
+    }
+    /// Copy the contents of an ODBC `AnySlice` into the `ColumnWriter` of the primitive column
+    /// with index `leaf`. Leaves are written in order, starting with the first one, for every
+    /// batch. Strategies with more than one leaf must implement this.
+    fn copy_odbc_to_parquet_leaf(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        leaf: usize,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        debug_assert_eq!(0, leaf);
+        self.copy_odbc_to_parquet(parquet_buffer, column_writer, column_view)
+    }
+    /// Name of the strategy, used to explain the mapping decisions to the user. E.g.
+    /// `IdenticalOptional<Int32Type>`.
+    fn name(&self) -> String {
+        short_type_name(type_name::<Self>())
+    }
+}
+
+/// Strips the module paths from a fully qualified type name, including those of its generic
+/// arguments.
+fn short_type_name(type_name: &str) -> String {
+    let mut short = String::new();
+    let mut segment = String::new();
+    for c in type_name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            segment.push(c);
+        } else {
+            short.push_str(segment.rsplit("::").next().unwrap());
+            segment.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(segment.rsplit("::").next().unwrap());
+    short
 }
 
 /// Controls how columns a queried and mapped onto parquet columns
 #[derive(Clone, Copy)]
 pub struct MappingOptions<'a> {
-    pub db_name: &'a str,
     pub use_utf16: bool,
     pub prefer_varbinary: bool,
+    /// Write GUIDs as text, rather than as parquet UUIDs.
+    pub uuid_as_text: bool,
     pub avoid_decimal: bool,
+    /// Write all decimals as `FIXED_LEN_BYTE_ARRAY`, rather than as `INT32` or `INT64` for small
+    /// precisions.
+    pub fixed_len_decimals: bool,
     pub driver_does_support_i64: bool,
     pub column_length_limit: Option<usize>,
+    /// Parquet types specified by the user for individual columns, overriding the inferred ones.
+    pub column_types: &'a [(String, ColumnType)],
+    /// Text columns parsed as JSON and written as nested groups with the specified type.
+    pub json_columns: &'a [(String, JsonType)],
+    /// How to handle values of JSON columns, which are not valid JSON or do not match the schema.
+    pub json_invalid: JsonInvalid,
+    /// Time zone of the wall clock time stored in timestamp columns without time zone. If set,
+    /// they are converted to UTC.
+    pub source_timezone: Option<Tz>,
+    /// Time unit of all timestamps, instead of the one derived from their precision.
+    pub timestamp_unit: Option<TimestampUnit>,
+    /// Write timestamps as legacy `INT96`.
+    pub int96_timestamps: bool,
+    /// Replace characters in column names, which are not supported by some readers.
+    pub sanitize_column_names: bool,
+    /// Remove the trailing blanks padding the values of all `CHAR` and `NCHAR` columns.
+    pub trim_char_padding: bool,
+    /// Remove the trailing blanks padding the values of these `CHAR` and `NCHAR` columns.
+    pub trim_char_padding_columns: &'a [String],
+    /// How to handle text which is not valid UTF-8, if fetched with narrow characters.
+    pub invalid_utf8: InvalidUtf8,
+    /// Character set of narrow text returned by the data source, if it is not UTF-8.
+    pub source_charset: Option<&'static Encoding>,
+    /// Rely on terminating zeroes, rather than indicators, to determine the length of text.
+    pub driver_returns_memory_garbage_for_indicators: bool,
+    /// Fetch columns with the driver specific SQL type `-154` as text and parse them as time.
+    pub time_as_text: bool,
+    /// Fetch columns with the driver specific SQL type `-155` as text and parse them as timestamps
+    /// with time zone.
+    pub datetimeoffset_as_text: bool,
+    /// Check character columns for being arrays, by their type name.
+    pub arrays_reported_as_text: bool,
+    /// Patterns for type names of timestamps with time zone, not reported as such by the driver.
+    pub time_zone_type_names: &'a [String],
+}
+
+impl MappingOptions<'_> {
+    /// Representation of timestamps with the specified number of fraction digits in parquet.
+    fn timestamp_output(&self, precision: u8) -> TimestampOutput {
+        if self.int96_timestamps {
+            return TimestampOutput::Int96;
+        }
+        let precision = match self.timestamp_unit {
+            None => TimestampPrecision::new(precision),
+            Some(TimestampUnit::Millis) => TimestampPrecision::Milliseconds,
+            Some(TimestampUnit::Micros) => TimestampPrecision::Microseconds,
+            Some(TimestampUnit::Nanos) => TimestampPrecision::Nanoseconds,
+        };
+        TimestampOutput::Int64(precision)
+    }
+
+    /// Whether to remove trailing blanks from the column with the specified name and type.
+    fn trim_padding(&self, name: &str, data_type: DataType) -> bool {
+        let is_fixed_length = matches!(data_type, DataType::Char { .. } | DataType::WChar { .. });
+        is_fixed_length
+            && (self.trim_char_padding
+                || self
+                    .trim_char_padding_columns
+                    .iter()
+                    .any(|column_name| column_name.eq_ignore_ascii_case(name)))
+    }
 }
 
 /// Fetch strategies based on column description and enviroment arguments `MappingOptions`.
@@ -79,12 +196,29 @@ pub fn strategy_from_column_description(
     index: i16,
 ) -> Result<Box<dyn ColumnStrategy>, Error> {
     let MappingOptions {
-        db_name,
         use_utf16,
         prefer_varbinary,
+        uuid_as_text,
         avoid_decimal,
+        fixed_len_decimals,
         driver_does_support_i64,
         column_length_limit,
+        column_types,
+        json_columns: _,
+        json_invalid: _,
+        source_timezone,
+        timestamp_unit: _,
+        int96_timestamps: _,
+        sanitize_column_names: _,
+        trim_char_padding: _,
+        trim_char_padding_columns: _,
+        invalid_utf8,
+        source_charset,
+        driver_returns_memory_garbage_for_indicators,
+        time_as_text,
+        datetimeoffset_as_text,
+        arrays_reported_as_text,
+        time_zone_type_names,
     } = mapping_options;
 
     // Convert ODBC nullability to Parquet repetition. If the ODBC driver can not tell wether a
@@ -115,6 +249,63 @@ pub fn strategy_from_column_description(
         }
     };
 
+    if let Some(column_type) = column_type_override(column_types, name) {
+        info!("Mapping column '{name}' to '{column_type}', as specified by the user.");
+        return strategy_from_column_type(
+            column_type,
+            cd,
+            name,
+            mapping_options,
+            cursor,
+            index,
+            apply_length_limit,
+        );
+    }
+
+    // Some drivers (e.g. PostgreSQL) report arrays as character data. Only their type name tells us
+    // they are arrays.
+    let is_character = matches!(
+        cd.data_type,
+        DataType::Char { .. }
+            | DataType::Varchar { .. }
+            | DataType::WVarchar { .. }
+            | DataType::LongVarchar { .. }
+            | DataType::WChar { .. }
+    );
+    if arrays_reported_as_text && is_character {
+        if let Some(element) = postgres_array_element(cursor, index.try_into().unwrap())? {
+            info!("Mapping array column '{name}' to a list of {element:?}.");
+            let length = if use_utf16 {
+                cd.data_type.utf16_len()
+            } else {
+                cd.data_type.utf8_len()
+            };
+            let length = apply_length_limit(length)?;
+            return Ok(list_strategy(element, use_utf16, repetition, length));
+        }
+    }
+
+    // Some drivers report timestamps with time zone as timestamps without one, or as a type
+    // unknown to ODBC. Only their type name tells us they have a time zone.
+    if let DataType::Timestamp { precision }
+    | DataType::Other {
+        decimal_digits: precision,
+        ..
+    } = cd.data_type
+    {
+        if !time_zone_type_names.is_empty()
+            && is_time_zone_type_name(
+                time_zone_type_names,
+                &col_type_name(cursor, index.try_into().unwrap())?,
+            )
+        {
+            info!("Applying instant semantics for timestamp with time zone column '{name}'.");
+            let precision = precision.try_into().unwrap();
+            let output = mapping_options.timestamp_output(precision);
+            return Ok(timestamp_tz(output, repetition)?);
+        }
+    }
+
     let strategy: Box<dyn ColumnStrategy> = match cd.data_type {
         DataType::Float { precision: 0..=24 } | DataType::Real => {
             fetch_identical::<FloatType>(is_optional)
@@ -143,11 +334,13 @@ pub fn strategy_from_column_description(
                 scale as i32,
                 precision.try_into().unwrap(),
                 avoid_decimal,
+                fixed_len_decimals,
                 driver_does_support_i64,
             )
         }
         DataType::Timestamp { precision } => {
-            timestamp_without_tz(repetition, precision.try_into().unwrap())
+            let output = mapping_options.timestamp_output(precision.try_into().unwrap());
+            timestamp_without_tz(repetition, output, source_timezone)
         }
         DataType::BigInt => fetch_identical::<Int64Type>(is_optional),
         DataType::Bit => Box::new(Boolean::new(repetition)),
@@ -188,17 +381,33 @@ pub fn strategy_from_column_description(
                 dt.utf8_len()
             };
             let length = apply_length_limit(len_in_chars)?;
-            text_strategy(use_utf16, repetition, length)
+            let trim_padding = mapping_options.trim_padding(name, dt);
+            text_strategy(
+                use_utf16,
+                repetition,
+                length,
+                trim_padding,
+                source_charset,
+                invalid_utf8,
+                driver_returns_memory_garbage_for_indicators,
+            )
         }
         DataType::Other {
             data_type: SqlDataType(-154),
             column_size: _,
             decimal_digits: precision,
         } => {
-            if db_name == "Microsoft SQL Server" {
+            if time_as_text {
                 time_from_text(repetition, precision.try_into().unwrap())
             } else {
-                unknown_non_char_type(cd, cursor, index, repetition, apply_length_limit)?
+                unknown_non_char_type(
+                    cd,
+                    cursor,
+                    index,
+                    repetition,
+                    invalid_utf8,
+                    apply_length_limit,
+                )?
             }
         }
         DataType::Other {
@@ -206,7 +415,7 @@ pub fn strategy_from_column_description(
             column_size: _,
             decimal_digits: precision,
         } => {
-            if db_name == "Microsoft SQL Server" {
+            if datetimeoffset_as_text {
                 // -155 is an indication for "Timestamp with timezone" on Microsoft SQL Server. We
                 // give it special treatment so users can sort by time instead lexographically.
                 info!(
@@ -214,13 +423,37 @@ pub fn strategy_from_column_description(
                     column {}.",
                     cd.name_to_string()?
                 );
-                timestamp_tz(precision.try_into().unwrap(), repetition)?
+                let precision = precision.try_into().unwrap();
+                let output = mapping_options.timestamp_output(precision);
+                timestamp_tz(output, repetition)?
             } else {
-                unknown_non_char_type(cd, cursor, index, repetition, apply_length_limit)?
+                unknown_non_char_type(
+                    cd,
+                    cursor,
+                    index,
+                    repetition,
+                    invalid_utf8,
+                    apply_length_limit,
+                )?
             }
         }
+        DataType::Other {
+            data_type: SqlDataType(-11),
+            column_size: _,
+            decimal_digits: _,
+        } if !uuid_as_text => {
+            // -11 is `SQL_GUID`
+            Box::new(Uuid::new(repetition))
+        }
         DataType::Unknown | DataType::Time { .. } | DataType::Other { .. } => {
-            unknown_non_char_type(cd, cursor, index, repetition, apply_length_limit)?
+            unknown_non_char_type(
+                cd,
+                cursor,
+                index,
+                repetition,
+                invalid_utf8,
+                apply_length_limit,
+            )?
         }
     };
 
@@ -233,11 +466,187 @@ pub fn strategy_from_column_description(
     Ok(strategy)
 }
 
+/// Name of the data type of the column, as reported by the data source. `odbc-api` does not offer
+/// this attribute, so we query it directly.
+pub fn col_type_name(cursor: &mut impl ResultSetMetadata, index: u16) -> Result<String, Error> {
+    let statement = cursor.as_stmt_ref();
+    let mut buffer = vec![0u8; 256];
+    let mut length: i16 = 0;
+    // Safety: The buffer outlives the call and its length is passed along with it. Type name is a
+    // character attribute, so the numeric attribute pointer may be NULL.
+    let ret = unsafe {
+        SQLColAttribute(
+            statement.as_sys(),
+            index,
+            Desc::TypeName,
+            buffer.as_mut_ptr() as Pointer,
+            buffer.len().try_into().unwrap(),
+            &mut length,
+            null_mut(),
+        )
+    };
+    if ret != SqlReturn::SUCCESS && ret != SqlReturn::SUCCESS_WITH_INFO {
+        bail!("Could not query the type name of column {index}.")
+    }
+    // Type names are short. Should one be truncated, it is not one of the array types we know.
+    buffer.truncate(min(length.try_into().unwrap_or(0), buffer.len() - 1));
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// The type specified by the user for the column with the given name. Names are matched case
+/// insensitive, if there is no exact match.
+pub fn column_type_override(
+    column_types: &[(String, ColumnType)],
+    name: &str,
+) -> Option<ColumnType> {
+    position_by_name(column_types, name, |(column_name, _)| column_name)
+        .map(|index| column_types[index].1)
+}
+
+/// Strategy for a column whose parquet type has been specified by the user. We bind a buffer
+/// matching the desired type and leave the conversion to the ODBC driver.
+fn strategy_from_column_type(
+    column_type: ColumnType,
+    cd: &ColumnDescription,
+    name: &str,
+    mapping_options: MappingOptions,
+    cursor: &mut impl ResultSetMetadata,
+    index: i16,
+    apply_length_limit: impl Fn(Option<NonZeroUsize>) -> Result<usize, Error>,
+) -> Result<Box<dyn ColumnStrategy>, Error> {
+    let repetition = match cd.nullability {
+        Nullability::Nullable | Nullability::Unknown => Repetition::OPTIONAL,
+        Nullability::NoNulls => Repetition::REQUIRED,
+    };
+    let is_optional = cd.could_be_nullable();
+
+    let is_binary = matches!(
+        cd.data_type,
+        DataType::Binary { .. } | DataType::Varbinary { .. } | DataType::LongVarbinary { .. }
+    );
+    let is_number = matches!(
+        cd.data_type,
+        DataType::Bit
+            | DataType::TinyInt
+            | DataType::SmallInt
+            | DataType::Integer
+            | DataType::BigInt
+            | DataType::Real
+            | DataType::Float { .. }
+            | DataType::Double
+            | DataType::Numeric { .. }
+            | DataType::Decimal { .. }
+    );
+    let is_temporal = matches!(
+        cd.data_type,
+        DataType::Date
+            | DataType::Time { .. }
+            | DataType::Timestamp { .. }
+            | DataType::Other {
+                data_type: SqlDataType(-154 | -155),
+                ..
+            }
+    );
+    let convertible = match column_type {
+        ColumnType::Boolean
+        | ColumnType::Int8
+        | ColumnType::Int16
+        | ColumnType::Int32
+        | ColumnType::Int64
+        | ColumnType::Float
+        | ColumnType::Double
+        | ColumnType::Decimal { .. } => !is_binary && !is_temporal,
+        ColumnType::Date | ColumnType::Timestamp { .. } => !is_binary && !is_number,
+        ColumnType::Text | ColumnType::Binary => true,
+    };
+    if !convertible {
+        bail!(
+            "Column '{name}' can not be mapped to '{column_type}', since values of its data type \
+            {:?} can not be converted into it.",
+            cd.data_type
+        )
+    }
+
+    let integer = |bit_width| LogicalType::Integer {
+        bit_width,
+        is_signed: true,
+    };
+    let strategy: Box<dyn ColumnStrategy> = match column_type {
+        ColumnType::Boolean => Box::new(Boolean::new(repetition)),
+        // Values are fetched as 32 Bit integers, so they must be checked to fit.
+        ColumnType::Int8 => Box::new(NarrowInteger::new(repetition, 8, name.to_owned())),
+        ColumnType::Int16 => Box::new(NarrowInteger::new(repetition, 16, name.to_owned())),
+        ColumnType::Int32 => {
+            fetch_identical_with_logical_type::<Int32Type>(is_optional, integer(32))
+        }
+        ColumnType::Int64 => {
+            int64_fetch_strategy(is_optional, mapping_options.driver_does_support_i64)
+        }
+        ColumnType::Float => fetch_identical::<FloatType>(is_optional),
+        ColumnType::Double => fetch_identical::<DoubleType>(is_optional),
+        ColumnType::Decimal { precision, scale } => decmial_fetch_strategy(
+            is_optional,
+            scale as i32,
+            precision,
+            false,
+            mapping_options.fixed_len_decimals,
+            mapping_options.driver_does_support_i64,
+        ),
+        ColumnType::Date => Box::new(Date::new(repetition)),
+        ColumnType::Timestamp { precision } => {
+            // Like an explicit `--timestamp-unit` takes precedence over `INT96` timestamps implied
+            // by `--compat`, the unit specified for the column takes precedence over both
+            // `--timestamp-unit` and `--int96-timestamps`.
+            let output = TimestampOutput::Int64(TimestampPrecision::new(precision));
+            timestamp_without_tz(repetition, output, mapping_options.source_timezone)
+        }
+        ColumnType::Text => {
+            let use_utf16 = mapping_options.use_utf16;
+            let length = if use_utf16 {
+                cd.data_type.utf16_len()
+            } else {
+                cd.data_type.utf8_len()
+            };
+            let length = match length {
+                Some(length) => Some(length),
+                None => cursor.col_display_size(index.try_into().unwrap())?,
+            };
+            let trim_padding = mapping_options.trim_padding(name, cd.data_type);
+            text_strategy(
+                use_utf16,
+                repetition,
+                apply_length_limit(length)?,
+                trim_padding,
+                mapping_options.source_charset,
+                mapping_options.invalid_utf8,
+                mapping_options.driver_returns_memory_garbage_for_indicators,
+            )
+        }
+        ColumnType::Binary => {
+            let length = match cd.data_type {
+                DataType::Binary { length }
+                | DataType::Varbinary { length }
+                | DataType::LongVarbinary { length } => length,
+                _ => match cd.data_type.utf8_len() {
+                    Some(length) => Some(length),
+                    None => cursor.col_display_size(index.try_into().unwrap())?,
+                },
+            };
+            Box::new(Binary::<ByteArrayType>::new(
+                repetition,
+                apply_length_limit(length)?,
+            ))
+        }
+    };
+    Ok(strategy)
+}
+
 fn unknown_non_char_type(
     cd: &ColumnDescription,
     cursor: &mut impl ResultSetMetadata,
     index: i16,
     repetition: Repetition,
+    invalid_utf8: InvalidUtf8,
     apply_length_limit: impl FnOnce(Option<NonZeroUsize>) -> Result<usize, Error>,
 ) -> Result<Box<dyn ColumnStrategy>, Error> {
     let length = if let Some(len) = cd.data_type.utf8_len() {
@@ -247,5 +656,32 @@ fn unknown_non_char_type(
     };
     let length = apply_length_limit(length)?;
     let use_utf16 = false;
-    Ok(text_strategy(use_utf16, repetition, length))
+    Ok(text_strategy(
+        use_utf16,
+        repetition,
+        length,
+        false,
+        None,
+        invalid_utf8,
+        false,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::short_type_name;
+
+    #[test]
+    fn strip_module_paths_from_type_name() {
+        assert_eq!(
+            "IdenticalOptional<Int32Type>",
+            short_type_name(
+                "odbc2parquet::query::identical::IdenticalOptional<parquet::data_type::Int32Type>"
+            )
+        );
+        assert_eq!(
+            "Boolean",
+            short_type_name("odbc2parquet::query::boolean::Boolean")
+        );
+    }
 }
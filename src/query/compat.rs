@@ -0,0 +1,101 @@
+use parquet::file::properties::WriterVersion;
+
+use crate::enum_args::{Compat, TimestampUnit};
+
+/// Settings required by a particular engine in order to read the output. They are applied in
+/// addition to the ones specified explicitly at the command line.
+pub struct CompatProfile {
+    pub prefer_varbinary: bool,
+    pub uuid_as_text: bool,
+    pub timestamp_unit: Option<TimestampUnit>,
+    pub int96_timestamps: bool,
+    /// Write all decimals as `FIXED_LEN_BYTE_ARRAY`, rather than as `INT32` or `INT64` for small
+    /// precisions.
+    pub fixed_len_decimals: bool,
+    pub sanitize_column_names: bool,
+    pub writer_version: WriterVersion,
+}
+
+impl CompatProfile {
+    pub fn new(compat: Option<Compat>) -> Self {
+        let default = CompatProfile {
+            prefer_varbinary: false,
+            uuid_as_text: false,
+            timestamp_unit: None,
+            int96_timestamps: false,
+            fixed_len_decimals: false,
+            sanitize_column_names: false,
+            writer_version: WriterVersion::PARQUET_2_0,
+        };
+        match compat {
+            // DuckDB reads everything we write by default.
+            None | Some(Compat::Duckdb) => default,
+            // Spark does not support nanoseconds, UUIDs, or special characters in column names.
+            Some(Compat::Spark) => CompatProfile {
+                prefer_varbinary: true,
+                uuid_as_text: true,
+                timestamp_unit: Some(TimestampUnit::Micros),
+                sanitize_column_names: true,
+                writer_version: WriterVersion::PARQUET_1_0,
+                ..default
+            },
+            // Hive expects legacy INT96 timestamps and decimals as fixed length byte arrays.
+            Some(Compat::Hive) => CompatProfile {
+                prefer_varbinary: true,
+                uuid_as_text: true,
+                int96_timestamps: true,
+                fixed_len_decimals: true,
+                sanitize_column_names: true,
+                writer_version: WriterVersion::PARQUET_1_0,
+                ..default
+            },
+            // BigQuery timestamps have microseconds precision and column names are restricted to
+            // letters, digits and underscores.
+            Some(Compat::Bigquery) => CompatProfile {
+                uuid_as_text: true,
+                timestamp_unit: Some(TimestampUnit::Micros),
+                sanitize_column_names: true,
+                writer_version: WriterVersion::PARQUET_1_0,
+                ..default
+            },
+            Some(Compat::Snowflake) => CompatProfile {
+                uuid_as_text: true,
+                writer_version: WriterVersion::PARQUET_1_0,
+                ..default
+            },
+        }
+    }
+}
+
+/// Replaces every character in `name` which is not an ASCII letter, digit or underscore with an
+/// underscore. Names starting with a digit are prefixed with an underscore.
+pub fn sanitize_column_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize_column_name;
+
+    #[test]
+    fn sanitize_column_names() {
+        assert_eq!("order_id", sanitize_column_name("order_id"));
+        assert_eq!("Order_Date", sanitize_column_name("Order Date"));
+        assert_eq!("sum_price_", sanitize_column_name("sum(price)"));
+        assert_eq!("_1st", sanitize_column_name("1st"));
+        assert_eq!("Gr__e", sanitize_column_name("Größe"));
+    }
+}
@@ -1,81 +1,267 @@
-use std::{fs::File, io::Write, path::PathBuf, sync::Arc};
+use std::{
+    fs::{create_dir_all, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::Error;
 use bytesize::ByteSize;
 use log::info;
 use parquet::{
-    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    file::{
+        metadata::RowGroupMetaData, properties::WriterProperties, writer::SerializedFileWriter,
+    },
     schema::types::Type,
 };
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use tempfile::TempPath;
 
-use super::table_strategy::ColumnExporter;
+use super::{
+    batch_size_limit::RowGroupSizeLimit,
+    provenance::file_written_at,
+    row_group::RowGroupWriter,
+    s3::{MultipartUpload, S3Storage},
+    table_strategy::ColumnExporter,
+    transaction::Transaction,
+};
 
-pub struct CurrentFile {
-    writer: SerializedFileWriter<Box<dyn Write + Send>>,
-    /// Path to the file currently being written to.
+/// Where output files are stored.
+#[derive(Clone)]
+pub enum Storage {
+    FileSystem,
+    /// Object storage compatible to Amazon S3. Paths are interpreted as object keys.
+    S3(Arc<S3Storage>),
+    /// Files are staged and only moved into place once the transaction is committed.
+    Transactional(Arc<Transaction>),
+}
+
+impl Storage {
+    pub fn create(&self, path: &Path) -> Result<Box<dyn OutputFile>, Error> {
+        let output: Box<dyn OutputFile> = match self {
+            Storage::FileSystem => Box::new(LocalFile::create(path, None)?),
+            Storage::S3(s3) => Box::new(s3.create(path)?),
+            Storage::Transactional(transaction) => transaction.create(path)?,
+        };
+        Ok(output)
+    }
+
+    /// Human readable location of the file at `path`.
+    pub fn location(&self, path: &Path) -> String {
+        match self {
+            Storage::FileSystem => path.to_string_lossy().into_owned(),
+            Storage::S3(s3) => s3.url(path),
+            Storage::Transactional(transaction) => transaction.location(path),
+        }
+    }
+}
+
+/// An output file, which is only persisted once it has been written completely. Dropping it
+/// without calling `persist` discards it.
+pub trait OutputFile: Write + Send {
+    /// Called once everything has been written, in case persisting the file is deferred. Allows
+    /// the output to release resources it no longer needs.
+    fn stage(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn persist(self: Box<Self>) -> Result<(), Error>;
+}
+
+pub struct LocalFile {
+    file: File,
+    /// Deletes the file, unless it is kept explicitly.
     path: TempPath,
+    /// Path the file is moved to once persisted. `None` if it is already written to its final
+    /// location.
+    target: Option<PathBuf>,
+}
+
+impl LocalFile {
+    pub fn create(path: &Path, target: Option<PathBuf>) -> Result<Self, Error> {
+        let file = File::create(path).map_err(|io_err| {
+            Error::from(io_err).context(format!(
+                "Could not create output file '{}'",
+                path.to_string_lossy()
+            ))
+        })?;
+        Ok(Self {
+            file,
+            path: TempPath::from_path(path),
+            target,
+        })
+    }
+}
+
+impl Write for LocalFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl OutputFile for LocalFile {
+    fn persist(self: Box<Self>) -> Result<(), Error> {
+        match self.target {
+            None => {
+                self.path.keep()?;
+            }
+            Some(target) => {
+                if let Some(dir) = target.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    create_dir_all(dir)?;
+                }
+                self.path.persist(&target).map_err(|err| {
+                    Error::from(err.error).context(format!(
+                        "Could not move output file into place at '{}'",
+                        target.to_string_lossy()
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputFile for MultipartUpload {
+    fn stage(&mut self) -> Result<(), Error> {
+        // Do not keep the last part in memory, while waiting for the transaction to complete.
+        self.upload_last_part()
+    }
+
+    fn persist(self: Box<Self>) -> Result<(), Error> {
+        self.complete()
+    }
+}
+
+/// Computes the size and SHA-256 checksum of everything written into an output file.
+struct Checksummed {
+    output: Box<dyn OutputFile>,
+    hasher: Sha256,
+    num_bytes: u64,
+}
+
+impl Write for Checksummed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.output.write(buf)?;
+        self.hasher.update(&buf[..num_bytes]);
+        self.num_bytes += num_bytes as u64;
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Describes a file which has been written completely.
+#[derive(Serialize)]
+pub struct WrittenFile {
+    /// Path of the file. Starts with `s3://` if uploaded into object storage.
+    pub path: String,
+    pub num_rows: u64,
+    /// Size of the file in bytes.
+    pub size_bytes: u64,
+    pub num_row_groups: u32,
+    /// Hex encoded SHA-256 checksum of the file content.
+    pub sha256: String,
+}
+
+pub struct CurrentFile {
+    writer: SerializedFileWriter<Checksummed>,
+    /// Path of the file in messages and the manifest, e.g. including the bucket in case of S3.
+    location: String,
     /// Keep track of curret file size so we can split it, should it get too large.
     file_size: ByteSize,
     /// Keep track of the total number of rows writte into the file so far.
     total_num_rows: u64,
+    num_row_groups: u32,
+    /// Decides which rows of the batches end up in the same row group.
+    row_groups: RowGroupWriter,
 }
 
 impl CurrentFile {
     pub fn new(
         path: PathBuf,
+        storage: &Storage,
         schema: Arc<Type>,
         properties: Arc<WriterProperties>,
+        row_group_size: RowGroupSizeLimit,
     ) -> Result<CurrentFile, Error> {
-        let output: Box<dyn Write + Send> = Box::new(File::create(&path).map_err(|io_err| {
-            Error::from(io_err).context(format!(
-                "Could not create output file '{}'",
-                path.to_string_lossy()
-            ))
-        })?);
-        let path = TempPath::from_path(path);
+        let output = Checksummed {
+            output: storage.create(&path)?,
+            hasher: Sha256::new(),
+            num_bytes: 0,
+        };
         let writer = SerializedFileWriter::new(output, schema.clone(), properties.clone())?;
 
         Ok(Self {
             writer,
-            path,
+            location: storage.location(&path),
             file_size: ByteSize::b(0),
             total_num_rows: 0,
+            num_row_groups: 0,
+            row_groups: RowGroupWriter::new(row_group_size),
         })
     }
 
-    pub fn write_row_group(
-        &mut self,
-        mut column_exporter: ColumnExporter,
-    ) -> Result<ByteSize, Error> {
-        let mut col_index = 0;
-        let mut row_group_writer = self.writer.next_row_group()?;
-        while let Some(mut column_writer) = row_group_writer.next_column()? {
-            column_exporter.export_nth_column(col_index, &mut column_writer)?;
-            column_writer.close()?;
-            col_index += 1;
+    /// Writes the current batch of `column_exporter` into the file and returns the file size so
+    /// far. Only row groups which are completed contribute to the file size.
+    pub fn write_batch(&mut self, column_exporter: ColumnExporter) -> Result<ByteSize, Error> {
+        for metadata in self
+            .row_groups
+            .write_batch(&mut self.writer, column_exporter)?
+        {
+            self.add_row_group(&metadata);
         }
-        let metadata = row_group_writer.close()?;
+        Ok(self.file_size)
+    }
+
+    /// Number of row groups written into the file so far.
+    pub fn num_row_groups(&self) -> u32 {
+        self.num_row_groups
+    }
+
+    fn add_row_group(&mut self, metadata: &RowGroupMetaData) {
         // Of course writing a row group increases file size. We keep track of it here, so we can
         // split on file size if we go over a threshold.
         self.file_size += ByteSize::b(metadata.compressed_size().try_into().unwrap());
         let rows_in_row_group: u64 = metadata.num_rows().try_into().unwrap();
         self.total_num_rows += rows_in_row_group;
-        Ok(self.file_size)
+        self.num_row_groups += 1;
     }
 
     /// Writes metadata at the end and persists the file. Called if we do not want to continue
     /// writing batches into this file.
-    pub fn finalize(self) -> Result<(), Error> {
-        self.writer.close()?;
-        // Do not persist empty files
-        let path = self.path.keep()?;
+    pub fn finalize(mut self) -> Result<WrittenFile, Error> {
+        if let Some(metadata) = self.row_groups.flush(&mut self.writer)? {
+            self.add_row_group(&metadata);
+        }
+        self.writer.append_key_value_metadata(file_written_at());
+        let Checksummed {
+            output,
+            hasher,
+            num_bytes,
+        } = self.writer.into_inner()?;
+        output.persist()?;
         info!(
             "{} rows have been written to {} with a file size of {}.",
-            self.total_num_rows,
-            path.to_string_lossy(),
-            self.file_size
+            self.total_num_rows, self.location, self.file_size
         );
-        Ok(())
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(WrittenFile {
+            path: self.location,
+            num_rows: self.total_num_rows,
+            size_bytes: num_bytes,
+            num_row_groups: self.num_row_groups,
+            sha256,
+        })
     }
 }
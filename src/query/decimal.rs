@@ -19,12 +19,41 @@ use super::{
     column_strategy::ColumnStrategy, identical::fetch_identical_with_logical_type, text::Utf8,
 };
 
+/// Fetch 64 Bit integers. Drivers which do not support them, are queried for text instead, which is
+/// parsed into 64 Bit integers.
+pub fn int64_fetch_strategy(
+    is_optional: bool,
+    driver_does_support_i64: bool,
+) -> Box<dyn ColumnStrategy> {
+    let logical_type = LogicalType::Integer {
+        bit_width: 64,
+        is_signed: true,
+    };
+    if driver_does_support_i64 {
+        fetch_identical_with_logical_type::<Int64Type>(is_optional, logical_type)
+    } else {
+        let repetition = if is_optional {
+            Repetition::OPTIONAL
+        } else {
+            Repetition::REQUIRED
+        };
+        // 19 digits are enough to hold any 64 Bit integer
+        Box::new(DecimalTextToInteger::<Int64Type>::new(
+            19,
+            0,
+            repetition,
+            logical_type,
+        ))
+    }
+}
+
 /// Choose how to fetch decimals from ODBC and store them in parquet
 pub fn decmial_fetch_strategy(
     is_optional: bool,
     scale: i32,
     precision: u8,
     avoid_decimal: bool,
+    fixed_len_decimals: bool,
     driver_does_support_i64: bool,
 ) -> Box<dyn ColumnStrategy> {
     let repetition = if is_optional {
@@ -39,6 +68,12 @@ pub fn decmial_fetch_strategy(
         return Box::new(Utf8::with_bytes_length(repetition, length));
     }
 
+    // Some readers only understand decimals stored as `FIXED_LEN_BYTE_ARRAY`, independent of their
+    // precision. Integers are still written as such, if decimals are avoided.
+    if fixed_len_decimals && !avoid_decimal && precision <= 38 {
+        return Box::new(DecimalAsBinary::new(repetition, scale, precision));
+    }
+
     match (precision, scale) {
         (0..=9, 0) => {
             let logical_type = if avoid_decimal {
@@ -0,0 +1,155 @@
+use anyhow::{bail, Error};
+use odbc_api::{ColumnDescription, Environment, ResultSetMetadata};
+use parquet::schema::printer::print_schema;
+use serde::Serialize;
+
+use crate::{enum_args::DescribeFormat, open_connection, DescribeOpt};
+
+use super::{
+    batch_size_limit::BatchSizeLimit, input_parameters, query_statement_text,
+    table_strategy::TableStrategy,
+};
+
+/// How a single column of the result set is mapped onto parquet.
+#[derive(Serialize)]
+struct ColumnReport {
+    /// One based index of the column in the result set.
+    index: u16,
+    name: String,
+    /// SQL data type as reported by the ODBC driver.
+    odbc_data_type: String,
+    odbc_nullability: String,
+    strategy: String,
+    /// Description of the buffer bound to fetch the column.
+    buffer: String,
+    buffer_bytes_per_row: usize,
+    parquet_type: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    columns: Vec<ColumnReport>,
+    /// Includes the memory required for the conversion to parquet.
+    memory_usage_per_row: usize,
+    batch_size_rows: usize,
+}
+
+/// Print how the columns of the result set of a query would be mapped onto parquet, without
+/// fetching any rows.
+pub fn describe(environment: &Environment, opt: DescribeOpt) -> Result<(), Error> {
+    let DescribeOpt {
+        connect_opts,
+        batch_size_opts,
+        mapping_opts,
+        format,
+        execute,
+        query,
+        parameters,
+    } = opt;
+
+    let query = query_statement_text(query)?;
+    let odbc_conn = open_connection(environment, &connect_opts)?;
+    let db_name = odbc_conn.database_management_system_name()?;
+    let quirks = mapping_opts.quirks(&db_name);
+    let mapping_options = mapping_opts.mapping_options(&quirks);
+
+    let report = if execute {
+        let params = input_parameters(&parameters);
+        let Some(mut cursor) = odbc_conn.execute(&query, params.as_slice())? else {
+            bail!("Query did not return a result set, so there is nothing to describe.")
+        };
+        let table_strategy = TableStrategy::new(&mut cursor, mapping_options)?;
+        report(&mut cursor, &table_strategy, batch_size_opts.limit())?
+    } else {
+        let mut prepared = odbc_conn.prepare(&query)?;
+        let table_strategy = TableStrategy::new(&mut prepared, mapping_options)?;
+        report(&mut prepared, &table_strategy, batch_size_opts.limit())?
+    };
+
+    match format {
+        DescribeFormat::Table => print_table(&report),
+        DescribeFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+fn report(
+    result_set: &mut impl ResultSetMetadata,
+    table_strategy: &TableStrategy,
+    batch_size: BatchSizeLimit,
+) -> Result<Report, Error> {
+    let mut columns = Vec::new();
+    for ((name, strategy), index) in table_strategy.columns().zip(1..) {
+        let mut cd = ColumnDescription::default();
+        result_set.describe_col(index, &mut cd)?;
+        let mut parquet_type = Vec::new();
+        print_schema(&mut parquet_type, &strategy.parquet_type(name));
+        let buffer_desc = strategy.buffer_desc();
+        columns.push(ColumnReport {
+            index,
+            name: name.to_owned(),
+            odbc_data_type: format!("{:?}", cd.data_type),
+            odbc_nullability: format!("{:?}", cd.nullability),
+            strategy: strategy.name(),
+            buffer: format!("{buffer_desc:?}"),
+            buffer_bytes_per_row: buffer_desc.bytes_per_row(),
+            parquet_type: String::from_utf8(parquet_type)?.trim().to_owned(),
+        })
+    }
+    let memory_usage_per_row = table_strategy.memory_usage_per_row();
+    Ok(Report {
+        columns,
+        memory_usage_per_row,
+        batch_size_rows: batch_size.batch_size_in_rows(memory_usage_per_row)?,
+    })
+}
+
+fn print_table(report: &Report) {
+    let header = [
+        "#",
+        "Name",
+        "ODBC type",
+        "Nullability",
+        "Strategy",
+        "Buffer",
+        "Bytes per row",
+        "Parquet type",
+    ]
+    .map(str::to_owned);
+    let rows: Vec<[String; 8]> = report
+        .columns
+        .iter()
+        .map(|column| {
+            [
+                column.index.to_string(),
+                column.name.clone(),
+                column.odbc_data_type.clone(),
+                column.odbc_nullability.clone(),
+                column.strategy.clone(),
+                column.buffer.clone(),
+                column.buffer_bytes_per_row.to_string(),
+                column.parquet_type.clone(),
+            ]
+        })
+        .collect();
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in [header].iter().chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+    println!();
+    println!(
+        "Memory usage per row: {} bytes (excluding memory allocated by the ODBC driver)",
+        report.memory_usage_per_row
+    );
+    println!("Batch size: {} rows", report.batch_size_rows);
+}
@@ -0,0 +1,750 @@
+//! Output split into directories by the values of one or more columns, following the layout used
+//! by Hive, e.g. `out/country=DE/year=2024/part-01.parquet`.
+
+use std::{
+    collections::BTreeMap,
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, format_err, Context, Error};
+use bytes::Bytes;
+use bytesize::ByteSize;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Timelike};
+use odbc_api::{
+    buffers::{AnyBuffer, AnySlice, BufferDesc, NullableSlice},
+    sys::NULL_DATA,
+};
+use parquet::{
+    basic::{ConvertedType, LogicalType, TimeUnit},
+    column::reader::{ColumnReader, ColumnReaderImpl},
+    data_type::DataType,
+    file::{
+        properties::WriterProperties, reader::FileReader, serialized_reader::SerializedFileReader,
+        writer::SerializedFileWriter,
+    },
+    schema::types::{ColumnDescriptor, Type},
+};
+
+use crate::parquet_buffer::{BufferedDataType, ParquetBuffer};
+
+use super::{
+    batch_size_limit::{FileSizeLimit, RowGroupSizeLimit},
+    current_file::{CurrentFile, Storage, WrittenFile},
+    parquet_writer::{ParquetOutput, ParquetWriterOptions},
+    position_by_name,
+    row_group::RowGroupWriter,
+    table_strategy::ColumnExporter,
+    text::text_value,
+};
+
+/// Directory name used by Hive for rows with a NULL (or empty) value in the partition column.
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Routes each row into a directory determined by the values of its partition columns. Every
+/// partition directory holds its own sequence of files, which is split according to the
+/// `FileSizeLimit`, just like the output of `FileWriter`. The partition columns themselves are not
+/// part of the files, since their values are encoded in the path.
+pub struct HivePartitioned {
+    base_dir: PathBuf,
+    storage: Storage,
+    /// Schema of the files, without the partition columns.
+    schema: Arc<Type>,
+    properties: Arc<WriterProperties>,
+    file_size: FileSizeLimit,
+    row_group_size: RowGroupSizeLimit,
+    /// Length of the number suffix in the file names.
+    suffix_length: usize,
+    /// Name and index of each partition column in the result set.
+    partition_columns: Vec<(String, usize)>,
+    /// Schema of the partition columns. See [`partition_values`].
+    partition_schema: Arc<Type>,
+    /// Indices of the columns of the result set, which are written into the files.
+    file_columns: Vec<usize>,
+    /// Files of every partition encountered so far. Keyed by the path relative to `base_dir`.
+    partitions: BTreeMap<PathBuf, Partition>,
+    /// Files which have already been finalized, across all partitions.
+    written_files: Vec<WrittenFile>,
+}
+
+impl HivePartitioned {
+    /// * `base_dir`: Directory containing the partition directories. Created if it does not exist.
+    /// * `schema`: Schema of the entire result set, including the partition columns.
+    /// * `options`: Names of the partition columns in `partition_by`. Their order determines the
+    ///   nesting of the directories. File size limits apply within each partition.
+    pub fn new(
+        base_dir: PathBuf,
+        storage: Storage,
+        schema: Arc<Type>,
+        options: &ParquetWriterOptions,
+        properties: Arc<WriterProperties>,
+    ) -> Result<Self, Error> {
+        let fields = schema.get_fields();
+        let partition_columns = options
+            .partition_by
+            .iter()
+            .map(|name| {
+                let index =
+                    position_by_name(fields, name, |field| field.name()).ok_or_else(|| {
+                        format_err!("Result set does not contain partition column '{name}'.")
+                    })?;
+                if !fields[index].is_primitive() {
+                    bail!("Can not partition output by column '{name}'. {UNSUPPORTED}")
+                }
+                Ok((fields[index].name().to_owned(), index))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let partition_schema = Arc::new(
+            Type::group_type_builder(schema.name())
+                .with_fields(
+                    partition_columns
+                        .iter()
+                        .map(|&(_, index)| fields[index].clone())
+                        .collect(),
+                )
+                .build()?,
+        );
+        let file_columns: Vec<usize> = (0..fields.len())
+            .filter(|index| !partition_columns.iter().any(|(_, i)| i == index))
+            .collect();
+        if file_columns.is_empty() {
+            bail!(
+                "At least one column of the result set must not be used to partition the output, \
+                otherwise the parquet files would not have any columns."
+            )
+        }
+        let schema = Arc::new(
+            Type::group_type_builder(schema.name())
+                .with_fields(
+                    file_columns
+                        .iter()
+                        .map(|&index| fields[index].clone())
+                        .collect(),
+                )
+                .build()?,
+        );
+
+        if let Storage::FileSystem = storage {
+            create_dir_all(&base_dir).with_context(|| {
+                format!(
+                    "Could not create output directory '{}'",
+                    base_dir.to_string_lossy()
+                )
+            })?;
+        }
+
+        Ok(Self {
+            base_dir,
+            storage,
+            schema,
+            properties,
+            file_size: options.file_size,
+            row_group_size: options.row_group_size,
+            suffix_length: options.suffix_length,
+            partition_columns,
+            partition_schema,
+            file_columns,
+            partitions: BTreeMap::new(),
+            written_files: Vec::new(),
+        })
+    }
+
+    /// Path of the partition directory relative to the base directory for the row with the given
+    /// index.
+    ///
+    /// * `partition_values`: Values of each partition column in the current batch.
+    fn partition_path(
+        &self,
+        partition_values: &[Vec<Option<String>>],
+        row_index: usize,
+    ) -> PathBuf {
+        let mut path = PathBuf::new();
+        for ((name, _), values) in self.partition_columns.iter().zip(partition_values) {
+            let value = match &values[row_index] {
+                Some(value) if !value.is_empty() => escape_path_name(value),
+                _ => DEFAULT_PARTITION.to_owned(),
+            };
+            path.push(format!("{}={}", escape_path_name(name), value));
+        }
+        path
+    }
+}
+
+impl ParquetOutput for HivePartitioned {
+    fn write_batch(&mut self, mut column_exporter: ColumnExporter) -> Result<(), Error> {
+        let column_indices: Vec<usize> = self
+            .partition_columns
+            .iter()
+            .map(|&(_, index)| index)
+            .collect();
+        let partition_values = partition_values(
+            &mut column_exporter,
+            &self.partition_schema,
+            &column_indices,
+        )?;
+        let mut rows_by_partition: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+        for row_index in 0..column_exporter.num_rows() {
+            let path = self.partition_path(&partition_values, row_index);
+            rows_by_partition.entry(path).or_default().push(row_index);
+        }
+
+        for (path, rows) in rows_by_partition {
+            let buffer = column_exporter.select_rows(&rows);
+            let partition = self
+                .partitions
+                .entry(path)
+                .or_insert_with_key(|path| Partition::new(self.base_dir.join(path)));
+            let (num_row_groups, file_size) = partition.write_batch(
+                column_exporter.with_buffer(&buffer, &rows, &self.file_columns),
+                &self.storage,
+                &self.schema,
+                &self.properties,
+                self.row_group_size,
+                self.suffix_length,
+            )?;
+            if self
+                .file_size
+                .should_start_new_file(num_row_groups, file_size)
+            {
+                self.written_files
+                    .extend(partition.finalize_current_file()?);
+            }
+        }
+        Ok(())
+    }
+
+    fn close(mut self) -> Result<Vec<WrittenFile>, Error> {
+        for (_path, mut partition) in self.partitions {
+            self.written_files
+                .extend(partition.finalize_current_file()?);
+        }
+        Ok(self.written_files)
+    }
+
+    fn close_box(self: Box<Self>) -> Result<Vec<WrittenFile>, Error> {
+        self.close()
+    }
+}
+
+/// Files written for a single partition.
+struct Partition {
+    dir: PathBuf,
+    num_file: u32,
+    /// `None` if no file has been created yet, or the last one has been closed due to the file
+    /// size limit.
+    current_file: Option<CurrentFile>,
+}
+
+impl Partition {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            num_file: 0,
+            current_file: None,
+        }
+    }
+
+    /// Writes the batch into the current file of the partition and returns the number of row
+    /// groups and the size of that file.
+    fn write_batch(
+        &mut self,
+        column_exporter: ColumnExporter,
+        storage: &Storage,
+        schema: &Arc<Type>,
+        properties: &Arc<WriterProperties>,
+        row_group_size: RowGroupSizeLimit,
+        suffix_length: usize,
+    ) -> Result<(u32, ByteSize), Error> {
+        if self.current_file.is_none() {
+            self.next_file(storage, schema, properties, row_group_size, suffix_length)?;
+        }
+        let current_file = self.current_file.as_mut().unwrap();
+        let file_size = current_file.write_batch(column_exporter)?;
+        Ok((current_file.num_row_groups(), file_size))
+    }
+
+    fn next_file(
+        &mut self,
+        storage: &Storage,
+        schema: &Arc<Type>,
+        properties: &Arc<WriterProperties>,
+        row_group_size: RowGroupSizeLimit,
+        suffix_length: usize,
+    ) -> Result<(), Error> {
+        // Object storage does not know directories. Prefixes of the object keys suffice.
+        if let Storage::FileSystem = storage {
+            create_dir_all(&self.dir).with_context(|| {
+                format!(
+                    "Could not create partition directory '{}'",
+                    self.dir.to_string_lossy()
+                )
+            })?;
+        }
+        self.num_file += 1;
+        let path = part_file_path(&self.dir, self.num_file, suffix_length);
+        self.current_file = Some(CurrentFile::new(
+            path,
+            storage,
+            schema.clone(),
+            properties.clone(),
+            row_group_size,
+        )?);
+        Ok(())
+    }
+
+    /// Finalizes the current file, if there is one, and returns its description.
+    fn finalize_current_file(&mut self) -> Result<Option<WrittenFile>, Error> {
+        self.current_file
+            .take()
+            .map(CurrentFile::finalize)
+            .transpose()
+    }
+}
+
+/// E.g. `dir/part-01.parquet`
+fn part_file_path(dir: &Path, num_file: u32, suffix_length: usize) -> PathBuf {
+    dir.join(format!(
+        "part-{:0width$}.parquet",
+        num_file,
+        width = suffix_length
+    ))
+}
+
+/// Values of the partition columns in the current batch of `column_exporter`, as they appear in
+/// the directory names before escaping. `None` for NULL. The values are written into an in-memory
+/// parquet file and read back, so they are converted by the column strategies exactly like the
+/// values written into the output, e.g. decoding the source character set or trimming padding.
+///
+/// * `schema`: Schema of the partition columns.
+/// * `column_indices`: Indices of the partition columns in the result set.
+fn partition_values(
+    column_exporter: &mut ColumnExporter,
+    schema: &Arc<Type>,
+    column_indices: &[usize],
+) -> Result<Vec<Vec<Option<String>>>, Error> {
+    let num_rows = column_exporter.num_rows();
+    let mut file = SerializedFileWriter::new(
+        Vec::new(),
+        schema.clone(),
+        Arc::new(WriterProperties::builder().build()),
+    )?;
+    RowGroupWriter::new(RowGroupSizeLimit::Batch)
+        .write_batch(&mut file, column_exporter.with_columns(column_indices))?;
+    let reader = SerializedFileReader::new(Bytes::from(file.into_inner()?))?;
+    let row_group = reader.get_row_group(0)?;
+    let mut buffer = ParquetBuffer::new(num_rows);
+    (0..column_indices.len())
+        .map(|index| {
+            let descr = row_group.metadata().column(index).column_descr_ptr();
+            read_partition_values(
+                &mut buffer,
+                row_group.get_column_reader(index)?,
+                &descr,
+                num_rows,
+            )
+            .with_context(|| format!("Can not partition output by column '{}'.", descr.name()))
+        })
+        .collect()
+}
+
+fn read_partition_values(
+    buffer: &mut ParquetBuffer,
+    column_reader: ColumnReader,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+) -> Result<Vec<Option<String>>, Error> {
+    let logical_type = descr.logical_type();
+    match column_reader {
+        ColumnReader::BoolColumnReader(mut reader) => {
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                Ok(value.to_string())
+            })
+        }
+        ColumnReader::Int32ColumnReader(mut reader) => read_values(
+            buffer,
+            &mut reader,
+            descr,
+            num_rows,
+            |&value| match &logical_type {
+                Some(LogicalType::Date) => format_date(value),
+                Some(LogicalType::Decimal { scale, .. }) => {
+                    Ok(format_decimal(value.into(), *scale))
+                }
+                Some(LogicalType::Integer {
+                    is_signed: false, ..
+                }) => Ok((value as u32).to_string()),
+                Some(LogicalType::Time { .. }) => bail!(UNSUPPORTED),
+                _ => Ok(value.to_string()),
+            },
+        ),
+        ColumnReader::Int64ColumnReader(mut reader) => read_values(
+            buffer,
+            &mut reader,
+            descr,
+            num_rows,
+            |&value| match &logical_type {
+                Some(LogicalType::Timestamp { unit, .. }) => {
+                    let timestamp = match unit {
+                        TimeUnit::MILLIS(_) => DateTime::from_timestamp_millis(value),
+                        TimeUnit::MICROS(_) => DateTime::from_timestamp_micros(value),
+                        TimeUnit::NANOS(_) => Some(DateTime::from_timestamp_nanos(value)),
+                    };
+                    timestamp
+                        .map(|timestamp| format_timestamp(&timestamp.naive_utc()))
+                        .ok_or_else(|| format_err!("Invalid timestamp: {value}"))
+                }
+                Some(LogicalType::Decimal { scale, .. }) => {
+                    Ok(format_decimal(value.into(), *scale))
+                }
+                Some(LogicalType::Integer {
+                    is_signed: false, ..
+                }) => Ok((value as u64).to_string()),
+                Some(LogicalType::Time { .. }) => bail!(UNSUPPORTED),
+                _ => Ok(value.to_string()),
+            },
+        ),
+        ColumnReader::Int96ColumnReader(mut reader) => {
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                Ok(format_timestamp(
+                    &DateTime::from_timestamp_nanos(value.to_nanos()).naive_utc(),
+                ))
+            })
+        }
+        ColumnReader::FloatColumnReader(mut reader) => {
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                Ok(value.to_string())
+            })
+        }
+        ColumnReader::DoubleColumnReader(mut reader) => {
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                Ok(value.to_string())
+            })
+        }
+        ColumnReader::ByteArrayColumnReader(mut reader)
+            if descr.converted_type() == ConvertedType::UTF8 =>
+        {
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                Ok(String::from_utf8_lossy(value.data()).into_owned())
+            })
+        }
+        ColumnReader::FixedLenByteArrayColumnReader(mut reader) => {
+            let Some(LogicalType::Decimal { scale, .. }) = logical_type else {
+                bail!(UNSUPPORTED)
+            };
+            read_values(buffer, &mut reader, descr, num_rows, |value| {
+                // Sign extend the big endian two's complement representation
+                let bytes = value.data();
+                let fill = if bytes.first().is_some_and(|&b| b & 0x80 != 0) {
+                    0xFF
+                } else {
+                    0
+                };
+                let mut be_bytes = [fill; 16];
+                be_bytes[16 - bytes.len()..].copy_from_slice(bytes);
+                Ok(format_decimal(i128::from_be_bytes(be_bytes), scale))
+            })
+        }
+        ColumnReader::ByteArrayColumnReader(_) => bail!(UNSUPPORTED),
+    }
+}
+
+const UNSUPPORTED: &str =
+    "Only integer, floating point, boolean, date, timestamp and text columns are supported.";
+
+/// Reads the values of a column with a single leaf and formats them.
+fn read_values<T>(
+    buffer: &mut ParquetBuffer,
+    reader: &mut ColumnReaderImpl<T>,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+    format: impl Fn(&T::T) -> Result<String, Error>,
+) -> Result<Vec<Option<String>>, Error>
+where
+    T: DataType,
+    T::T: BufferedDataType,
+{
+    if descr.max_def_level() == 0 {
+        buffer
+            .read_required(reader, num_rows)?
+            .iter()
+            .map(|value| format(value).map(Some))
+            .collect()
+    } else {
+        buffer
+            .read_optional(reader, num_rows)?
+            .map(|value| value.map(&format).transpose())
+            .collect()
+    }
+}
+
+fn format_date(days_since_epoch: i32) -> Result<String, Error> {
+    let date = NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .checked_add_signed(TimeDelta::days(days_since_epoch.into()))
+        .ok_or_else(|| format_err!("Invalid date: {days_since_epoch}"))?;
+    Ok(date.format("%Y-%m-%d").to_string())
+}
+
+fn format_timestamp(timestamp: &NaiveDateTime) -> String {
+    let mut text = timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    let nanos = timestamp.nanosecond();
+    if nanos != 0 {
+        let fraction = format!("{nanos:09}");
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    text
+}
+
+/// Formats the unscaled integer `value` of a decimal, e.g. `12345` with scale 2 as `123.45`.
+fn format_decimal(value: i128, scale: i32) -> String {
+    if scale <= 0 {
+        return value.to_string();
+    }
+    let scale = scale as usize;
+    let digits = format!("{:0width$}", value.unsigned_abs(), width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    format!("{sign}{integer}.{fraction}")
+}
+
+/// Escapes characters which are not allowed in directory names the same way Hive does, e.g. `/`
+/// becomes `%2F`.
+fn escape_path_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_control()
+            || matches!(
+                c,
+                '"' | '#' | '%' | '\'' | '*' | '/' | ':' | '=' | '?' | '\\' | '{' | '[' | ']' | '^'
+            )
+        {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Copies the rows with the specified indices from `source` into a new buffer.
+///
+/// * `desc`: Description of the buffer `source` has been fetched into.
+/// * `ignore_indicators`: Determine the length of text values by their terminating zero, rather
+///   than their indicator. See [`super::table_strategy::TableStrategy::ignore_indicators`].
+pub fn select_rows(
+    source: AnySlice,
+    desc: BufferDesc,
+    rows: &[usize],
+    ignore_indicators: bool,
+) -> AnyBuffer {
+    fn select<T: Copy>(source: &[T], target: &mut [T], rows: &[usize]) {
+        for (target, &row_index) in target.iter_mut().zip(rows) {
+            *target = source[row_index];
+        }
+    }
+
+    fn select_nullable<'a, T: Copy>(
+        source: NullableSlice<'a, T>,
+        rows: &'a [usize],
+    ) -> impl Iterator<Item = Option<T>> + 'a {
+        let (values, indicators) = source.raw_values();
+        rows.iter()
+            .map(move |&row_index| (indicators[row_index] != NULL_DATA).then(|| values[row_index]))
+    }
+
+    let num_rows = rows.len();
+    let mut target = AnyBuffer::from_desc(num_rows, desc);
+    match (source, &mut target) {
+        (AnySlice::Text(view), AnyBuffer::Text(column)) => {
+            for (index, &row_index) in rows.iter().enumerate() {
+                column.set_value(index, text_value(view, row_index, ignore_indicators))
+            }
+        }
+        (AnySlice::WText(view), AnyBuffer::WText(column)) => {
+            for (index, &row_index) in rows.iter().enumerate() {
+                column.set_value(index, text_value(view, row_index, ignore_indicators))
+            }
+        }
+        (AnySlice::Binary(view), AnyBuffer::Binary(column)) => {
+            for (index, &row_index) in rows.iter().enumerate() {
+                column.set_value(index, view.get(row_index))
+            }
+        }
+        (AnySlice::Date(source), AnyBuffer::Date(target)) => select(source, target, rows),
+        (AnySlice::Time(source), AnyBuffer::Time(target)) => select(source, target, rows),
+        (AnySlice::Timestamp(source), AnyBuffer::Timestamp(target)) => select(source, target, rows),
+        (AnySlice::F64(source), AnyBuffer::F64(target)) => select(source, target, rows),
+        (AnySlice::F32(source), AnyBuffer::F32(target)) => select(source, target, rows),
+        (AnySlice::I8(source), AnyBuffer::I8(target)) => select(source, target, rows),
+        (AnySlice::I16(source), AnyBuffer::I16(target)) => select(source, target, rows),
+        (AnySlice::I32(source), AnyBuffer::I32(target)) => select(source, target, rows),
+        (AnySlice::I64(source), AnyBuffer::I64(target)) => select(source, target, rows),
+        (AnySlice::U8(source), AnyBuffer::U8(target)) => select(source, target, rows),
+        (AnySlice::Bit(source), AnyBuffer::Bit(target)) => select(source, target, rows),
+        (AnySlice::NullableDate(source), AnyBuffer::NullableDate(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableTime(source), AnyBuffer::NullableTime(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableTimestamp(source), AnyBuffer::NullableTimestamp(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableF64(source), AnyBuffer::NullableF64(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableF32(source), AnyBuffer::NullableF32(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableI8(source), AnyBuffer::NullableI8(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableI16(source), AnyBuffer::NullableI16(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableI32(source), AnyBuffer::NullableI32(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableI64(source), AnyBuffer::NullableI64(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableU8(source), AnyBuffer::NullableU8(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        (AnySlice::NullableBit(source), AnyBuffer::NullableBit(target)) => target
+            .writer_n(num_rows)
+            .write(select_nullable(source, rows)),
+        _ => unreachable!("Buffer must be allocated using the description of the source column."),
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use encoding_rs::Encoding;
+    use odbc_api::{
+        buffers::{AnyBuffer, BufferDesc, ColumnarAnyBuffer, TextColumn},
+        RowSetBuffer,
+    };
+    use parquet::{basic::Repetition, schema::types::Type};
+
+    use crate::{
+        enum_args::InvalidUtf8,
+        parquet_buffer::{ParquetBuffer, RowIndices},
+        query::{table_strategy::ColumnExporter, text::text_strategy},
+    };
+
+    use super::{escape_path_name, format_decimal, partition_values, select_rows};
+
+    #[test]
+    fn escape_partition_values() {
+        assert_eq!("Berlin", escape_path_name("Berlin"));
+        assert_eq!("AC%2FDC", escape_path_name("AC/DC"));
+        assert_eq!("a%3Db", escape_path_name("a=b"));
+        assert_eq!("12%3A30%3A00", escape_path_name("12:30:00"));
+        assert_eq!("Düsseldorf", escape_path_name("Düsseldorf"));
+    }
+
+    #[test]
+    fn select_subset_of_rows() {
+        let desc = BufferDesc::I32 { nullable: true };
+        let mut source = AnyBuffer::from_desc(4, desc);
+        let AnyBuffer::NullableI32(column) = &mut source else {
+            panic!("Buffer must be nullable i32")
+        };
+        column
+            .writer_n(4)
+            .write([Some(1), None, Some(3), Some(4)].into_iter());
+        let mut source = ColumnarAnyBuffer::new(vec![(1, source)]);
+        *source.mut_num_fetch_rows() = 4;
+
+        let selected = select_rows(source.column(0), desc, &[1, 3], false);
+
+        let AnyBuffer::NullableI32(column) = selected else {
+            panic!("Buffer must be nullable i32")
+        };
+        let values: Vec<_> = column.iter(2).map(|value| value.copied()).collect();
+        assert_eq!(vec![None, Some(4)], values);
+    }
+
+    #[test]
+    fn select_text_rows_ignoring_indicators() {
+        let max_str_len = 5;
+        let mut column = TextColumn::new(3, max_str_len);
+        // Mock a driver reporting the maximum length as indicator for every value, followed by
+        // garbage behind the terminating zero.
+        for (index, value) in ["ab\0xy", "hello", "de\0zz"].iter().enumerate() {
+            column
+                .set_mut(index, max_str_len)
+                .copy_from_slice(value.as_bytes());
+        }
+        let mut source = ColumnarAnyBuffer::new(vec![(1, AnyBuffer::Text(column))]);
+        *source.mut_num_fetch_rows() = 3;
+        let desc = BufferDesc::Text { max_str_len };
+
+        let selected = select_rows(source.column(0), desc, &[0, 2], true);
+
+        let mut selected = ColumnarAnyBuffer::new(vec![(1, selected)]);
+        *selected.mut_num_fetch_rows() = 2;
+        let values: Vec<_> = selected.column(0).as_text_view().unwrap().iter().collect();
+        assert_eq!(vec![Some(&b"ab"[..]), Some(&b"de"[..])], values);
+    }
+
+    #[test]
+    fn convert_partition_values_like_output() {
+        let max_str_len = 12;
+        // Padded and encoded in Windows-1252
+        let mut column = TextColumn::new(3, max_str_len);
+        column.set_value(0, Some(b"D\xfcsseldorf "));
+        column.set_value(1, None);
+        column.set_value(2, Some(b"Berlin      "));
+        let mut buffer = ColumnarAnyBuffer::new(vec![(1, AnyBuffer::Text(column))]);
+        *buffer.mut_num_fetch_rows() = 3;
+        let strategy = text_strategy(
+            false,
+            Repetition::OPTIONAL,
+            max_str_len,
+            true,
+            Encoding::for_label(b"windows-1252"),
+            InvalidUtf8::Error,
+            false,
+        );
+        let schema = Arc::new(
+            Type::group_type_builder("schema")
+                .with_fields(vec![Arc::new(strategy.parquet_type("city"))])
+                .build()
+                .unwrap(),
+        );
+        let columns = [("city".to_owned(), strategy)];
+        let mut parquet_buffer = ParquetBuffer::new(3);
+        let mut column_exporter = ColumnExporter::new(
+            &buffer,
+            &mut parquet_buffer,
+            &columns,
+            false,
+            RowIndices::Consecutive(0),
+        );
+
+        let values = partition_values(&mut column_exporter, &schema, &[0]).unwrap();
+
+        assert_eq!(
+            vec![vec![
+                Some("Düsseldorf".to_owned()),
+                None,
+                Some("Berlin".to_owned())
+            ]],
+            values
+        );
+    }
+
+    #[test]
+    fn format_decimals() {
+        assert_eq!("123.45", format_decimal(12345, 2));
+        assert_eq!("-0.05", format_decimal(-5, 2));
+        assert_eq!("42", format_decimal(42, 0));
+    }
+}
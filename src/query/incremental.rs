@@ -0,0 +1,465 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use anyhow::{anyhow, bail, Context, Error};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use log::info;
+use odbc_api::{
+    buffers::{AnySlice, BufferDesc},
+    parameter::{InputParameter, WithDataType},
+    sys::{Date, Timestamp},
+    DataType, IntoParameter, ResultSetMetadata,
+};
+use parquet::{
+    basic::{ConvertedType, Type as PhysicalType},
+    column::writer::ColumnWriter,
+    schema::types::Type,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::parquet_buffer::ParquetBuffer;
+
+use super::{
+    column_strategy::ColumnStrategy, subquery_text, table_strategy::TableStrategy,
+    write_json_atomically,
+};
+
+/// Largest value of the incremental column, which has been written to the output.
+#[derive(Clone, PartialEq, PartialOrd, Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
+pub enum Watermark {
+    Integer(i64),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    Text(String),
+}
+
+impl Watermark {
+    /// Bind the watermark as a parameter, so we only fetch rows with larger values.
+    ///
+    /// * `precision`: Number of fractional second digits of the incremental column, if it is a
+    ///   timestamp.
+    fn to_parameter(&self, precision: Option<i16>) -> Box<dyn InputParameter> {
+        match self {
+            Watermark::Integer(value) => Box::new(*value),
+            Watermark::Date(date) => Box::new(odbc_date(date)),
+            // Bind with the precision of the column. Data sources may reject larger ones, e.g.
+            // Microsoft SQL Server supports at most 7 digits.
+            Watermark::Timestamp(timestamp) => Box::new(WithDataType {
+                value: odbc_timestamp(timestamp),
+                data_type: DataType::Timestamp {
+                    precision: precision.unwrap_or_else(|| fraction_digits(timestamp.nanosecond())),
+                },
+            }),
+            Watermark::Text(text) => Box::new(text.clone().into_parameter()),
+        }
+    }
+}
+
+/// Content of the state file.
+#[derive(Serialize, Deserialize)]
+struct State {
+    /// Name of the incremental column. Stored so we can detect then a state file is accidentally
+    /// used with another column.
+    column: String,
+    watermark: Watermark,
+    /// Number of fractional second digits of the incremental column, if it is a timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    precision: Option<i16>,
+}
+
+/// Only fetches rows past the high-water mark persisted in a state file.
+pub struct Incremental {
+    column: String,
+    state_file: PathBuf,
+    /// High-water mark of the last successful run. `None` if there has not been one, or it did
+    /// not write any rows.
+    watermark: Option<Watermark>,
+    /// Precision of the incremental column in the last successful run.
+    precision: Option<i16>,
+}
+
+impl Incremental {
+    /// Reads the watermark from the state file. A missing state file is treated as if there had
+    /// not been a previous run.
+    pub fn load(column: String, state_file: PathBuf) -> Result<Self, Error> {
+        let (watermark, precision) = match File::open(&state_file) {
+            Ok(file) => {
+                let state: State =
+                    serde_json::from_reader(BufReader::new(file)).with_context(|| {
+                        format!("Invalid state file '{}'.", state_file.to_string_lossy())
+                    })?;
+                if state.column != column {
+                    bail!(
+                        "State file '{}' holds the watermark for column '{}', but the incremental \
+                        column is '{}'.",
+                        state_file.to_string_lossy(),
+                        state.column,
+                        column
+                    )
+                }
+                info!(
+                    "Fetching rows with '{column}' larger than {:?}.",
+                    state.watermark
+                );
+                (Some(state.watermark), state.precision)
+            }
+            Err(io_err) if io_err.kind() == ErrorKind::NotFound => {
+                info!("No state file found. Fetching all rows.");
+                (None, None)
+            }
+            Err(io_err) => {
+                return Err(Error::from(io_err).context(format!(
+                    "Could not open state file '{}'",
+                    state_file.to_string_lossy()
+                )))
+            }
+        };
+        Ok(Self {
+            column,
+            state_file,
+            watermark,
+            precision,
+        })
+    }
+
+    /// Restricts the query to rows past the watermark. The watermark is bound as an additional
+    /// parameter after the ones supplied by the user.
+    pub fn query(&self, query: &str) -> String {
+        if self.watermark.is_some() {
+            format!(
+                "SELECT * FROM ({}) incremental_source WHERE {} > ?",
+                subquery_text(query),
+                self.column
+            )
+        } else {
+            query.to_owned()
+        }
+    }
+
+    /// Parameter to bind for the placeholder in [`Self::query`].
+    pub fn parameter(&self) -> Option<Box<dyn InputParameter>> {
+        self.watermark
+            .as_ref()
+            .map(|watermark| watermark.to_parameter(self.precision))
+    }
+
+    /// Observes the values written for the incremental column, in order to determine the new
+    /// watermark.
+    pub fn track(
+        &self,
+        cursor: &mut impl ResultSetMetadata,
+        table_strategy: &mut TableStrategy,
+    ) -> Result<WatermarkTracker, Error> {
+        let current = Rc::new(RefCell::new(None));
+        let shared = current.clone();
+        let column_number = (table_strategy.column_index(&self.column)? + 1)
+            .try_into()
+            .unwrap();
+        let precision = match cursor.col_data_type(column_number)? {
+            DataType::Timestamp { precision } => Some(precision),
+            _ => None,
+        };
+        table_strategy.map_column_strategy(&self.column, |strategy| {
+            if !supports_watermark(
+                &strategy.buffer_desc(),
+                &strategy.parquet_type(&self.column),
+            ) {
+                bail!(
+                    "Column '{}' can not be used as incremental column. Only integer, date, \
+                    timestamp and text columns are supported. Numbers fetched as text, e.g. \
+                    decimals, are not supported, since their text does not sort like their value.",
+                    self.column
+                )
+            }
+            Ok(Box::new(TrackWatermark {
+                inner: strategy,
+                watermark: shared,
+            }))
+        })?;
+        Ok(WatermarkTracker {
+            column: self.column.clone(),
+            state_file: self.state_file.clone(),
+            current,
+            precision,
+        })
+    }
+}
+
+/// Holds the largest value written to the incremental column so far.
+pub struct WatermarkTracker {
+    column: String,
+    state_file: PathBuf,
+    current: Rc<RefCell<Option<Watermark>>>,
+    precision: Option<i16>,
+}
+
+impl WatermarkTracker {
+    /// Persist the new watermark. Must only be called once the output has been written
+    /// successfully, otherwise rows could be lost in the next run.
+    pub fn commit(self) -> Result<(), Error> {
+        let Some(watermark) = self.current.take() else {
+            info!("No rows have been fetched. State file remains unchanged.");
+            return Ok(());
+        };
+        info!(
+            "Updating watermark of '{}' to {:?}.",
+            self.column, watermark
+        );
+        let state = State {
+            column: self.column,
+            watermark,
+            precision: self.precision,
+        };
+        write_json_atomically(&self.state_file, &state).with_context(|| {
+            format!(
+                "Could not write state file '{}'",
+                self.state_file.to_string_lossy()
+            )
+        })
+    }
+}
+
+/// `true` if the largest value of a column can be determined from its ODBC buffer. Text buffers are
+/// only supported if they are also written as text. Other values fetched as text, e.g. decimals,
+/// would be compared by their text, so `"9"` would be larger than `"10"`.
+fn supports_watermark(buffer_desc: &BufferDesc, parquet_type: &Type) -> bool {
+    match buffer_desc {
+        BufferDesc::I8 { .. }
+        | BufferDesc::I16 { .. }
+        | BufferDesc::I32 { .. }
+        | BufferDesc::I64 { .. }
+        | BufferDesc::U8 { .. }
+        | BufferDesc::Date { .. }
+        | BufferDesc::Timestamp { .. } => true,
+        BufferDesc::Text { .. } | BufferDesc::WText { .. } => {
+            parquet_type.is_primitive()
+                && parquet_type.get_physical_type() == PhysicalType::BYTE_ARRAY
+                && parquet_type.get_basic_info().converted_type() == ConvertedType::UTF8
+        }
+        _ => false,
+    }
+}
+
+/// Decorates the strategy of the incremental column, keeping track of the largest value written.
+struct TrackWatermark {
+    inner: Box<dyn ColumnStrategy>,
+    watermark: Rc<RefCell<Option<Watermark>>>,
+}
+
+impl ColumnStrategy for TrackWatermark {
+    fn parquet_type(&self, name: &str) -> Type {
+        self.inner.parquet_type(name)
+    }
+
+    fn buffer_desc(&self) -> BufferDesc {
+        self.inner.buffer_desc()
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn num_leaves(&self) -> usize {
+        self.inner.num_leaves()
+    }
+
+    fn copy_odbc_to_parquet_leaf(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        leaf: usize,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        if leaf == 0 {
+            self.copy_odbc_to_parquet(parquet_buffer, column_writer, column_view)
+        } else {
+            self.inner
+                .copy_odbc_to_parquet_leaf(parquet_buffer, leaf, column_writer, column_view)
+        }
+    }
+
+    fn copy_odbc_to_parquet(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        self.inner
+            .copy_odbc_to_parquet(parquet_buffer, column_writer, column_view)?;
+        let batch_max = match column_view {
+            // Take text from the values written, so it is decoded and trimmed like the output.
+            AnySlice::Text(_) | AnySlice::WText(_) => parquet_buffer
+                .written_byte_arrays()
+                .iter()
+                .max_by(|a, b| a.data().cmp(b.data()))
+                .map(|text| Watermark::Text(String::from_utf8_lossy(text.data()).into_owned())),
+            _ => max_in_batch(column_view)?,
+        };
+        if let Some(batch_max) = batch_max {
+            let mut watermark = self.watermark.borrow_mut();
+            if watermark
+                .as_ref()
+                .is_none_or(|current| batch_max > *current)
+            {
+                *watermark = Some(batch_max);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Largest value within a batch of the incremental column. `None` if the batch only contains
+/// NULL values.
+fn max_in_batch(column_view: AnySlice) -> Result<Option<Watermark>, Error> {
+    let integer = |value: Option<i64>| value.map(Watermark::Integer);
+    let max = match column_view {
+        AnySlice::I8(values) => integer(values.iter().map(|&v| v as i64).max()),
+        AnySlice::I16(values) => integer(values.iter().map(|&v| v as i64).max()),
+        AnySlice::I32(values) => integer(values.iter().map(|&v| v as i64).max()),
+        AnySlice::I64(values) => integer(values.iter().copied().max()),
+        AnySlice::U8(values) => integer(values.iter().map(|&v| v as i64).max()),
+        AnySlice::NullableI8(values) => integer(values.flatten().map(|&v| v as i64).max()),
+        AnySlice::NullableI16(values) => integer(values.flatten().map(|&v| v as i64).max()),
+        AnySlice::NullableI32(values) => integer(values.flatten().map(|&v| v as i64).max()),
+        AnySlice::NullableI64(values) => integer(values.flatten().copied().max()),
+        AnySlice::NullableU8(values) => integer(values.flatten().map(|&v| v as i64).max()),
+        AnySlice::Date(values) => max_date(values.iter())?,
+        AnySlice::NullableDate(values) => max_date(values.flatten())?,
+        AnySlice::Timestamp(values) => max_timestamp(values.iter())?,
+        AnySlice::NullableTimestamp(values) => max_timestamp(values.flatten())?,
+        _ => bail!("Unsupported buffer type for tracking the watermark of incremental column."),
+    };
+    Ok(max)
+}
+
+fn max_date<'a>(dates: impl Iterator<Item = &'a Date>) -> Result<Option<Watermark>, Error> {
+    let mut max = None;
+    for date in dates {
+        let date = NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)
+            .ok_or_else(|| anyhow!("Invalid date in incremental column: {date:?}"))?;
+        max = max.max(Some(date));
+    }
+    Ok(max.map(Watermark::Date))
+}
+
+fn max_timestamp<'a>(
+    timestamps: impl Iterator<Item = &'a Timestamp>,
+) -> Result<Option<Watermark>, Error> {
+    let mut max = None;
+    for ts in timestamps {
+        let timestamp = NaiveDate::from_ymd_opt(ts.year as i32, ts.month as u32, ts.day as u32)
+            .and_then(|date| {
+                date.and_hms_nano_opt(
+                    ts.hour as u32,
+                    ts.minute as u32,
+                    ts.second as u32,
+                    ts.fraction,
+                )
+            })
+            .ok_or_else(|| anyhow!("Invalid timestamp in incremental column: {ts:?}"))?;
+        max = max.max(Some(timestamp));
+    }
+    Ok(max.map(Watermark::Timestamp))
+}
+
+/// Number of fractional second digits required to represent `nanos` without loss. Used to bind
+/// timestamps from state files which do not specify the precision of the column.
+fn fraction_digits(mut nanos: u32) -> i16 {
+    let mut digits = 9;
+    while digits > 0 && nanos % 10 == 0 {
+        nanos /= 10;
+        digits -= 1;
+    }
+    digits
+}
+
+fn odbc_date(date: &NaiveDate) -> Date {
+    Date {
+        year: date.year() as i16,
+        month: date.month() as u16,
+        day: date.day() as u16,
+    }
+}
+
+fn odbc_timestamp(timestamp: &NaiveDateTime) -> Timestamp {
+    Timestamp {
+        year: timestamp.year() as i16,
+        month: timestamp.month() as u16,
+        day: timestamp.day() as u16,
+        hour: timestamp.hour() as u16,
+        minute: timestamp.minute() as u16,
+        second: timestamp.second() as u16,
+        fraction: timestamp.nanosecond(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use odbc_api::buffers::BufferDesc;
+    use parquet::{
+        basic::{ConvertedType, LogicalType, Repetition, Type as PhysicalType},
+        schema::types::Type,
+    };
+
+    use super::{fraction_digits, supports_watermark, Watermark};
+
+    #[test]
+    fn serialize_watermark() {
+        let watermark = Watermark::Timestamp(
+            NaiveDate::from_ymd_opt(2024, 3, 1)
+                .unwrap()
+                .and_hms_milli_opt(12, 30, 0, 123)
+                .unwrap(),
+        );
+
+        let json = serde_json::to_string(&watermark).unwrap();
+
+        assert_eq!(
+            r#"{"type":"timestamp","value":"2024-03-01T12:30:00.123"}"#,
+            json
+        );
+        assert_eq!(watermark, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn fraction_digits_of_timestamp_without_precision() {
+        assert_eq!(0, fraction_digits(0));
+        assert_eq!(3, fraction_digits(123_000_000));
+        assert_eq!(7, fraction_digits(123_456_700));
+        assert_eq!(9, fraction_digits(1));
+    }
+
+    #[test]
+    fn reject_numbers_fetched_as_text_as_watermark() {
+        let text = BufferDesc::Text { max_str_len: 10 };
+        let string = Type::primitive_type_builder("a", PhysicalType::BYTE_ARRAY)
+            .with_converted_type(ConvertedType::UTF8)
+            .with_repetition(Repetition::OPTIONAL)
+            .build()
+            .unwrap();
+        let decimal = Type::primitive_type_builder("a", PhysicalType::INT64)
+            .with_logical_type(Some(LogicalType::Decimal {
+                scale: 0,
+                precision: 10,
+            }))
+            .with_precision(10)
+            .with_scale(0)
+            .with_repetition(Repetition::OPTIONAL)
+            .build()
+            .unwrap();
+
+        assert!(supports_watermark(&text, &string));
+        assert!(!supports_watermark(&text, &decimal));
+        assert!(supports_watermark(
+            &BufferDesc::I64 { nullable: true },
+            &decimal
+        ));
+    }
+}
@@ -0,0 +1,609 @@
+//! JSON text columns written as nested parquet groups, according to a schema supplied by the user.
+
+use std::{borrow::Cow, cell::RefCell, fs::File, io::BufReader, path::Path, str, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Error};
+use odbc_api::buffers::{AnySlice, BufferDesc};
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    column::writer::{get_typed_column_writer_mut, ColumnWriter},
+    data_type::{
+        BoolType, ByteArray, ByteArrayType, DataType, DoubleType, FloatType, Int32Type, Int64Type,
+    },
+    schema::types::Type,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    enum_args::JsonInvalid,
+    parquet_buffer::{BufferedDataType, ParquetBuffer},
+};
+
+use super::column_strategy::ColumnStrategy;
+
+/// Type of a JSON value, as described in the schema file passed to `--json-column`. E.g.
+/// `{"struct": [{"name": "id", "type": "int64"}, {"name": "tags", "type": {"list": "string"}}]}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonType {
+    Boolean,
+    Int32,
+    Int64,
+    Float,
+    Double,
+    String,
+    Struct(Vec<JsonField>),
+    List(Box<JsonType>),
+    /// JSON object with arbitrary keys, whose values all share the same type.
+    Map(Box<JsonType>),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub json_type: JsonType,
+}
+
+impl JsonType {
+    /// Reads the schema of a JSON column from a file. The outermost type must be a struct, list or
+    /// map.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)
+            .with_context(|| format!("Could not open JSON schema '{}'", path.display()))?;
+        let json_type: JsonType = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Invalid JSON schema '{}'", path.display()))?;
+        if !matches!(
+            json_type,
+            JsonType::Struct(_) | JsonType::List(_) | JsonType::Map(_)
+        ) {
+            bail!(
+                "JSON schema '{}' must describe a struct, list or map.",
+                path.display()
+            )
+        }
+        Ok(json_type)
+    }
+
+    /// Parquet type of a value of this type. Every value is optional, since any of them may be
+    /// `null` or missing in JSON.
+    fn parquet_type(&self, name: &str) -> Type {
+        let primitive = |physical_type, logical_type| {
+            Type::primitive_type_builder(name, physical_type)
+                .with_logical_type(logical_type)
+                .with_repetition(Repetition::OPTIONAL)
+                .build()
+                .unwrap()
+        };
+        let group = |logical_type, fields: Vec<Type>| {
+            Type::group_type_builder(name)
+                .with_logical_type(logical_type)
+                .with_repetition(Repetition::OPTIONAL)
+                .with_fields(fields.into_iter().map(Arc::new).collect())
+                .build()
+                .unwrap()
+        };
+        let repeated = |name, fields: Vec<Type>| {
+            Type::group_type_builder(name)
+                .with_repetition(Repetition::REPEATED)
+                .with_fields(fields.into_iter().map(Arc::new).collect())
+                .build()
+                .unwrap()
+        };
+        match self {
+            JsonType::Boolean => primitive(PhysicalType::BOOLEAN, None),
+            JsonType::Int32 => primitive(
+                PhysicalType::INT32,
+                Some(LogicalType::Integer {
+                    bit_width: 32,
+                    is_signed: true,
+                }),
+            ),
+            JsonType::Int64 => primitive(PhysicalType::INT64, None),
+            JsonType::Float => primitive(PhysicalType::FLOAT, None),
+            JsonType::Double => primitive(PhysicalType::DOUBLE, None),
+            JsonType::String => primitive(PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            JsonType::Struct(fields) => group(
+                None,
+                fields
+                    .iter()
+                    .map(|field| field.json_type.parquet_type(&field.name))
+                    .collect(),
+            ),
+            JsonType::List(element) => group(
+                Some(LogicalType::List),
+                vec![repeated("list", vec![element.parquet_type("element")])],
+            ),
+            JsonType::Map(value) => {
+                let key = Type::primitive_type_builder("key", PhysicalType::BYTE_ARRAY)
+                    .with_logical_type(Some(LogicalType::String))
+                    .with_repetition(Repetition::REQUIRED)
+                    .build()
+                    .unwrap();
+                group(
+                    Some(LogicalType::Map),
+                    vec![repeated(
+                        "key_value",
+                        vec![key, value.parquet_type("value")],
+                    )],
+                )
+            }
+        }
+    }
+
+    /// Appends the path to every primitive value within this type to `leaves`, in the order of the
+    /// primitive columns in the parquet schema.
+    fn collect_leaves(&self, path: &mut Vec<Step>, leaves: &mut Vec<Leaf>) {
+        match self {
+            JsonType::Struct(fields) => {
+                for (index, field) in fields.iter().enumerate() {
+                    path.push(Step::Field(index));
+                    field.json_type.collect_leaves(path, leaves);
+                    path.pop();
+                }
+            }
+            JsonType::List(element) => {
+                path.push(Step::Element);
+                element.collect_leaves(path, leaves);
+                path.pop();
+            }
+            JsonType::Map(value) => {
+                path.push(Step::Key);
+                leaves.push(Leaf {
+                    path: path.clone(),
+                    json_type: JsonType::String,
+                });
+                *path.last_mut().unwrap() = Step::Value;
+                value.collect_leaves(path, leaves);
+                path.pop();
+            }
+            primitive => leaves.push(Leaf {
+                path: path.clone(),
+                json_type: primitive.clone(),
+            }),
+        }
+    }
+}
+
+/// Descends from a nested type into one of its children.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Field(usize),
+    Element,
+    Key,
+    Value,
+}
+
+/// A primitive value within a JSON type, which is written into its own parquet column.
+struct Leaf {
+    path: Vec<Step>,
+    json_type: JsonType,
+}
+
+/// Definition level, repetition level and value of an entry of a primitive parquet column.
+type Entry<'v> = (i16, i16, Option<Cow<'v, Value>>);
+
+/// Parses JSON text and writes it as a nested parquet group. Fields not part of the schema are
+/// dropped.
+pub struct Json {
+    json_type: JsonType,
+    leaves: Vec<Leaf>,
+    /// Buffer description of the text strategy the column would have been fetched with otherwise.
+    buffer_desc: BufferDesc,
+    invalid: JsonInvalid,
+    /// JSON values of the current batch. Parsed then writing the first leaf, so the text is only
+    /// parsed once for all of them.
+    parsed: RefCell<Vec<Option<Value>>>,
+}
+
+impl Json {
+    /// * `text_strategy`: Strategy fetching the column as text, i.e. binding a `Text` or `WText`
+    ///   buffer.
+    pub fn new(
+        text_strategy: &dyn ColumnStrategy,
+        json_type: JsonType,
+        invalid: JsonInvalid,
+    ) -> Result<Self, Error> {
+        let buffer_desc = text_strategy.buffer_desc();
+        if !matches!(
+            buffer_desc,
+            BufferDesc::Text { .. } | BufferDesc::WText { .. }
+        ) {
+            bail!("Only text columns can be parsed as JSON.")
+        }
+        let mut leaves = Vec::new();
+        json_type.collect_leaves(&mut Vec::new(), &mut leaves);
+        Ok(Self {
+            json_type,
+            leaves,
+            buffer_desc,
+            invalid,
+            parsed: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn parse(&self, column_view: AnySlice) -> Result<(), Error> {
+        let mut parsed = self.parsed.borrow_mut();
+        parsed.clear();
+        let parse = |text: Result<Cow<str>, Error>| -> Result<Value, Error> {
+            let text = text?;
+            serde_json::from_str(&text).with_context(|| format!("Invalid JSON: '{text}'"))
+        };
+        let values: Vec<Option<Result<Value, Error>>> = match column_view {
+            AnySlice::Text(view) => view
+                .iter()
+                .map(|text| {
+                    text.map(|bytes| {
+                        parse(
+                            str::from_utf8(bytes)
+                                .map(Cow::Borrowed)
+                                .map_err(Error::from),
+                        )
+                    })
+                })
+                .collect(),
+            AnySlice::WText(view) => view
+                .iter()
+                .map(|text| {
+                    text.map(|utf16| parse(utf16.to_string().map(Cow::Owned).map_err(Error::from)))
+                })
+                .collect(),
+            _ => panic!("JSON columns must be bound to character buffers."),
+        };
+        for value in values {
+            let value = match (value.transpose(), self.invalid) {
+                (Ok(value), _) => value,
+                (Err(_), JsonInvalid::Null) => None,
+                (Err(error), JsonInvalid::Error) => return Err(error),
+            };
+            parsed.push(value);
+        }
+        Ok(())
+    }
+
+    /// Definition and repetition levels of `value` for the primitive column reached by following
+    /// `path` from `json_type`.
+    ///
+    /// * `def`: Definition level of the parent of `value`.
+    /// * `rep`: Repetition level of the first entry written for `value`.
+    /// * `depth`: Number of repeated ancestors of `value`.
+    #[allow(clippy::too_many_arguments)]
+    fn shred<'v>(
+        &self,
+        value: Option<&'v Value>,
+        json_type: &JsonType,
+        path: &[Step],
+        def: i16,
+        rep: i16,
+        depth: i16,
+        entries: &mut Vec<Entry<'v>>,
+    ) -> Result<(), Error> {
+        let Some(value) = value.filter(|value| !value.is_null()) else {
+            entries.push((def, rep, None));
+            return Ok(());
+        };
+        let mut mismatch = |expected: &str| match self.invalid {
+            JsonInvalid::Error => Err(anyhow!("Expected {expected}, found: {value}")),
+            JsonInvalid::Null => {
+                entries.push((def, rep, None));
+                Ok(())
+            }
+        };
+        match (json_type, path.split_first()) {
+            (JsonType::Struct(fields), Some((Step::Field(index), path))) => {
+                let Some(object) = value.as_object() else {
+                    return mismatch("object");
+                };
+                let field = &fields[*index];
+                self.shred(
+                    object.get(&field.name),
+                    &field.json_type,
+                    path,
+                    def + 1,
+                    rep,
+                    depth,
+                    entries,
+                )
+            }
+            (JsonType::List(element), Some((Step::Element, path))) => {
+                let Some(array) = value.as_array() else {
+                    return mismatch("array");
+                };
+                if array.is_empty() {
+                    entries.push((def + 1, rep, None));
+                }
+                for (index, item) in array.iter().enumerate() {
+                    let rep = if index == 0 { rep } else { depth + 1 };
+                    self.shred(Some(item), element, path, def + 2, rep, depth + 1, entries)?;
+                }
+                Ok(())
+            }
+            (JsonType::Map(value_type), Some((step, path))) => {
+                let Some(object) = value.as_object() else {
+                    return mismatch("object");
+                };
+                if object.is_empty() {
+                    entries.push((def + 1, rep, None));
+                }
+                for (index, (key, item)) in object.iter().enumerate() {
+                    let rep = if index == 0 { rep } else { depth + 1 };
+                    if let Step::Key = step {
+                        // Keys are required, so their definition level is the one of the entry.
+                        let key = Value::String(key.clone());
+                        entries.push((def + 2, rep, Some(Cow::Owned(key))));
+                    } else {
+                        self.shred(
+                            Some(item),
+                            value_type,
+                            path,
+                            def + 2,
+                            rep,
+                            depth + 1,
+                            entries,
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            (_primitive, _) => {
+                entries.push((def + 1, rep, Some(Cow::Borrowed(value))));
+                Ok(())
+            }
+        }
+    }
+
+    fn write_leaf<Pdt>(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        entries: Vec<Entry>,
+        convert: fn(&Value) -> Option<Pdt::T>,
+    ) -> Result<(), Error>
+    where
+        Pdt: DataType,
+        Pdt::T: BufferedDataType + Default,
+    {
+        let cw = get_typed_column_writer_mut::<Pdt>(column_writer);
+        parquet_buffer.write_nested(
+            cw,
+            entries.into_iter().map(|(def, rep, value)| {
+                let Some(value) = value else {
+                    return Ok((def, rep, None));
+                };
+                match (convert(&value), self.invalid) {
+                    (Some(physical), _) => Ok((def, rep, Some(physical))),
+                    // All primitive values are optional, so one definition level less indicates
+                    // NULL.
+                    (None, JsonInvalid::Null) => Ok((def - 1, rep, None)),
+                    (None, JsonInvalid::Error) => Err(anyhow!("Unexpected JSON value: {value}")),
+                }
+            }),
+        )
+    }
+}
+
+impl ColumnStrategy for Json {
+    fn parquet_type(&self, name: &str) -> Type {
+        self.json_type.parquet_type(name)
+    }
+
+    fn buffer_desc(&self) -> BufferDesc {
+        self.buffer_desc
+    }
+
+    fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    fn copy_odbc_to_parquet(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        self.copy_odbc_to_parquet_leaf(parquet_buffer, 0, column_writer, column_view)
+    }
+
+    fn copy_odbc_to_parquet_leaf(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        leaf: usize,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        if leaf == 0 {
+            self.parse(column_view)?;
+        }
+        let parsed = self.parsed.borrow();
+        let Leaf { path, json_type } = &self.leaves[leaf];
+        let mut entries = Vec::new();
+        for value in parsed.iter() {
+            self.shred(value.as_ref(), &self.json_type, path, 0, 0, 0, &mut entries)?;
+        }
+        match json_type {
+            JsonType::Boolean => {
+                self.write_leaf::<BoolType>(parquet_buffer, column_writer, entries, Value::as_bool)
+            }
+            JsonType::Int32 => {
+                self.write_leaf::<Int32Type>(parquet_buffer, column_writer, entries, |value| {
+                    value.as_i64().and_then(|int| int.try_into().ok())
+                })
+            }
+            JsonType::Int64 => {
+                self.write_leaf::<Int64Type>(parquet_buffer, column_writer, entries, Value::as_i64)
+            }
+            JsonType::Float => {
+                self.write_leaf::<FloatType>(parquet_buffer, column_writer, entries, |value| {
+                    value.as_f64().map(|float| float as f32)
+                })
+            }
+            JsonType::Double => {
+                self.write_leaf::<DoubleType>(parquet_buffer, column_writer, entries, Value::as_f64)
+            }
+            JsonType::String => {
+                self.write_leaf::<ByteArrayType>(parquet_buffer, column_writer, entries, |value| {
+                    value
+                        .as_str()
+                        .map(|text| ByteArray::from(text.as_bytes().to_vec()))
+                })
+            }
+            JsonType::Struct(_) | JsonType::List(_) | JsonType::Map(_) => {
+                unreachable!("Leaves are always primitive")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use odbc_api::{
+        buffers::{AnyBuffer, ColumnarAnyBuffer, TextColumn},
+        RowSetBuffer,
+    };
+    use parquet::{
+        basic::Repetition,
+        data_type::Int32Type,
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader, writer::SerializedFileWriter,
+        },
+        schema::types::Type,
+    };
+
+    use crate::{
+        enum_args::{InvalidUtf8, JsonInvalid},
+        parquet_buffer::{ParquetBuffer, RowIndices},
+        query::{
+            batch_size_limit::RowGroupSizeLimit, column_strategy::ColumnStrategy,
+            identical::fetch_identical, row_group::RowGroupWriter, table_strategy::ColumnExporter,
+            text::text_strategy,
+        },
+    };
+
+    use super::{Json, JsonType};
+
+    /// Writes the documents into a JSON column, followed by an integer column, and returns the
+    /// rows read back from the file.
+    fn write_documents(
+        schema: &str,
+        invalid: JsonInvalid,
+        documents: &[Option<&str>],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let json_type: JsonType = serde_json::from_str(schema).unwrap();
+        let json = Json::new(
+            text_strategy(
+                false,
+                Repetition::OPTIONAL,
+                100,
+                false,
+                None,
+                InvalidUtf8::Replace,
+                false,
+            )
+            .as_ref(),
+            json_type,
+            invalid,
+        )
+        .unwrap();
+        let columns: Vec<(String, Box<dyn ColumnStrategy>)> = vec![
+            ("doc".to_owned(), Box::new(json)),
+            ("n".to_owned(), fetch_identical::<Int32Type>(false)),
+        ];
+        let schema = Type::group_type_builder("schema")
+            .with_fields(
+                columns
+                    .iter()
+                    .map(|(name, strategy)| Arc::new(strategy.parquet_type(name)))
+                    .collect(),
+            )
+            .build()
+            .unwrap();
+        let mut file = SerializedFileWriter::new(
+            Vec::new(),
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+
+        let mut text = TextColumn::new(documents.len(), 100);
+        for (index, document) in documents.iter().enumerate() {
+            text.set_value(index, document.map(str::as_bytes));
+        }
+        let numbers = (0..documents.len() as i32).collect();
+        let mut buffer = ColumnarAnyBuffer::new(vec![
+            (1, AnyBuffer::Text(text)),
+            (2, AnyBuffer::I32(numbers)),
+        ]);
+        *buffer.mut_num_fetch_rows() = documents.len();
+        let mut conversion_buffer = ParquetBuffer::new(documents.len());
+        let column_exporter = ColumnExporter::new(
+            &buffer,
+            &mut conversion_buffer,
+            &columns,
+            false,
+            RowIndices::Consecutive(0),
+        );
+        RowGroupWriter::new(RowGroupSizeLimit::Batch).write_batch(&mut file, column_exporter)?;
+        let bytes = Bytes::from(file.into_inner().unwrap());
+
+        let reader = SerializedFileReader::new(bytes).unwrap();
+        let rows = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        Ok(rows)
+    }
+
+    #[test]
+    fn write_nested_documents() {
+        let schema = r#"{"struct": [
+            {"name": "id", "type": "int64"},
+            {"name": "tags", "type": {"list": "string"}},
+            {"name": "scores", "type": {"map": "double"}},
+            {"name": "owner", "type": {"struct": [{"name": "name", "type": "string"}]}}
+        ]}"#;
+        let documents = [
+            Some(r#"{"id": 1, "tags": ["a", null], "scores": {"x": 0.5}, "unknown": 3}"#),
+            None,
+            Some(r#"{"tags": [], "scores": {}, "owner": {"name": "b"}}"#),
+        ];
+
+        let rows = write_documents(schema, JsonInvalid::Error, &documents).unwrap();
+
+        assert_eq!(
+            vec![
+                r#"{doc: {id: 1, tags: ["a", null], scores: {"x" -> 0.5}, owner: null}, n: 0}"#,
+                "{doc: null, n: 1}",
+                r#"{doc: {id: null, tags: [], scores: {}, owner: {name: "b"}}, n: 2}"#,
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn invalid_documents() {
+        let schema = r#"{"struct": [
+            {"name": "id", "type": "int32"},
+            {"name": "tags", "type": {"list": "string"}}
+        ]}"#;
+        let documents = [Some("{"), Some(r#"{"id": "one", "tags": ["a"]}"#)];
+
+        let rows = write_documents(schema, JsonInvalid::Null, &documents).unwrap();
+
+        assert_eq!(
+            vec![
+                "{doc: null, n: 0}",
+                r#"{doc: {id: null, tags: ["a"]}, n: 1}"#
+            ],
+            rows
+        );
+        assert!(write_documents(schema, JsonInvalid::Error, &documents[..1]).is_err());
+        assert!(write_documents(schema, JsonInvalid::Error, &documents[1..]).is_err());
+    }
+}
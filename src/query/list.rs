@@ -0,0 +1,403 @@
+//! PostgreSQL array columns, e.g. `int4[]` or `text[]`, written as parquet `LIST` columns.
+
+use std::{str, sync::Arc};
+
+use anyhow::{anyhow, bail, Error};
+use odbc_api::{
+    buffers::{AnySlice, BufferDesc},
+    ResultSetMetadata,
+};
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    column::writer::{get_typed_column_writer_mut, ColumnWriter},
+    data_type::{
+        BoolType, ByteArray, ByteArrayType, DataType, DoubleType, FloatType, Int32Type, Int64Type,
+    },
+    schema::types::Type,
+};
+
+use crate::parquet_buffer::{BufferedDataType, ParquetBuffer};
+
+use super::column_strategy::{col_type_name, ColumnStrategy};
+
+/// Element types of PostgreSQL arrays, which are mapped onto parquet lists. Arrays of other types
+/// are written as text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayElement {
+    Boolean,
+    Int16,
+    Int32,
+    Int64,
+    Float,
+    Double,
+    Text,
+}
+
+impl ArrayElement {
+    /// PostgreSQL names array types after their element type, prefixed with an underscore. E.g.
+    /// `_int4` for `int4[]`.
+    fn from_type_name(type_name: &str) -> Option<Self> {
+        let element = match type_name.strip_prefix('_')? {
+            "bool" => ArrayElement::Boolean,
+            "int2" => ArrayElement::Int16,
+            "int4" => ArrayElement::Int32,
+            "int8" => ArrayElement::Int64,
+            "float4" => ArrayElement::Float,
+            "float8" => ArrayElement::Double,
+            "text" | "varchar" | "bpchar" | "name" => ArrayElement::Text,
+            _ => return None,
+        };
+        Some(element)
+    }
+}
+
+/// Element type of the column, if it is a PostgreSQL array we can map onto a parquet list.
+///
+/// * `index`: One based column index.
+pub fn postgres_array_element(
+    cursor: &mut impl ResultSetMetadata,
+    index: u16,
+) -> Result<Option<ArrayElement>, Error> {
+    Ok(ArrayElement::from_type_name(&col_type_name(cursor, index)?))
+}
+
+/// * `use_utf16`: Fetch the array literals using wide character buffers.
+/// * `length`: Maximum length of the array literals, in characters of the respective encoding.
+pub fn list_strategy(
+    element: ArrayElement,
+    use_utf16: bool,
+    repetition: Repetition,
+    length: usize,
+) -> Box<dyn ColumnStrategy> {
+    let integer = |bit_width| LogicalType::Integer {
+        bit_width,
+        is_signed: true,
+    };
+    match element {
+        ArrayElement::Boolean => Box::new(List::<BoolType>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::BOOLEAN, None),
+            |text| match text {
+                "t" | "true" => Ok(true),
+                "f" | "false" => Ok(false),
+                _ => Err(anyhow!("Invalid boolean array element: '{text}'")),
+            },
+        )),
+        ArrayElement::Int16 => Box::new(List::<Int32Type>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::INT32, Some(integer(16))),
+            |text| Ok(text.parse::<i16>()?.into()),
+        )),
+        ArrayElement::Int32 => Box::new(List::<Int32Type>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::INT32, Some(integer(32))),
+            |text| Ok(text.parse()?),
+        )),
+        ArrayElement::Int64 => Box::new(List::<Int64Type>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::INT64, None),
+            |text| Ok(text.parse()?),
+        )),
+        ArrayElement::Float => Box::new(List::<FloatType>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::FLOAT, None),
+            |text| Ok(text.parse()?),
+        )),
+        ArrayElement::Double => Box::new(List::<DoubleType>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::DOUBLE, None),
+            |text| Ok(text.parse()?),
+        )),
+        ArrayElement::Text => Box::new(List::<ByteArrayType>::new(
+            use_utf16,
+            repetition,
+            length,
+            element_type(PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            |text| Ok(ByteArray::from(text.as_bytes().to_vec())),
+        )),
+    }
+}
+
+fn element_type(physical_type: PhysicalType, logical_type: Option<LogicalType>) -> Type {
+    Type::primitive_type_builder("element", physical_type)
+        .with_logical_type(logical_type)
+        .with_repetition(Repetition::OPTIONAL)
+        .build()
+        .unwrap()
+}
+
+/// Fetches the text representation of an array and writes its elements into a parquet list.
+struct List<Pdt: DataType> {
+    repetition: Repetition,
+    use_utf16: bool,
+    /// Maximum length of the array literal.
+    length: usize,
+    element: Type,
+    parse_element: fn(&str) -> Result<Pdt::T, Error>,
+}
+
+impl<Pdt: DataType> List<Pdt> {
+    fn new(
+        use_utf16: bool,
+        repetition: Repetition,
+        length: usize,
+        element: Type,
+        parse_element: fn(&str) -> Result<Pdt::T, Error>,
+    ) -> Self {
+        Self {
+            repetition,
+            use_utf16,
+            length,
+            element,
+            parse_element,
+        }
+    }
+
+    fn parse_list(&self, literal: &str) -> Result<Vec<Option<Pdt::T>>, Error> {
+        parse_array_literal(literal)?
+            .into_iter()
+            .map(|element| {
+                element
+                    .map(|text| {
+                        (self.parse_element)(&text).map_err(|error| {
+                            error.context(format!("Invalid element in array '{literal}'."))
+                        })
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+}
+
+impl<Pdt> ColumnStrategy for List<Pdt>
+where
+    Pdt: DataType,
+    Pdt::T: BufferedDataType + Default,
+{
+    fn parquet_type(&self, name: &str) -> Type {
+        let list = Type::group_type_builder("list")
+            .with_repetition(Repetition::REPEATED)
+            .with_fields(vec![Arc::new(self.element.clone())])
+            .build()
+            .unwrap();
+        Type::group_type_builder(name)
+            .with_repetition(self.repetition)
+            .with_logical_type(Some(LogicalType::List))
+            .with_fields(vec![Arc::new(list)])
+            .build()
+            .unwrap()
+    }
+
+    fn buffer_desc(&self) -> BufferDesc {
+        if self.use_utf16 {
+            BufferDesc::WText {
+                max_str_len: self.length,
+            }
+        } else {
+            BufferDesc::Text {
+                max_str_len: self.length,
+            }
+        }
+    }
+
+    fn copy_odbc_to_parquet(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        let cw = get_typed_column_writer_mut::<Pdt>(column_writer);
+        let list_is_optional = self.repetition == Repetition::OPTIONAL;
+        match column_view {
+            AnySlice::Text(view) => parquet_buffer.write_list(
+                cw,
+                list_is_optional,
+                view.iter().map(|literal| {
+                    literal
+                        .map(|literal| self.parse_list(str::from_utf8(literal)?))
+                        .transpose()
+                }),
+            ),
+            AnySlice::WText(view) => parquet_buffer.write_list(
+                cw,
+                list_is_optional,
+                view.iter().map(|literal| {
+                    literal
+                        .map(|literal| self.parse_list(&literal.to_string()?))
+                        .transpose()
+                }),
+            ),
+            _ => panic!("Array literals must be bound to character buffers."),
+        }
+    }
+}
+
+/// Splits the text representation of a one dimensional PostgreSQL array, e.g.
+/// `{1,NULL,"a \"b\""}`, into its elements. `None` for NULL elements.
+fn parse_array_literal(literal: &str) -> Result<Vec<Option<String>>, Error> {
+    // Arrays with a lower bound other than one are prefixed with their dimensions, e.g.
+    // `[0:1]={1,2}`.
+    let elements = match literal.split_once('=') {
+        Some((dimensions, elements)) if dimensions.starts_with('[') => elements,
+        _ => literal,
+    };
+    let Some(elements) = elements
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+    else {
+        bail!("Invalid array literal: '{literal}'")
+    };
+    if elements.is_empty() {
+        return Ok(Vec::new());
+    }
+    if elements.starts_with('{') {
+        bail!("Multidimensional arrays are not supported: '{literal}'")
+    }
+
+    let mut result = Vec::new();
+    let mut chars = elements.chars();
+    loop {
+        let mut element = String::new();
+        let mut next = chars.next();
+        let is_quoted = next == Some('"');
+        if is_quoted {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => element.extend(chars.next()),
+                    Some(c) => element.push(c),
+                    None => bail!("Unterminated quote in array literal: '{literal}'"),
+                }
+            }
+            next = chars.next();
+        } else {
+            while let Some(c) = next.filter(|&c| c != ',') {
+                element.push(c);
+                next = chars.next();
+            }
+        }
+        // Only unquoted NULLs indicate a missing element. `"NULL"` is a string.
+        result.push((is_quoted || !element.eq_ignore_ascii_case("NULL")).then_some(element));
+        match next {
+            Some(',') => (),
+            None => break,
+            Some(c) => bail!("Unexpected character '{c}' in array literal: '{literal}'"),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use odbc_api::{
+        buffers::{AnyBuffer, ColumnarAnyBuffer, TextColumn},
+        RowSetBuffer,
+    };
+    use parquet::{
+        basic::Repetition,
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader, writer::SerializedFileWriter,
+        },
+        schema::types::Type,
+    };
+
+    use crate::parquet_buffer::ParquetBuffer;
+
+    use super::{list_strategy, parse_array_literal, ArrayElement};
+
+    fn parse(literal: &str) -> Vec<Option<String>> {
+        parse_array_literal(literal).unwrap()
+    }
+
+    #[test]
+    fn parse_array_literals() {
+        let some = |text: &str| Some(text.to_owned());
+
+        assert_eq!(Vec::<Option<String>>::new(), parse("{}"));
+        assert_eq!(vec![some("1"), some("2"), None], parse("{1,2,NULL}"));
+        assert_eq!(
+            vec![some("a b"), some("NULL"), some(r#"x"y\z"#), some("c")],
+            parse(r#"{"a b","NULL","x\"y\\z",c}"#)
+        );
+        assert_eq!(vec![some("1"), some("2")], parse("[0:1]={1,2}"));
+        assert!(parse_array_literal("{{1,2},{3,4}}").is_err());
+        assert!(parse_array_literal("1,2").is_err());
+    }
+
+    #[test]
+    fn element_from_type_name() {
+        assert_eq!(
+            Some(ArrayElement::Int32),
+            ArrayElement::from_type_name("_int4")
+        );
+        assert_eq!(
+            Some(ArrayElement::Text),
+            ArrayElement::from_type_name("_text")
+        );
+        assert_eq!(None, ArrayElement::from_type_name("_numeric"));
+        assert_eq!(None, ArrayElement::from_type_name("int4"));
+    }
+
+    #[test]
+    fn write_integer_arrays() {
+        let literals = [Some("{1,NULL,3}"), None, Some("{}"), Some("{4}")];
+        let strategy = list_strategy(ArrayElement::Int32, false, Repetition::OPTIONAL, 20);
+        let mut column = TextColumn::new(literals.len(), 20);
+        for (index, literal) in literals.iter().enumerate() {
+            column.set_value(index, literal.map(str::as_bytes));
+        }
+        let mut buffer = ColumnarAnyBuffer::new(vec![(1, AnyBuffer::Text(column))]);
+        *buffer.mut_num_fetch_rows() = literals.len();
+
+        let schema = Type::group_type_builder("schema")
+            .with_fields(vec![Arc::new(strategy.parquet_type("a"))])
+            .build()
+            .unwrap();
+        let mut file = SerializedFileWriter::new(
+            Vec::new(),
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+        let mut row_group = file.next_row_group().unwrap();
+        let mut column_writer = row_group.next_column().unwrap().unwrap();
+        let mut parquet_buffer = ParquetBuffer::new(literals.len());
+        strategy
+            .copy_odbc_to_parquet(
+                &mut parquet_buffer,
+                column_writer.untyped(),
+                buffer.column(0),
+            )
+            .unwrap();
+        column_writer.close().unwrap();
+        row_group.close().unwrap();
+        let bytes = Bytes::from(file.into_inner().unwrap());
+
+        let reader = SerializedFileReader::new(bytes).unwrap();
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert_eq!(
+            vec!["{a: [1, null, 3]}", "{a: null}", "{a: []}", "{a: [4]}"],
+            rows
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use anyhow::{Context, Error};
+use parquet::schema::{printer::print_schema, types::TypePtr};
+use serde::Serialize;
+
+use super::{current_file::WrittenFile, write_json_atomically};
+
+/// Result of writing an entire result set to parquet.
+pub struct WriteSummary {
+    /// Schema of the result set.
+    pub schema: TypePtr,
+    /// Every file which has been written, in the order they have been created.
+    pub files: Vec<WrittenFile>,
+}
+
+/// Machine readable description of the output, written after all files are complete. Its existence
+/// indicates to downstream consumers that the run has been successful.
+#[derive(Serialize)]
+pub struct Manifest<'a> {
+    /// Query text as passed by the user.
+    query: &'a str,
+    /// Parquet schema of the result set in its textual representation. `None` if the query did not
+    /// return a result set.
+    schema: Option<String>,
+    total_num_rows: u64,
+    files: &'a [WrittenFile],
+}
+
+impl<'a> Manifest<'a> {
+    pub fn new(query: &'a str, summary: Option<&'a WriteSummary>) -> Self {
+        let schema = summary.map(|summary| {
+            let mut text = Vec::new();
+            print_schema(&mut text, &summary.schema);
+            String::from_utf8(text).expect("Parquet schema must be printed as UTF-8")
+        });
+        let files = summary.map_or(&[][..], |summary| summary.files.as_slice());
+        Self {
+            query,
+            schema,
+            total_num_rows: files.iter().map(|file| file.num_rows).sum(),
+            files,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        write_json_atomically(path, self)
+            .with_context(|| format!("Could not write manifest '{}'", path.to_string_lossy()))
+    }
+}
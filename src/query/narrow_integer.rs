@@ -0,0 +1,95 @@
+use anyhow::{bail, Error};
+use odbc_api::buffers::{AnySlice, BufferDesc, Item};
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    column::writer::{get_typed_column_writer_mut, ColumnWriter},
+    data_type::Int32Type,
+    schema::types::Type,
+};
+
+use crate::parquet_buffer::ParquetBuffer;
+
+use super::column_strategy::ColumnStrategy;
+
+/// Integers annotated with a logical type narrower than their physical `INT32`, e.g. because the
+/// user mapped a column to `int8` or `int16`. Since the driver fetches them as 32 Bit integers,
+/// every value is checked to fit into the narrower type. Otherwise readers would silently truncate
+/// it.
+pub struct NarrowInteger {
+    repetition: Repetition,
+    bit_width: u8,
+    /// Name of the column in the result set. Used to report values which do not fit.
+    column_name: String,
+}
+
+impl NarrowInteger {
+    pub fn new(repetition: Repetition, bit_width: u8, column_name: String) -> Self {
+        Self {
+            repetition,
+            bit_width,
+            column_name,
+        }
+    }
+}
+
+impl ColumnStrategy for NarrowInteger {
+    fn parquet_type(&self, name: &str) -> Type {
+        Type::primitive_type_builder(name, PhysicalType::INT32)
+            .with_logical_type(Some(LogicalType::Integer {
+                bit_width: self.bit_width as i8,
+                is_signed: true,
+            }))
+            .with_repetition(self.repetition)
+            .build()
+            .unwrap()
+    }
+
+    fn buffer_desc(&self) -> BufferDesc {
+        BufferDesc::I32 { nullable: true }
+    }
+
+    fn copy_odbc_to_parquet(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        let it = i32::as_nullable_slice(column_view).unwrap();
+        let column_writer = get_typed_column_writer_mut::<Int32Type>(column_writer);
+        parquet_buffer.write_optional_falliable(
+            column_writer,
+            it.map(|value| match value {
+                Some(&value) if !fits_into(value, self.bit_width) => bail!(
+                    "Value {value} of column '{}' does not fit into a {} Bit integer.",
+                    self.column_name,
+                    self.bit_width
+                ),
+                value => Ok(value.copied()),
+            }),
+        )?;
+        Ok(())
+    }
+}
+
+/// `true` if `value` can be represented by a signed integer with `bit_width` bits.
+fn fits_into(value: i32, bit_width: u8) -> bool {
+    let max = (1i32 << (bit_width - 1)) - 1;
+    (-max - 1..=max).contains(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fits_into;
+
+    #[test]
+    fn check_range_of_narrow_integers() {
+        assert!(fits_into(127, 8));
+        assert!(fits_into(-128, 8));
+        assert!(!fits_into(128, 8));
+        assert!(!fits_into(-129, 8));
+        assert!(fits_into(32767, 16));
+        assert!(fits_into(-32768, 16));
+        assert!(!fits_into(32768, 16));
+        assert!(!fits_into(-32769, 16));
+    }
+}
@@ -4,25 +4,42 @@ use std::{
     sync::Arc,
 };
 
-use anyhow::{format_err, Error};
+use anyhow::{bail, format_err, Error};
 use io_arg::IoArg;
 use parquet::{
     basic::{Compression, Encoding},
     file::{
+        metadata::KeyValue,
         properties::{WriterProperties, WriterVersion},
         writer::SerializedFileWriter,
     },
     schema::types::{ColumnPath, Type},
 };
 
+use crate::{enum_args::BloomFilter, S3Opts};
+
 use super::{
-    batch_size_limit::FileSizeLimit, current_file::CurrentFile, table_strategy::ColumnExporter,
+    batch_size_limit::{FileSizeLimit, RowGroupSizeLimit},
+    current_file::{CurrentFile, Storage, WrittenFile},
+    hive_partitioned::HivePartitioned,
+    provenance::file_written_at,
+    row_group::RowGroupWriter,
+    s3::{s3_location, S3Storage},
+    table_strategy::ColumnExporter,
+    transaction::Transaction,
 };
 
 /// Options influencing the output parquet file independent of schema or row content.
+#[derive(Clone)]
 pub struct ParquetWriterOptions {
     /// Directly correlated to the `--column-compression-default` command line option
     pub column_compression_default: Compression,
+    /// Tuples of column name and compression, overriding the default for the associated columns.
+    pub column_compressions: Vec<(String, Compression)>,
+    /// Columns for which bloom filters are written.
+    pub bloom_filters: Vec<BloomFilter>,
+    /// Written into the footer of every file, in addition to the time the file is completed.
+    pub key_value_metadata: Vec<KeyValue>,
     /// Tuples of column name and encoding which control the encoding for the associated columns.
     pub column_encodings: Vec<(String, Encoding)>,
     /// Number of digits in the suffix, appended to the end of a file in case they are numbered.
@@ -30,8 +47,19 @@ pub struct ParquetWriterOptions {
     /// A fuzzy limit for file size, causing the rest of the query to be written into new files if a
     /// threshold is passed.
     pub file_size: FileSizeLimit,
+    /// Limits the number of rows or bytes in a row group, independent of the batch size.
+    pub row_group_size: RowGroupSizeLimit,
     /// Do not create a file if no row was in the result set.
     pub no_empty_file: bool,
+    /// Names of the columns used to split the output into Hive style partition directories. Empty
+    /// if the output is not partitioned.
+    pub partition_by: Vec<String>,
+    /// Endpoint and credentials, in case the output is written to S3.
+    pub s3: S3Opts,
+    /// If set, files are staged and only persisted once the transaction is committed.
+    pub transaction: Option<Arc<Transaction>>,
+    /// Version 2 enables newer encodings and data pages, which are not understood by every reader.
+    pub writer_version: WriterVersion,
 }
 
 pub fn parquet_output(
@@ -43,8 +71,39 @@ pub fn parquet_output(
     // Seems to also work fine without setting the batch size explicitly, but what the heck. Just to
     // be on the safe side.
     let mut wpb = WriterProperties::builder()
-        .set_writer_version(WriterVersion::PARQUET_2_0)
-        .set_compression(options.column_compression_default);
+        .set_writer_version(options.writer_version)
+        .set_compression(options.column_compression_default)
+        .set_key_value_metadata(Some(options.key_value_metadata.clone()));
+    for (column_name, compression) in options.column_compressions.clone() {
+        let col = ColumnPath::new(vec![column_name]);
+        wpb = wpb.set_column_compression(col, compression)
+    }
+    for bloom_filter in &options.bloom_filters {
+        let is_file_column = schema
+            .get_fields()
+            .iter()
+            .any(|field| field.name() == bloom_filter.column)
+            // Partition columns are matched case insensitive, so they are excluded the same way.
+            && !options
+                .partition_by
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&bloom_filter.column));
+        if !is_file_column {
+            bail!(
+                "Can not write bloom filter for column '{}', since the output does not contain a \
+                column with this name.",
+                bloom_filter.column
+            )
+        }
+        let col = ColumnPath::new(vec![bloom_filter.column.clone()]);
+        wpb = wpb.set_column_bloom_filter_enabled(col.clone(), true);
+        if let Some(fpp) = bloom_filter.fpp {
+            wpb = wpb.set_column_bloom_filter_fpp(col.clone(), fpp);
+        }
+        if let Some(ndv) = bloom_filter.ndv {
+            wpb = wpb.set_column_bloom_filter_ndv(col, ndv);
+        }
+    }
     for (column_name, encoding) in options.column_encodings.clone() {
         let col = ColumnPath::new(vec![column_name]);
         wpb = wpb.set_column_encoding(col, encoding)
@@ -52,42 +111,68 @@ pub fn parquet_output(
     let properties = Arc::new(wpb.build());
 
     let writer: Box<dyn ParquetOutput> = match output {
-        IoArg::StdStream => Box::new(StandardOut::new(schema, properties)?),
-        IoArg::File(path) => Box::new(FileWriter::new(path, schema, options, properties)?),
+        IoArg::StdStream => Box::new(StandardOut::new(
+            schema,
+            properties,
+            options.row_group_size,
+        )?),
+        IoArg::File(path) => {
+            let (storage, path) = output_storage(path, &options.s3)?;
+            // Stage the files instead, if the output is part of a transaction.
+            let storage = match &options.transaction {
+                Some(transaction) => Storage::Transactional(transaction.clone()),
+                None => storage,
+            };
+            if options.partition_by.is_empty() {
+                Box::new(FileWriter::new(path, storage, schema, options, properties)?)
+            } else {
+                Box::new(HivePartitioned::new(
+                    path, storage, schema, &options, properties,
+                )?)
+            }
+        }
     };
 
     Ok(writer)
 }
 
+/// Storage the output path refers to, together with the path within that storage. E.g. an output
+/// like `s3://bucket/prefix/out.par` is split into S3 storage and the object key `prefix/out.par`.
+pub fn output_storage(path: PathBuf, s3_opts: &S3Opts) -> Result<(Storage, PathBuf), Error> {
+    let storage_and_path = match s3_location(&path) {
+        Some(location) => {
+            let (bucket, key) = location?;
+            let s3 = S3Storage::new(bucket, s3_opts)?;
+            (Storage::S3(Arc::new(s3)), key)
+        }
+        None => (Storage::FileSystem, path),
+    };
+    Ok(storage_and_path)
+}
+
 /// Writes row groups to the output, which could be either standard out, a single parquet file or
 /// multiple parquet files with incrementing number suffixes.
 pub trait ParquetOutput {
-    /// Retrieve the next row group writer. May trigger creation of a new file if limit of the
-    /// previous one is reached.
-    ///
-    /// # Parametersc
-    ///
-    /// * `num_batch`: Zero based num batch index
-    fn write_row_group(
-        &mut self,
-        num_batch: u32,
-        export_nth_column: ColumnExporter,
-    ) -> Result<(), Error>;
+    /// Write the next batch fetched from the data source. May trigger creation of a new file if
+    /// limit of the previous one is reached.
+    fn write_batch(&mut self, column_exporter: ColumnExporter) -> Result<(), Error>;
 
     /// Indicate that no further output is written. this triggers writing the parquet meta data and
-    /// potentially persists a temporary file.
-    fn close(self) -> Result<(), Error>;
+    /// potentially persists a temporary file. Returns a description of every file written.
+    fn close(self) -> Result<Vec<WrittenFile>, Error>;
 
-    fn close_box(self: Box<Self>) -> Result<(), Error>;
+    fn close_box(self: Box<Self>) -> Result<Vec<WrittenFile>, Error>;
 }
 
 /// Wraps parquet SerializedFileWriter. Handles splitting into new files after maximum amount of
 /// batches is reached.
 struct FileWriter {
     base_path: PathBuf,
+    storage: Storage,
     schema: Arc<Type>,
     properties: Arc<WriterProperties>,
     file_size: FileSizeLimit,
+    row_group_size: RowGroupSizeLimit,
     num_file: u32,
     /// Length of the suffix, appended to the end of a file in case they are numbered.
     suffix_length: usize,
@@ -95,23 +180,29 @@ struct FileWriter {
     /// closed, due to the size threshold, but a new row group has not yet been received from the
     /// database.
     current_file: Option<CurrentFile>,
+    /// Files which have already been finalized.
+    written_files: Vec<WrittenFile>,
 }
 
 impl FileWriter {
     pub fn new(
         path: PathBuf,
+        storage: Storage,
         schema: Arc<Type>,
         options: ParquetWriterOptions,
         properties: Arc<WriterProperties>,
     ) -> Result<Self, Error> {
         let mut file_writer = Self {
             base_path: path,
+            storage,
             schema,
             properties,
             file_size: options.file_size,
+            row_group_size: options.row_group_size,
             num_file: 0,
             suffix_length: options.suffix_length,
             current_file: None,
+            written_files: Vec::new(),
         };
 
         if !options.no_empty_file {
@@ -129,8 +220,10 @@ impl FileWriter {
         let path = Self::current_path(&self.base_path, suffix)?;
         self.current_file = Some(CurrentFile::new(
             path,
+            &self.storage,
             self.schema.clone(),
             self.properties.clone(),
+            self.row_group_size,
         )?);
         self.num_file += 1;
         Ok(())
@@ -147,46 +240,39 @@ impl FileWriter {
 }
 
 impl ParquetOutput for FileWriter {
-    fn write_row_group(
-        &mut self,
-        num_batch: u32,
-        column_exporter: ColumnExporter,
-    ) -> Result<(), Error> {
-        // There is no file. Let us create one so we can write the row group.
+    fn write_batch(&mut self, column_exporter: ColumnExporter) -> Result<(), Error> {
+        // There is no file. Let us create one so we can write the batch.
         if self.current_file.is_none() {
             self.next_file()?
         }
 
-        // Write next row group
-        let file_size = self
-            .current_file
-            .as_mut()
-            .unwrap()
-            .write_row_group(column_exporter)?;
+        let current_file = self.current_file.as_mut().unwrap();
+        let file_size = current_file.write_batch(column_exporter)?;
 
         if self
             .file_size
-            .should_start_new_file(num_batch + 1, file_size)
+            .should_start_new_file(current_file.num_row_groups(), file_size)
         {
-            self.current_file.take().unwrap().finalize()?;
+            let written_file = self.current_file.take().unwrap().finalize()?;
+            self.written_files.push(written_file);
         }
 
         Ok(())
     }
 
-    fn close(self) -> Result<(), Error> {
+    fn close(mut self) -> Result<Vec<WrittenFile>, Error> {
         // An active file might, or might not exsist at this point, dependening on wether or not the
         // file splitting to due size thresholds coincides with the data source being consumed and
         // all data being read from it. If our data source ran out of data, just after we closed the
         // current file due to its size threshold it is `None`. In this case there is nothing to do
         // though.
         if let Some(open_file) = self.current_file {
-            open_file.finalize()?;
+            self.written_files.push(open_file.finalize()?);
         }
-        Ok(())
+        Ok(self.written_files)
     }
 
-    fn close_box(self: Box<Self>) -> Result<(), Error> {
+    fn close_box(self: Box<Self>) -> Result<Vec<WrittenFile>, Error> {
         self.close()
     }
 }
@@ -194,46 +280,62 @@ impl ParquetOutput for FileWriter {
 /// Stream parquet directly to standard out
 struct StandardOut {
     writer: SerializedFileWriter<Box<dyn Write + Send>>,
+    row_groups: RowGroupWriter,
 }
 
 impl StandardOut {
-    pub fn new(schema: Arc<Type>, properties: Arc<WriterProperties>) -> Result<Self, Error> {
+    pub fn new(
+        schema: Arc<Type>,
+        properties: Arc<WriterProperties>,
+        row_group_size: RowGroupSizeLimit,
+    ) -> Result<Self, Error> {
         let output: Box<dyn Write + Send> = Box::new(stdout());
         let writer = SerializedFileWriter::new(output, schema.clone(), properties.clone())?;
 
-        Ok(Self { writer })
+        Ok(Self {
+            writer,
+            row_groups: RowGroupWriter::new(row_group_size),
+        })
     }
 }
 
 impl ParquetOutput for StandardOut {
-    fn write_row_group(
-        &mut self,
-        _num_batch: u32,
-        mut column_exporter: ColumnExporter,
-    ) -> Result<(), Error> {
-        let mut row_group_writer = self.writer.next_row_group()?;
-        let mut col_index = 0;
-        while let Some(mut column_writer) = row_group_writer.next_column()? {
-            column_exporter.export_nth_column(col_index, &mut column_writer)?;
-            column_writer.close()?;
-            col_index += 1;
-        }
-        row_group_writer.close()?;
+    fn write_batch(&mut self, column_exporter: ColumnExporter) -> Result<(), Error> {
+        self.row_groups
+            .write_batch(&mut self.writer, column_exporter)?;
         Ok(())
     }
 
-    fn close(self) -> Result<(), Error> {
+    fn close(mut self) -> Result<Vec<WrittenFile>, Error> {
+        self.row_groups.flush(&mut self.writer)?;
+        self.writer.append_key_value_metadata(file_written_at());
         self.writer.close()?;
-        Ok(())
+        // Standard out is not a file we could describe.
+        Ok(Vec::new())
     }
 
-    fn close_box(self: Box<Self>) -> Result<(), Error> {
+    fn close_box(self: Box<Self>) -> Result<Vec<WrittenFile>, Error> {
         self.close()
     }
 }
 
 fn path_with_suffix(path: &Path, num_file: u32, suffix_length: usize) -> Result<PathBuf, Error> {
     let suffix = format!("_{:0width$}", num_file, width = suffix_length);
+    append_to_file_stem(path, &suffix)
+}
+
+/// Path of the output for an individual partition, if the result set is fetched in parallel. E.g.
+/// `out.par` becomes `out_part_01.par`.
+pub fn path_with_partition_suffix(
+    path: &Path,
+    num_partition: u32,
+    suffix_length: usize,
+) -> Result<PathBuf, Error> {
+    let suffix = format!("_part_{:0width$}", num_partition, width = suffix_length);
+    append_to_file_stem(path, &suffix)
+}
+
+fn append_to_file_stem(path: &Path, suffix: &str) -> Result<PathBuf, Error> {
     let mut stem = path
         .file_stem()
         .ok_or_else(|| format_err!("Output needs To have a file stem."))?
@@ -0,0 +1,246 @@
+use std::{num::NonZeroU32, path::Path, thread};
+
+use anyhow::{anyhow, bail, Context, Error};
+use io_arg::IoArg;
+use log::info;
+use odbc_api::{Connection, Cursor, Environment, IntoParameter, Nullable};
+use parquet::schema::types::Type;
+
+use crate::{open_connection, ConnectOpts};
+
+use super::{
+    batch_size_limit::BatchSizeLimit,
+    column_strategy::MappingOptions,
+    current_file::WrittenFile,
+    fetch_into_parquet,
+    manifest::WriteSummary,
+    parquet_writer::{path_with_partition_suffix, ParquetWriterOptions},
+    prepare_session, subquery_text,
+    table_strategy::TableStrategy,
+};
+
+/// A query whose result set is split into ranges of an integer column. Each range is fetched in
+/// parallel over its own connection.
+pub struct PartitionedQuery<'a> {
+    pub environment: &'a Environment,
+    /// Used to open one connection for each partition.
+    pub connect_opts: &'a ConnectOpts,
+    pub query: &'a str,
+    pub parameters: &'a [String],
+    /// Name of the integer column used to split the result set.
+    pub column: String,
+    pub count: NonZeroU32,
+    pub lower_bound: Option<i64>,
+    pub upper_bound: Option<i64>,
+    /// Executed on the connection of each partition, before fetching it.
+    pub session_statements: &'a [String],
+}
+
+impl PartitionedQuery<'_> {
+    /// Fetch all partitions in parallel and write each into its own output file.
+    ///
+    /// * `odbc_conn`: Used to query the bounds of the partition column, if not specified by the
+    ///   user, and to determine the parquet schema every partition must adhere to.
+    /// * `path`: Output path. Each partition is written to this path with an additional suffix.
+    pub fn to_parquet(
+        &self,
+        odbc_conn: &Connection,
+        path: &Path,
+        batch_size: BatchSizeLimit,
+        mapping_options: MappingOptions,
+        parquet_format_options: ParquetWriterOptions,
+    ) -> Result<WriteSummary, Error> {
+        let (lower, upper) = match (self.lower_bound, self.upper_bound) {
+            (Some(lower), Some(upper)) => (lower, upper),
+            (lower, upper) => {
+                // If there are no values in the partition column, there is nothing to split.
+                // Everything (if anything) ends up in the first partition.
+                let (min, max) = self.query_bounds(odbc_conn)?.unwrap_or((0, 0));
+                (lower.unwrap_or(min), upper.unwrap_or(max))
+            }
+        };
+        if lower > upper {
+            bail!(
+                "Lower bound of partition column ({lower}) must not be larger than its upper \
+                bound ({upper})."
+            )
+        }
+        let conditions = partition_conditions(&self.column, self.count, lower, upper);
+
+        // All partitions must share the same schema, otherwise the output would not form one
+        // consistent dataset. Preparing the statement allows us to inspect the result set
+        // without fetching it.
+        let mut prepared = odbc_conn.prepare(&partition_query(self.query, &conditions[0]))?;
+        let reference_schema = TableStrategy::new(&mut prepared, mapping_options)?.parquet_schema();
+
+        let results: Vec<Result<Vec<WrittenFile>, Error>> = thread::scope(|scope| {
+            let handles: Vec<_> = conditions
+                .iter()
+                .enumerate()
+                .map(|(index, condition)| {
+                    let num_partition = index as u32 + 1;
+                    let reference_schema = &reference_schema;
+                    let parquet_format_options = parquet_format_options.clone();
+                    scope.spawn(move || {
+                        let path = path_with_partition_suffix(
+                            path,
+                            num_partition,
+                            parquet_format_options.suffix_length,
+                        )?;
+                        self.fetch_partition(
+                            condition,
+                            path.as_path(),
+                            reference_schema,
+                            batch_size,
+                            mapping_options,
+                            parquet_format_options,
+                        )
+                        .with_context(|| format!("Failed to fetch partition {num_partition}."))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .expect("Thread fetching partition must not panic.")
+                })
+                .collect()
+        });
+
+        // Report the first error, but only after all partitions are done.
+        let files = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+        Ok(WriteSummary {
+            schema: reference_schema,
+            files: files.into_iter().flatten().collect(),
+        })
+    }
+
+    fn fetch_partition(
+        &self,
+        condition: &str,
+        path: &Path,
+        reference_schema: &Type,
+        batch_size: BatchSizeLimit,
+        mapping_options: MappingOptions,
+        parquet_format_options: ParquetWriterOptions,
+    ) -> Result<Vec<WrittenFile>, Error> {
+        info!("Fetching partition with condition: {condition}");
+        let odbc_conn = open_connection(self.environment, self.connect_opts)?;
+        prepare_session(&odbc_conn, self.session_statements)?;
+        let query = partition_query(self.query, condition);
+        let params: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|param| param.as_str().into_parameter())
+            .collect();
+        let mut cursor = odbc_conn
+            .execute(&query, params.as_slice())?
+            .ok_or_else(|| anyhow!("Query for partition did not return a result set."))?;
+        let table_strategy = TableStrategy::new(&mut cursor, mapping_options)?;
+        if *table_strategy.parquet_schema() != *reference_schema {
+            bail!(
+                "Parquet schema of partition deviates from the schema of the other partitions. \
+                The partitions can not be written as one consistent dataset."
+            )
+        }
+        let summary = fetch_into_parquet(
+            cursor,
+            table_strategy,
+            IoArg::File(path.to_owned()),
+            batch_size,
+            parquet_format_options,
+        )?;
+        Ok(summary.files)
+    }
+
+    /// Query the minimum and maximum value of the partition column within the result set. `None`
+    /// if the result set does not contain any non NULL values in the partition column.
+    fn query_bounds(&self, odbc_conn: &Connection) -> Result<Option<(i64, i64)>, Error> {
+        let query = format!(
+            "SELECT MIN({column}), MAX({column}) FROM ({query}) partition_source",
+            column = self.column,
+            query = subquery_text(self.query)
+        );
+        let params: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|param| param.as_str().into_parameter())
+            .collect();
+        let mut cursor = odbc_conn
+            .execute(&query, params.as_slice())?
+            .ok_or_else(|| anyhow!("Query for partition bounds did not return a result set."))?;
+        let mut row = cursor
+            .next_row()?
+            .ok_or_else(|| anyhow!("Query for partition bounds did not return a row."))?;
+        let mut min = Nullable::<i64>::null();
+        let mut max = Nullable::<i64>::null();
+        row.get_data(1, &mut min)?;
+        row.get_data(2, &mut max)?;
+        let bounds = min.into_opt().zip(max.into_opt());
+        info!("Bounds of partition column '{}': {:?}", self.column, bounds);
+        Ok(bounds)
+    }
+}
+
+/// One condition for each partition. Together they cover the entire result set, including values
+/// outside the bounds and NULLs.
+fn partition_conditions(column: &str, count: NonZeroU32, lower: i64, upper: i64) -> Vec<String> {
+    let count = count.get() as i128;
+    // Calculate with 128 Bit, so the span between the bounds can not overflow.
+    let span = upper as i128 - lower as i128 + 1;
+    let width = (span + count - 1) / count;
+    // Start of each partition, except for the first one.
+    let starts: Vec<i64> = (1..count)
+        .map(|index| (lower as i128 + index * width).min(i64::MAX as i128) as i64)
+        .collect();
+    if starts.is_empty() {
+        return vec!["1=1".to_owned()];
+    }
+    let mut conditions = vec![format!("{column} < {} OR {column} IS NULL", starts[0])];
+    conditions.extend(
+        starts
+            .windows(2)
+            .map(|window| format!("{column} >= {} AND {column} < {}", window[0], window[1])),
+    );
+    conditions.push(format!("{column} >= {}", starts[starts.len() - 1]));
+    conditions
+}
+
+fn partition_query(query: &str, condition: &str) -> String {
+    format!(
+        "SELECT * FROM ({}) partition_source WHERE {condition}",
+        subquery_text(query)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::{partition_conditions, partition_query};
+
+    #[test]
+    fn split_bounds_into_conditions() {
+        let count = |count| NonZeroU32::new(count).unwrap();
+
+        assert_eq!(vec!["1=1"], partition_conditions("a", count(1), 1, 6));
+        assert_eq!(
+            vec!["a < 4 OR a IS NULL", "a >= 4"],
+            partition_conditions("a", count(2), 1, 6)
+        );
+        assert_eq!(
+            vec!["a < 3 OR a IS NULL", "a >= 3 AND a < 5", "a >= 5"],
+            partition_conditions("a", count(3), 1, 5)
+        );
+    }
+
+    #[test]
+    fn wrap_query_for_partition() {
+        assert_eq!(
+            "SELECT * FROM (SELECT a FROM MyTable) partition_source WHERE a >= 4",
+            partition_query("SELECT a FROM MyTable; ", "a >= 4")
+        );
+    }
+}
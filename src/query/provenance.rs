@@ -0,0 +1,67 @@
+//! Key value metadata written into the footer of every output file, so consumers can trace where
+//! the data came from, after the process writing it has long exited.
+
+use anyhow::Error;
+use chrono::{DateTime, SecondsFormat, Utc};
+use odbc_api::{ColumnDescription, ResultSetMetadata};
+use parquet::file::metadata::KeyValue;
+use serde::Serialize;
+
+/// All keys written by odbc2parquet itself start with this prefix. Custom keys must not.
+pub const PROVENANCE_KEY_PREFIX: &str = "odbc2parquet.";
+
+/// Original ODBC description of a column in the result set.
+#[derive(Serialize)]
+struct OdbcColumn {
+    name: String,
+    data_type: String,
+    nullability: String,
+}
+
+/// Entries describing the extraction, which are known before the first row is fetched.
+pub fn provenance(
+    query: &str,
+    parameters: &[String],
+    db_name: &str,
+    extraction_start: DateTime<Utc>,
+) -> Result<Vec<KeyValue>, Error> {
+    Ok(vec![
+        entry("query", query.to_owned()),
+        entry("parameters", serde_json::to_string(parameters)?),
+        entry("dbms_name", db_name.to_owned()),
+        entry("extraction_start", timestamp(extraction_start)),
+        entry("version", env!("CARGO_PKG_VERSION").to_owned()),
+    ])
+}
+
+/// Data types of the columns in the result set, as reported by the ODBC driver. Encoded as JSON
+/// array.
+pub fn odbc_column_types(result_set: &mut impl ResultSetMetadata) -> Result<KeyValue, Error> {
+    let num_cols: u16 = result_set.num_result_cols()?.try_into().unwrap();
+    let mut columns = Vec::new();
+    for index in 1..(num_cols + 1) {
+        let mut cd = ColumnDescription::default();
+        result_set.describe_col(index, &mut cd)?;
+        columns.push(OdbcColumn {
+            name: cd.name_to_string()?,
+            data_type: format!("{:?}", cd.data_type),
+            nullability: format!("{:?}", cd.nullability),
+        });
+    }
+    Ok(entry("odbc_column_types", serde_json::to_string(&columns)?))
+}
+
+/// Written when a file is completed, i.e. after the last of its rows has been fetched. If the
+/// output is split into several files, this is earlier than the end of the extraction for all but
+/// the last one.
+pub fn file_written_at() -> KeyValue {
+    entry("file_written_at", timestamp(Utc::now()))
+}
+
+fn entry(key: &str, value: String) -> KeyValue {
+    KeyValue::new(format!("{PROVENANCE_KEY_PREFIX}{key}"), value)
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
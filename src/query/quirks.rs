@@ -0,0 +1,233 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+/// Known misbehaviors of a data source, or its ODBC driver, which require a workaround. Identified
+/// by the name of the database management system reported by the driver.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Quirks {
+    /// Name of the database management system as reported by the driver, e.g. `Oracle`. A trailing
+    /// `*` matches any suffix, e.g. `DB2/*`.
+    pub dbms_name: String,
+    /// Fetch 64 Bit integers as text, because the driver does not support binding them.
+    #[serde(default)]
+    pub driver_does_not_support_64bit_integers: bool,
+    /// Rely on terminating zeroes, rather than indicators, to determine the length of text.
+    #[serde(default)]
+    pub driver_returns_memory_garbage_for_indicators: bool,
+    /// Fetch columns with the driver specific SQL type `-154` as text and parse them as time. This
+    /// is how Microsoft SQL Server reports `TIME` columns.
+    #[serde(default)]
+    pub time_as_text: bool,
+    /// Fetch columns with the driver specific SQL type `-155` as text and parse them as timestamps
+    /// with time zone. This is how Microsoft SQL Server reports `DATETIMEOFFSET` columns.
+    #[serde(default)]
+    pub datetimeoffset_as_text: bool,
+    /// Arrays are reported as character data. Only their type name tells them apart from text,
+    /// e.g. `_int4` for PostgreSQL.
+    #[serde(default)]
+    pub arrays_reported_as_text: bool,
+    /// Type names of timestamp columns with a time zone, which the driver does not report as such.
+    /// Matched case insensitive. A leading `*` matches any prefix, e.g. `* WITH TIME ZONE`.
+    #[serde(default)]
+    pub time_zone_type_names: Vec<String>,
+    /// Statements executed on each connection before fetching, e.g. in order to configure the
+    /// text representation of values.
+    #[serde(default)]
+    pub session_statements: Vec<String>,
+}
+
+impl Quirks {
+    fn matches(&self, db_name: &str) -> bool {
+        match self.dbms_name.strip_suffix('*') {
+            Some(prefix) => db_name.starts_with(prefix),
+            None => db_name == self.dbms_name,
+        }
+    }
+}
+
+/// `true` if `type_name` matches one of the patterns in [`Quirks::time_zone_type_names`].
+pub fn is_time_zone_type_name(patterns: &[String], type_name: &str) -> bool {
+    let type_name = type_name.to_ascii_uppercase();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_uppercase();
+        match pattern.strip_prefix('*') {
+            Some(suffix) => type_name.ends_with(suffix),
+            None => type_name == pattern,
+        }
+    })
+}
+
+/// Quirk definitions loaded from a file passed at the command line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuirksFile {
+    #[serde(rename = "quirk", default)]
+    pub quirks: Vec<Quirks>,
+}
+
+impl QuirksFile {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read quirks file '{}'", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid quirks file '{}'", path.display()))
+    }
+}
+
+/// Quirks odbc2parquet knows about without being told.
+pub fn built_in_quirks() -> Vec<Quirks> {
+    vec![
+        Quirks {
+            dbms_name: "Microsoft SQL Server".to_owned(),
+            time_as_text: true,
+            datetimeoffset_as_text: true,
+            ..Quirks::default()
+        },
+        Quirks {
+            dbms_name: "PostgreSQL".to_owned(),
+            arrays_reported_as_text: true,
+            time_zone_type_names: vec!["timestamptz".to_owned()],
+            ..Quirks::default()
+        },
+        Quirks {
+            dbms_name: "Oracle".to_owned(),
+            driver_does_not_support_64bit_integers: true,
+            // E.g. `TIMESTAMP(6) WITH TIME ZONE`. `WITH LOCAL TIME ZONE` is normalized to the time
+            // zone of the session and represented without an offset.
+            time_zone_type_names: vec!["* WITH TIME ZONE".to_owned()],
+            // By default timestamps with time zone are formatted according to the NLS settings,
+            // e.g. `07-SEP-22 04.04.12.123000 PM +02:00`, or even with the name of a time zone
+            // region instead of an offset.
+            session_statements: vec![
+                "ALTER SESSION SET NLS_TIMESTAMP_TZ_FORMAT = 'YYYY-MM-DD HH24:MI:SS.FF TZH:TZM'"
+                    .to_owned(),
+            ],
+            ..Quirks::default()
+        },
+        Quirks {
+            dbms_name: "DB2/*".to_owned(),
+            driver_returns_memory_garbage_for_indicators: true,
+            ..Quirks::default()
+        },
+    ]
+}
+
+/// All quirk definitions, in the order they are matched against the DBMS name. Definitions from
+/// `quirks_file` take precedence over built-in ones.
+pub fn quirk_registry(quirks_file: Option<&QuirksFile>, ignore_built_in: bool) -> Vec<Quirks> {
+    let mut registry: Vec<Quirks> = quirks_file
+        .map(|file| file.quirks.clone())
+        .unwrap_or_default();
+    if !ignore_built_in {
+        registry.extend(built_in_quirks());
+    }
+    registry
+}
+
+/// Quirks of the data source with the specified DBMS name. Only the first matching definition is
+/// applied. If none matches, no workarounds are applied.
+pub fn quirks_for(db_name: &str, registry: &[Quirks]) -> Quirks {
+    registry
+        .iter()
+        .find(|quirks| quirks.matches(db_name))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Print the quirk definitions which would be used to the standard output, in the same format as
+/// the file passed to `--quirks-file`.
+pub fn list_quirks(quirks_file: Option<&QuirksFile>, ignore_built_in: bool) -> Result<(), Error> {
+    let registry = QuirksFile {
+        quirks: quirk_registry(quirks_file, ignore_built_in),
+    };
+    print!("{}", toml::to_string(&registry)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_time_zone_type_name, quirk_registry, quirks_for, QuirksFile};
+
+    #[test]
+    fn match_dbms_name() {
+        let registry = quirk_registry(None, false);
+
+        assert!(quirks_for("Oracle", &registry).driver_does_not_support_64bit_integers);
+        assert!(
+            quirks_for("DB2/LINUXX8664", &registry).driver_returns_memory_garbage_for_indicators
+        );
+        assert!(quirks_for("Microsoft SQL Server", &registry).time_as_text);
+        assert!(!quirks_for("PostgreSQL", &registry).time_as_text);
+        assert!(quirks_for("PostgreSQL", &registry).arrays_reported_as_text);
+        assert!(!quirks_for("MySQL", &registry).arrays_reported_as_text);
+    }
+
+    #[test]
+    fn match_time_zone_type_names() {
+        let registry = quirk_registry(None, false);
+        let oracle = quirks_for("Oracle", &registry).time_zone_type_names;
+        let postgres = quirks_for("PostgreSQL", &registry).time_zone_type_names;
+
+        assert!(is_time_zone_type_name(
+            &oracle,
+            "TIMESTAMP(6) WITH TIME ZONE"
+        ));
+        assert!(!is_time_zone_type_name(
+            &oracle,
+            "TIMESTAMP(6) WITH LOCAL TIME ZONE"
+        ));
+        assert!(!is_time_zone_type_name(&oracle, "TIMESTAMP(6)"));
+        assert!(is_time_zone_type_name(&postgres, "timestamptz"));
+        assert!(!is_time_zone_type_name(&postgres, "timestamp"));
+    }
+
+    #[test]
+    fn definitions_from_file_take_precedence() {
+        let quirks_file: QuirksFile = toml::from_str(
+            r#"
+            [[quirk]]
+            dbms-name = "Oracle"
+
+            [[quirk]]
+            dbms-name = "Informix*"
+            driver-does-not-support-64bit-integers = true
+            "#,
+        )
+        .unwrap();
+        let registry = quirk_registry(Some(&quirks_file), false);
+
+        assert!(!quirks_for("Oracle", &registry).driver_does_not_support_64bit_integers);
+        assert!(
+            quirks_for("Informix Dynamic Server", &registry).driver_does_not_support_64bit_integers
+        );
+        assert!(quirks_for("DB2/NT64", &registry).driver_returns_memory_garbage_for_indicators);
+    }
+
+    #[test]
+    fn listed_quirks_can_be_loaded_again() {
+        let registry = QuirksFile {
+            quirks: quirk_registry(None, false),
+        };
+
+        let text = toml::to_string(&registry).unwrap();
+        let loaded: QuirksFile = toml::from_str(&text).unwrap();
+
+        assert!(text.starts_with("[[quirk]]\n"));
+        assert_eq!(registry.quirks, loaded.quirks);
+    }
+
+    #[test]
+    fn reject_unknown_quirks() {
+        let result: Result<QuirksFile, _> = toml::from_str(
+            r#"
+            [[quirk]]
+            dbms-name = "Oracle"
+            makes-coffee = true
+            "#,
+        );
+
+        assert!(result.is_err());
+    }
+}
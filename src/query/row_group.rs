@@ -0,0 +1,284 @@
+use std::{
+    io::Write,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Error;
+use bytes::Bytes;
+use bytesize::ByteSize;
+use parquet::{
+    column::{
+        page::{CompressedPage, PageWriteSpec, PageWriter},
+        writer::{get_column_writer, ColumnWriter},
+    },
+    errors::Result as ParquetResult,
+    file::{
+        metadata::{ColumnChunkMetaData, RowGroupMetaDataPtr},
+        writer::{SerializedFileWriter, SerializedPageWriter, TrackedWrite},
+    },
+};
+
+use super::{batch_size_limit::RowGroupSizeLimit, table_strategy::ColumnExporter};
+
+/// Writes the batches fetched from the data source into the row groups of a parquet file. Several
+/// batches may be accumulated into one row group, or a single batch may be split across several
+/// row groups, depending on the [`RowGroupSizeLimit`].
+pub struct RowGroupWriter {
+    limit: RowGroupSizeLimit,
+    /// Row group which has been started, but is not yet written into the file.
+    pending: Option<RowGroupBuffer>,
+}
+
+impl RowGroupWriter {
+    pub fn new(limit: RowGroupSizeLimit) -> Self {
+        Self {
+            limit,
+            pending: None,
+        }
+    }
+
+    /// Writes the current batch of `column_exporter`. Returns the metadata of every row group
+    /// which has been completed and written into `file` as a consequence.
+    pub fn write_batch<W: Write + Send>(
+        &mut self,
+        file: &mut SerializedFileWriter<W>,
+        mut column_exporter: ColumnExporter,
+    ) -> Result<Vec<RowGroupMetaDataPtr>, Error> {
+        if let RowGroupSizeLimit::Batch = self.limit {
+            // Write directly into the file, there is no need to buffer anything.
+            let mut row_group_writer = file.next_row_group()?;
+            let mut col_index = 0;
+            while let Some(mut column_writer) = row_group_writer.next_column()? {
+                column_exporter.export_nth_column(col_index, column_writer.untyped())?;
+                column_writer.close()?;
+                col_index += 1;
+            }
+            return Ok(vec![row_group_writer.close()?]);
+        }
+
+        let mut completed = Vec::new();
+        let num_rows = column_exporter.num_rows();
+        let mut offset = 0;
+        while offset < num_rows {
+            let pending = self
+                .pending
+                .get_or_insert_with(|| RowGroupBuffer::new(file));
+            let num_rows_chunk = self.limit.max_rows().map_or(num_rows - offset, |max_rows| {
+                (max_rows - pending.num_rows).min(num_rows - offset)
+            });
+            if num_rows_chunk == num_rows {
+                pending.write(&mut column_exporter)?;
+            } else {
+                let rows: Vec<usize> = (offset..offset + num_rows_chunk).collect();
+                let buffer = column_exporter.select_rows(&rows);
+                pending.write(&mut column_exporter.with_rows(&buffer, &rows))?;
+            }
+            offset += num_rows_chunk;
+            if self.limit.is_full(pending.num_rows, pending.size()) {
+                completed.extend(self.flush(file)?);
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Writes the pending row group, if any, into `file`. Must be called before the file is
+    /// closed.
+    pub fn flush<W: Write + Send>(
+        &mut self,
+        file: &mut SerializedFileWriter<W>,
+    ) -> Result<Option<RowGroupMetaDataPtr>, Error> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(None);
+        };
+        let mut row_group_writer = file.next_row_group()?;
+        for (column_writer, pages) in pending.columns {
+            let close_result = column_writer.close()?;
+            let pages = mem::replace(&mut *pages.lock().unwrap(), TrackedWrite::new(Vec::new()));
+            let pages = Bytes::from(pages.into_inner()?);
+            row_group_writer.append_column(&pages, close_result)?;
+        }
+        Ok(Some(row_group_writer.close()?))
+    }
+}
+
+/// Encoded and compressed pages of a single column chunk, held in memory.
+type Pages = Arc<Mutex<TrackedWrite<Vec<u8>>>>;
+
+/// A row group which is accumulated in memory, before it is appended to the file in one go.
+struct RowGroupBuffer {
+    columns: Vec<(ColumnWriter<'static>, Pages)>,
+    num_rows: usize,
+}
+
+impl RowGroupBuffer {
+    fn new<W: Write + Send>(file: &SerializedFileWriter<W>) -> Self {
+        let columns = file
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|column| {
+                let pages = Pages::new(Mutex::new(TrackedWrite::new(Vec::new())));
+                let page_writer = Box::new(InMemoryPageWriter {
+                    pages: pages.clone(),
+                });
+                let column_writer =
+                    get_column_writer(column.clone(), file.properties().clone(), page_writer);
+                (column_writer, pages)
+            })
+            .collect();
+        Self {
+            columns,
+            num_rows: 0,
+        }
+    }
+
+    fn write(&mut self, column_exporter: &mut ColumnExporter) -> Result<(), Error> {
+        for (col_index, (column_writer, _pages)) in self.columns.iter_mut().enumerate() {
+            column_exporter.export_nth_column(col_index, column_writer)?;
+        }
+        self.num_rows += column_exporter.num_rows();
+        Ok(())
+    }
+
+    /// Size of the pages written so far. Does not include values which are still buffered by the
+    /// column writers.
+    fn size(&self) -> ByteSize {
+        let num_bytes = self
+            .columns
+            .iter()
+            .map(|(_column_writer, pages)| pages.lock().unwrap().bytes_written() as u64)
+            .sum();
+        ByteSize::b(num_bytes)
+    }
+}
+
+/// Serializes the pages of a column chunk into memory, so they can be appended to the file once
+/// the row group is complete.
+struct InMemoryPageWriter {
+    pages: Pages,
+}
+
+impl PageWriter for InMemoryPageWriter {
+    fn write_page(&mut self, page: CompressedPage) -> ParquetResult<PageWriteSpec> {
+        SerializedPageWriter::new(&mut self.pages.lock().unwrap()).write_page(page)
+    }
+
+    fn write_metadata(&mut self, _metadata: &ColumnChunkMetaData) -> ParquetResult<()> {
+        // Written by the row group writer of the file, once the column chunk is appended to it.
+        Ok(())
+    }
+
+    fn close(&mut self) -> ParquetResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use bytesize::ByteSize;
+    use odbc_api::{
+        buffers::{AnyBuffer, ColumnarAnyBuffer},
+        RowSetBuffer,
+    };
+    use parquet::{
+        data_type::Int32Type,
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader, writer::SerializedFileWriter,
+        },
+        record::RowAccessor,
+        schema::types::Type,
+    };
+
+    use crate::{
+        parquet_buffer::{ParquetBuffer, RowIndices},
+        query::{
+            batch_size_limit::RowGroupSizeLimit, column_strategy::ColumnStrategy,
+            identical::fetch_identical, table_strategy::ColumnExporter,
+        },
+    };
+
+    use super::RowGroupWriter;
+
+    /// Writes the batches as a single integer column and returns the number of rows in each row
+    /// group, together with all values in the file.
+    fn write_batches(limit: RowGroupSizeLimit, batches: &[&[i32]]) -> (Vec<i64>, Vec<i32>) {
+        let columns: Vec<(String, Box<dyn ColumnStrategy>)> =
+            vec![("a".to_owned(), fetch_identical::<Int32Type>(false))];
+        let schema = Type::group_type_builder("schema")
+            .with_fields(vec![Arc::new(columns[0].1.parquet_type("a"))])
+            .build()
+            .unwrap();
+        let mut file = SerializedFileWriter::new(
+            Vec::new(),
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+        let mut row_groups = RowGroupWriter::new(limit);
+        let mut conversion_buffer = ParquetBuffer::new(10);
+        for batch in batches {
+            let mut buffer = ColumnarAnyBuffer::new(vec![(1, AnyBuffer::I32(batch.to_vec()))]);
+            *buffer.mut_num_fetch_rows() = batch.len();
+            let column_exporter = ColumnExporter::new(
+                &buffer,
+                &mut conversion_buffer,
+                &columns,
+                false,
+                RowIndices::Consecutive(0),
+            );
+            row_groups.write_batch(&mut file, column_exporter).unwrap();
+        }
+        row_groups.flush(&mut file).unwrap();
+        let bytes = Bytes::from(file.into_inner().unwrap());
+
+        let reader = SerializedFileReader::new(bytes).unwrap();
+        let row_group_sizes = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group.num_rows())
+            .collect();
+        let values = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().get_int(0).unwrap())
+            .collect();
+        (row_group_sizes, values)
+    }
+
+    #[test]
+    fn one_row_group_per_batch() {
+        let (row_group_sizes, values) =
+            write_batches(RowGroupSizeLimit::Batch, &[&[1, 2], &[3], &[4, 5]]);
+
+        assert_eq!(vec![2, 1, 2], row_group_sizes);
+        assert_eq!(vec![1, 2, 3, 4, 5], values);
+    }
+
+    #[test]
+    fn accumulate_and_split_batches_by_rows() {
+        let (row_group_sizes, values) = write_batches(
+            RowGroupSizeLimit::Rows(3),
+            &[&[1, 2], &[3, 4, 5, 6, 7], &[8]],
+        );
+
+        assert_eq!(vec![3, 3, 2], row_group_sizes);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], values);
+    }
+
+    #[test]
+    fn accumulate_batches_by_size() {
+        let (row_group_sizes, values) = write_batches(
+            RowGroupSizeLimit::Bytes(ByteSize::gib(1)),
+            &[&[1, 2], &[3], &[4]],
+        );
+
+        assert_eq!(vec![4], row_group_sizes);
+        assert_eq!(vec![1, 2, 3, 4], values);
+    }
+}
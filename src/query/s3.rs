@@ -0,0 +1,293 @@
+//! Upload of output files into S3 compatible object storage.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, format_err, Context, Error};
+use log::{debug, warn};
+use rusty_s3::{actions::CreateMultipartUpload, Bucket, Credentials, S3Action, UrlStyle};
+use ureq::Agent;
+
+use crate::S3Opts;
+
+/// Prefix of output paths, which are written to S3 rather than the local file system.
+const S3_SCHEME: &str = "s3://";
+
+/// Amazon S3 does not accept parts smaller than this, except for the last one.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// S3 limits the number of parts of a single upload.
+const MAX_NUM_PARTS: u16 = 10_000;
+
+/// Validity of the presigned URLs used for each individual request.
+const SIGNATURE_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+/// Splits an output path like `s3://bucket/prefix/file.par` into bucket name and object key.
+/// `None` if the path does not refer to S3.
+pub fn s3_location(path: &Path) -> Option<Result<(String, PathBuf), Error>> {
+    let path = path.to_str()?;
+    let location = path.strip_prefix(S3_SCHEME)?;
+    let result = match location.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+            Ok((bucket.to_owned(), PathBuf::from(key)))
+        }
+        _ => Err(format_err!(
+            "Output '{path}' must specify both bucket and object key, e.g. \
+            's3://bucket/prefix/file.par'."
+        )),
+    };
+    Some(result)
+}
+
+/// Object keys always use `/` as separator, independent of the platform.
+fn object_key(path: &Path) -> String {
+    let key = path.to_string_lossy().into_owned();
+    if cfg!(target_os = "windows") {
+        key.replace('\\', "/")
+    } else {
+        key
+    }
+}
+
+/// A bucket in S3 compatible object storage, together with everything required to upload objects
+/// into it.
+pub struct S3Storage {
+    bucket: Bucket,
+    /// `None` for anonymous access.
+    credentials: Option<Credentials>,
+    agent: Agent,
+    /// Size of each part of a multipart upload in bytes (except the last one).
+    part_size: usize,
+}
+
+impl S3Storage {
+    pub fn new(bucket_name: String, opts: &S3Opts) -> Result<Self, Error> {
+        if opts.s3_part_size.as_u64() < MIN_PART_SIZE {
+            bail!("--s3-part-size must be at least 5MiB.")
+        }
+        let endpoint = opts
+            .s3_endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", opts.s3_region));
+        let url_style = if opts.s3_path_style {
+            UrlStyle::Path
+        } else {
+            UrlStyle::VirtualHost
+        };
+        let bucket = Bucket::new(
+            endpoint
+                .parse()
+                .with_context(|| format!("Invalid S3 endpoint '{endpoint}'"))?,
+            url_style,
+            bucket_name,
+            opts.s3_region.clone(),
+        )?;
+        let credentials = match (&opts.s3_access_key_id, &opts.s3_secret_access_key) {
+            (Some(key), Some(secret)) => Some(match &opts.s3_session_token {
+                Some(token) => Credentials::new_with_token(key, secret, token),
+                None => Credentials::new(key, secret),
+            }),
+            (None, None) => None,
+            _ => bail!(
+                "Access key id and secret access key for S3 must be specified together. Use \
+                `--s3-access-key-id` and `--s3-secret-access-key`, or the environment variables \
+                `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`."
+            ),
+        };
+        Ok(Self {
+            bucket,
+            credentials,
+            agent: Agent::new_with_defaults(),
+            part_size: opts.s3_part_size.as_u64().try_into().unwrap(),
+        })
+    }
+
+    /// URL of the object with the specified key, e.g. `s3://bucket/prefix/file.par`.
+    pub fn url(&self, key: &Path) -> String {
+        format!("{S3_SCHEME}{}/{}", self.bucket.name(), object_key(key))
+    }
+
+    /// Starts the upload of a new object. The object only becomes visible once the upload is
+    /// completed.
+    pub fn create(self: &Arc<Self>, key: &Path) -> Result<MultipartUpload, Error> {
+        let key = object_key(key);
+        let action = self
+            .bucket
+            .create_multipart_upload(self.credentials.as_ref(), &key);
+        let url = action.sign(SIGNATURE_VALIDITY);
+        let body = self
+            .agent
+            .post(url.as_str())
+            .send_empty()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .with_context(|| {
+                format!(
+                    "Could not start upload of '{}' into bucket '{}'",
+                    key,
+                    self.bucket.name()
+                )
+            })?;
+        let upload_id = CreateMultipartUpload::parse_response(&body)
+            .map_err(|err| format_err!("Invalid response to creating multipart upload: {err}"))?
+            .upload_id()
+            .to_owned();
+        debug!("Started multipart upload {upload_id} of '{key}'.");
+        Ok(MultipartUpload {
+            storage: self.clone(),
+            key,
+            upload_id,
+            buffer: Vec::new(),
+            etags: Vec::new(),
+            completed: false,
+        })
+    }
+}
+
+/// Streams an object into S3 using a multipart upload. Written bytes are buffered until they fill a
+/// part. If dropped without calling [`Self::complete`], the upload is aborted and the object is
+/// never created.
+pub struct MultipartUpload {
+    storage: Arc<S3Storage>,
+    key: String,
+    upload_id: String,
+    /// Bytes not yet uploaded as part.
+    buffer: Vec<u8>,
+    /// ETag of each part uploaded so far. Required to complete the upload.
+    etags: Vec<String>,
+    completed: bool,
+}
+
+impl MultipartUpload {
+    /// Uploads the remaining bytes and assembles all the parts into the final object.
+    pub fn complete(mut self) -> Result<(), Error> {
+        self.upload_last_part()?;
+        let storage = &self.storage;
+        let action = storage.bucket.complete_multipart_upload(
+            storage.credentials.as_ref(),
+            &self.key,
+            &self.upload_id,
+            self.etags.iter().map(String::as_str),
+        );
+        let url = action.sign(SIGNATURE_VALIDITY);
+        let response = storage
+            .agent
+            .post(url.as_str())
+            .send(action.body())
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .with_context(|| format!("Could not complete upload of '{}'", self.key))?;
+        // S3 may report errors with a successful status code, once it already started sending the
+        // response.
+        if response.contains("<Error>") {
+            bail!("Could not complete upload of '{}': {}", self.key, response)
+        }
+        self.completed = true;
+        Ok(())
+    }
+
+    /// Uploads the bytes not yet sent as a part. Must only be called once everything has been
+    /// written, since only the last part may be smaller than the minimum part size.
+    pub fn upload_last_part(&mut self) -> Result<(), Error> {
+        // An object consists of at least one part, even if it is empty.
+        if !self.buffer.is_empty() || self.etags.is_empty() {
+            self.upload_part()?;
+        }
+        Ok(())
+    }
+
+    fn upload_part(&mut self) -> Result<(), Error> {
+        if self.etags.len() == MAX_NUM_PARTS as usize {
+            bail!(
+                "Upload of '{}' exceeds the maximum of {MAX_NUM_PARTS} parts. Increase \
+                `--s3-part-size` or limit the file size using `--file-size-threshold`.",
+                self.key
+            )
+        }
+        let part_number = self.etags.len() as u16 + 1;
+        let storage = &self.storage;
+        let action = storage.bucket.upload_part(
+            storage.credentials.as_ref(),
+            &self.key,
+            part_number,
+            &self.upload_id,
+        );
+        let url = action.sign(SIGNATURE_VALIDITY);
+        let response = storage
+            .agent
+            .put(url.as_str())
+            .send(self.buffer.as_slice())
+            .with_context(|| format!("Could not upload part {part_number} of '{}'", self.key))?;
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| format_err!("Response to uploading a part lacks an ETag."))?;
+        debug!(
+            "Uploaded part {part_number} of '{}' with {} bytes.",
+            self.key,
+            self.buffer.len()
+        );
+        self.etags.push(etag.to_owned());
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Write for MultipartUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = buf.len().min(self.storage.part_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..num_bytes]);
+        if self.buffer.len() == self.storage.part_size {
+            self.upload_part().map_err(io::Error::other)?;
+        }
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Parts must have a minimum size, so we can not upload them any earlier.
+        Ok(())
+    }
+}
+
+impl Drop for MultipartUpload {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        let storage = &self.storage;
+        let action = storage.bucket.abort_multipart_upload(
+            storage.credentials.as_ref(),
+            &self.key,
+            &self.upload_id,
+        );
+        let url = action.sign(SIGNATURE_VALIDITY);
+        if let Err(err) = storage.agent.delete(url.as_str()).call() {
+            warn!(
+                "Could not abort upload of '{}'. Incomplete parts may remain in the bucket: {err}",
+                self.key
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::s3_location;
+
+    #[test]
+    fn split_s3_location() {
+        assert_eq!(
+            ("bucket".to_owned(), PathBuf::from("prefix/file.par")),
+            s3_location(Path::new("s3://bucket/prefix/file.par"))
+                .unwrap()
+                .unwrap()
+        );
+        assert!(s3_location(Path::new("out/file.par")).is_none());
+        assert!(s3_location(Path::new("s3://bucket")).unwrap().is_err());
+    }
+}
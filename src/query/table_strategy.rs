@@ -1,20 +1,26 @@
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use log::{debug, info};
 use odbc_api::{
     buffers::ColumnarAnyBuffer, BlockCursor, ColumnDescription, Cursor, ResultSetMetadata,
+    RowSetBuffer,
 };
 use parquet::{
-    file::writer::SerializedColumnWriter,
+    column::writer::ColumnWriter,
     schema::types::{Type, TypePtr},
 };
 use std::sync::Arc;
 
-use crate::parquet_buffer::ParquetBuffer;
+use crate::parquet_buffer::{ParquetBuffer, RowIndices};
 
 use super::{
     batch_size_limit::BatchSizeLimit,
     column_strategy::{strategy_from_column_description, ColumnStrategy, MappingOptions},
+    compat::sanitize_column_name,
+    current_file::WrittenFile,
+    hive_partitioned::select_rows,
+    json::{Json, JsonType},
     parquet_writer::ParquetOutput,
+    position_by_name,
 };
 
 /// Contains the decisions of how to fetch each columns of a table from an ODBC data source and copy
@@ -23,6 +29,10 @@ use super::{
 pub struct TableStrategy {
     columns: Vec<ColumnInfo>,
     parquet_schema: TypePtr,
+    /// Determine the length of text values by their terminating zero, rather than their indicator.
+    /// Also applies to text values which are not written by a column strategy, e.g. in order to
+    /// determine the partition of a row.
+    ignore_indicators: bool,
 }
 
 /// Name, ColumnStrategy
@@ -53,8 +63,19 @@ impl TableStrategy {
                 name
             };
 
-            let column_fetch_strategy =
+            let mut column_fetch_strategy =
                 strategy_from_column_description(&cd, &name, mapping_options, cursor, index)?;
+            if let Some(json_type) = json_column_type(mapping_options.json_columns, &name) {
+                info!("Parsing column '{name}' as JSON.");
+                column_fetch_strategy = Box::new(
+                    Json::new(
+                        column_fetch_strategy.as_ref(),
+                        json_type.clone(),
+                        mapping_options.json_invalid,
+                    )
+                    .with_context(|| format!("Can not parse column '{name}' as JSON."))?,
+                );
+            }
             columns.push((name, column_fetch_strategy));
         }
 
@@ -62,6 +83,40 @@ impl TableStrategy {
             bail!("Resulting parquet file would not have any columns!")
         }
 
+        ensure_columns_exist(
+            &columns,
+            mapping_options.json_columns.iter().map(|(name, _)| name),
+            "A JSON schema",
+        )?;
+        ensure_columns_exist(
+            &columns,
+            mapping_options.column_types.iter().map(|(name, _)| name),
+            "A column type",
+        )?;
+        ensure_columns_exist(
+            &columns,
+            mapping_options.trim_char_padding_columns,
+            "Trimming padding",
+        )?;
+
+        if mapping_options.sanitize_column_names {
+            for (name, _) in &mut columns {
+                let sanitized = sanitize_column_name(name);
+                if sanitized != *name {
+                    info!("Renaming column '{name}' to '{sanitized}'.");
+                    *name = sanitized;
+                }
+            }
+            for (index, (name, _)) in columns.iter().enumerate() {
+                if columns[..index].iter().any(|(other, _)| other == name) {
+                    bail!(
+                        "Column name '{name}' occurs more than once after replacing unsupported \
+                        characters. Please use distinct aliases in the query."
+                    )
+                }
+            }
+        }
+
         let fields = columns
             .iter()
             .map(|(name, s)| Arc::new(s.parquet_type(name)))
@@ -76,20 +131,26 @@ impl TableStrategy {
         Ok(TableStrategy {
             columns,
             parquet_schema,
+            ignore_indicators: mapping_options.driver_returns_memory_garbage_for_indicators,
         })
     }
 
-    pub fn allocate_fetch_buffer(
-        &self,
-        batch_size: BatchSizeLimit,
-    ) -> Result<ColumnarAnyBuffer, Error> {
+    /// Memory required to fetch and convert a single row. This excludes memory directly allocated
+    /// by the ODBC driver.
+    pub fn memory_usage_per_row(&self) -> usize {
         let mem_usage_odbc_buffer_per_row: usize = self
             .columns
             .iter()
             .map(|(_name, strategy)| strategy.buffer_desc().bytes_per_row())
             .sum();
-        let total_mem_usage_per_row =
-            mem_usage_odbc_buffer_per_row + ParquetBuffer::MEMORY_USAGE_BYTES_PER_ROW;
+        mem_usage_odbc_buffer_per_row + ParquetBuffer::MEMORY_USAGE_BYTES_PER_ROW
+    }
+
+    pub fn allocate_fetch_buffer(
+        &self,
+        batch_size: BatchSizeLimit,
+    ) -> Result<ColumnarAnyBuffer, Error> {
+        let total_mem_usage_per_row = self.memory_usage_per_row();
         info!(
             "Memory usage per row is {} bytes. This excludes memory directly allocated by the ODBC \
             driver.",
@@ -114,11 +175,40 @@ impl TableStrategy {
         self.parquet_schema.clone()
     }
 
-    pub fn block_cursor_to_parquet(
+    /// Name and strategy of each column, in the order of the result set.
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &dyn ColumnStrategy)> {
+        self.columns
+            .iter()
+            .map(|(name, strategy)| (name.as_str(), strategy.as_ref()))
+    }
+
+    /// Zero based index of the column with the specified name. The name is matched case
+    /// insensitive, if no column with the exact name exists.
+    pub fn column_index(&self, name: &str) -> Result<usize, Error> {
+        position_by_name(&self.columns, name, |(column_name, _)| column_name)
+            .ok_or_else(|| anyhow!("Result set does not contain a column named '{name}'."))
+    }
+
+    /// Replaces the strategy of the column with the specified name, with one derived from it. The
+    /// new strategy must map to the same parquet type. See [`Self::column_index`] for how the
+    /// column is matched.
+    pub fn map_column_strategy(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(Box<dyn ColumnStrategy>) -> Result<Box<dyn ColumnStrategy>, Error>,
+    ) -> Result<(), Error> {
+        let index = self.column_index(name)?;
+        let (column_name, strategy) = self.columns.remove(index);
+        let strategy = f(strategy)?;
+        self.columns.insert(index, (column_name, strategy));
+        Ok(())
+    }
+
+    pub fn block_cursor_to_parquet<C: Cursor>(
         &self,
-        mut row_set_cursor: BlockCursor<impl Cursor, &mut ColumnarAnyBuffer>,
+        mut row_set_cursor: BlockCursor<C, &mut ColumnarAnyBuffer>,
         mut writer: Box<dyn ParquetOutput>,
-    ) -> Result<(), Error> {
+    ) -> Result<Vec<WrittenFile>, Error> {
         let mut num_batch = 0;
         // Count the number of total rows fetched so far for logging. This should be identical to
         // `num_batch * batch_size_row + num_rows`.
@@ -132,32 +222,47 @@ impl TableStrategy {
         {
             num_batch += 1;
             let num_rows = buffer.num_rows();
+            let first_row = total_rows_fetched;
             total_rows_fetched += num_rows;
             info!("Fetched batch {num_batch} with {num_rows} rows.");
             info!("Fetched {total_rows_fetched} rows in total.");
-            self.write_batch(&mut writer, num_batch, buffer, &mut pb)?;
+            self.write_batch(&mut writer, buffer, &mut pb, first_row)?;
+            if self.ignore_indicators {
+                let (cursor, buffer) = row_set_cursor.unbind()?;
+                self.clear_fetch_buffer(buffer);
+                row_set_cursor = cursor.bind_buffer(buffer)?;
+            }
         }
-        writer.close_box()?;
-        Ok(())
+        writer.close_box()
+    }
+
+    /// Zeroes the fetch buffer. Drivers do not write to the buffer for NULL values. If we ignore
+    /// the indicators, we would otherwise see the text of the previous batch in their place,
+    /// rather than an empty string.
+    fn clear_fetch_buffer(&self, buffer: &mut ColumnarAnyBuffer) {
+        *buffer = ColumnarAnyBuffer::from_descs(
+            buffer.row_array_size(),
+            self.columns
+                .iter()
+                .map(|(_name, strategy)| strategy.buffer_desc()),
+        );
     }
 
     fn write_batch(
         &self,
         writer: &mut Box<dyn ParquetOutput>,
-        num_batch: u32,
         buffer: &ColumnarAnyBuffer,
         pb: &mut ParquetBuffer,
+        first_row: usize,
     ) -> Result<(), Error> {
-        let num_rows = buffer.num_rows();
-        pb.set_num_rows_fetched(num_rows);
-
-        let column_exporter = ColumnExporter {
+        let column_exporter = ColumnExporter::new(
             buffer,
-            conversion_buffer: pb,
-            columns: &self.columns,
-        };
-
-        writer.write_row_group(num_batch, column_exporter)?;
+            pb,
+            &self.columns,
+            self.ignore_indicators,
+            RowIndices::Consecutive(first_row),
+        );
+        writer.write_batch(column_exporter)?;
         Ok(())
     }
 }
@@ -167,25 +272,181 @@ pub struct ColumnExporter<'a> {
     buffer: &'a ColumnarAnyBuffer,
     conversion_buffer: &'a mut ParquetBuffer,
     columns: &'a [(String, Box<dyn ColumnStrategy>)],
+    /// Indices of the exported columns within `columns`, if only a subset of them is written into
+    /// the output. `None` if all columns are exported.
+    column_indices: Option<&'a [usize]>,
+    /// See [`TableStrategy::ignore_indicators`].
+    ignore_indicators: bool,
+    /// Position of the rows in `buffer` within the result set.
+    row_indices: RowIndices,
 }
 
 impl<'a> ColumnExporter<'a> {
+    /// Exports all `columns` of the batch in `buffer`.
+    pub fn new(
+        buffer: &'a ColumnarAnyBuffer,
+        conversion_buffer: &'a mut ParquetBuffer,
+        columns: &'a [(String, Box<dyn ColumnStrategy>)],
+        ignore_indicators: bool,
+        row_indices: RowIndices,
+    ) -> Self {
+        conversion_buffer.set_num_rows_fetched(buffer.num_rows());
+        ColumnExporter {
+            buffer,
+            conversion_buffer,
+            columns,
+            column_indices: None,
+            ignore_indicators,
+            row_indices,
+        }
+    }
+
+    /// Number of rows in the current batch.
+    pub fn num_rows(&self) -> usize {
+        self.buffer.num_rows()
+    }
+
+    /// Copies the rows with the specified indices of the current batch into a new buffer.
+    pub fn select_rows(&self, rows: &[usize]) -> ColumnarAnyBuffer {
+        let columns = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(col_index, (_name, strategy))| {
+                let column = select_rows(
+                    self.buffer.column(col_index),
+                    strategy.buffer_desc(),
+                    rows,
+                    self.ignore_indicators,
+                );
+                ((col_index + 1) as u16, column)
+            })
+            .collect();
+        let mut buffer = ColumnarAnyBuffer::new(columns);
+        *buffer.mut_num_fetch_rows() = rows.len();
+        buffer
+    }
+
+    /// Exports only the columns with the specified `column_indices` of the current batch.
+    pub fn with_columns<'b>(&'b mut self, column_indices: &'b [usize]) -> ColumnExporter<'b> {
+        ColumnExporter {
+            buffer: self.buffer,
+            conversion_buffer: self.conversion_buffer,
+            columns: self.columns,
+            column_indices: Some(column_indices),
+            ignore_indicators: self.ignore_indicators,
+            row_indices: self.row_indices.clone(),
+        }
+    }
+
+    /// Exports the rows in `buffer` instead of the current batch. `buffer` must have the same
+    /// layout as the fetch buffer, e.g. created by [`Self::select_rows`] from the specified `rows`.
+    /// Only the columns with the specified `column_indices` are exported.
+    pub fn with_buffer<'b>(
+        &'b mut self,
+        buffer: &'b ColumnarAnyBuffer,
+        rows: &[usize],
+        column_indices: &'b [usize],
+    ) -> ColumnExporter<'b> {
+        self.conversion_buffer
+            .set_num_rows_fetched(buffer.num_rows());
+        ColumnExporter {
+            buffer,
+            conversion_buffer: self.conversion_buffer,
+            columns: self.columns,
+            column_indices: Some(column_indices),
+            ignore_indicators: self.ignore_indicators,
+            row_indices: self.row_indices.select(rows),
+        }
+    }
+
+    /// Exports the rows in `buffer` instead of the current batch, keeping the selection of
+    /// exported columns. `buffer` must have the same layout as the current batch, e.g. created by
+    /// [`Self::select_rows`] from the specified `rows`.
+    pub fn with_rows<'b>(
+        &'b mut self,
+        buffer: &'b ColumnarAnyBuffer,
+        rows: &[usize],
+    ) -> ColumnExporter<'b> {
+        self.conversion_buffer
+            .set_num_rows_fetched(buffer.num_rows());
+        ColumnExporter {
+            buffer,
+            conversion_buffer: self.conversion_buffer,
+            columns: self.columns,
+            column_indices: self.column_indices,
+            ignore_indicators: self.ignore_indicators,
+            row_indices: self.row_indices.select(rows),
+        }
+    }
+
+    /// Exports the primitive parquet column with index `col_index`. Columns of the result set
+    /// mapped to nested groups may span several primitive parquet columns.
     pub fn export_nth_column(
         &mut self,
         col_index: usize,
-        column_writer: &mut SerializedColumnWriter,
+        column_writer: &mut ColumnWriter,
     ) -> Result<(), Error> {
+        let (col_index, leaf) = self.column_and_leaf(col_index);
         let col_name = &self.columns[col_index].0;
         debug!("Writing column with index {col_index} and name '{col_name}'.");
         let odbc_column = self.buffer.column(col_index);
+        self.conversion_buffer
+            .set_row_indices(self.row_indices.clone());
         self.columns[col_index]
             .1
-            .copy_odbc_to_parquet(self.conversion_buffer, column_writer.untyped(), odbc_column)
+            .copy_odbc_to_parquet_leaf(self.conversion_buffer, leaf, column_writer, odbc_column)
             .with_context(|| {
                 format!("Failed to copy column '{col_name}' from ODBC representation into Parquet.")
             })?;
         Ok::<(), Error>(())
     }
+
+    /// Index of the result set column and of the leaf within it, for the primitive parquet column
+    /// with index `col_index`.
+    fn column_and_leaf(&self, col_index: usize) -> (usize, usize) {
+        let mut leaf = col_index;
+        let column_indices: Box<dyn Iterator<Item = usize>> = match self.column_indices {
+            Some(column_indices) => Box::new(column_indices.iter().copied()),
+            None => Box::new(0..self.columns.len()),
+        };
+        for index in column_indices {
+            let num_leaves = self.columns[index].1.num_leaves();
+            if leaf < num_leaves {
+                return (index, leaf);
+            }
+            leaf -= num_leaves;
+        }
+        panic!("Parquet column index {col_index} is out of bounds.")
+    }
+}
+
+/// The JSON type specified by the user for the column with the given name. Names are matched case
+/// insensitive, if there is no exact match.
+fn json_column_type<'a>(
+    json_columns: &'a [(String, JsonType)],
+    name: &str,
+) -> Option<&'a JsonType> {
+    position_by_name(json_columns, name, |(column_name, _)| column_name)
+        .map(|index| &json_columns[index].1)
+}
+
+/// Fails if the result set does not contain a column for each of the `names`, which `option` has
+/// been specified for. Otherwise a typo in a column name would silently not have any effect.
+fn ensure_columns_exist<'a>(
+    columns: &[ColumnInfo],
+    names: impl IntoIterator<Item = &'a String>,
+    option: &str,
+) -> Result<(), Error> {
+    for name in names {
+        if position_by_name(columns, name, |(column_name, _)| column_name).is_none() {
+            bail!(
+                "{option} has been specified for '{name}', but the result set does not contain a \
+                column with this name."
+            )
+        }
+    }
+    Ok(())
 }
 
 /// If we hit the issue with oracle not supporting 64Bit, let's tell our users that we have
@@ -202,3 +463,59 @@ fn give_hint_about_flag_for_oracle_users(error: odbc_api::Error) -> Error {
         other => other.into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use odbc_api::{
+        buffers::{AnyBuffer, ColumnarAnyBuffer, TextColumn},
+        RowSetBuffer,
+    };
+    use parquet::{basic::Repetition, schema::types::Type};
+
+    use crate::{
+        enum_args::InvalidUtf8,
+        query::text::{text_strategy, text_value},
+    };
+
+    use super::TableStrategy;
+
+    #[test]
+    fn do_not_see_text_of_previous_batch_ignoring_indicators() {
+        let max_str_len = 5;
+        let strategy = text_strategy(
+            false,
+            Repetition::OPTIONAL,
+            max_str_len,
+            false,
+            None,
+            InvalidUtf8::Error,
+            true,
+        );
+        let table_strategy = TableStrategy {
+            columns: vec![("a".to_owned(), strategy)],
+            parquet_schema: Arc::new(Type::group_type_builder("schema").build().unwrap()),
+            ignore_indicators: true,
+        };
+        // First batch, with values for both rows
+        let mut column = TextColumn::new(2, max_str_len);
+        column.set_value(0, Some(b"hello"));
+        column.set_value(1, Some(b"world"));
+        let mut buffer = ColumnarAnyBuffer::new(vec![(1, AnyBuffer::Text(column))]);
+        *buffer.mut_num_fetch_rows() = 2;
+        let values = |buffer: &ColumnarAnyBuffer| -> Vec<Vec<u8>> {
+            let view = buffer.column(0).as_text_view().unwrap();
+            (0..view.len())
+                .map(|row_index| text_value(view, row_index, true).unwrap().to_vec())
+                .collect()
+        };
+        assert_eq!(vec![b"hello".to_vec(), b"world".to_vec()], values(&buffer));
+
+        // Second batch. The driver does not write anything to the buffer for NULL values.
+        table_strategy.clear_fetch_buffer(&mut buffer);
+        *buffer.mut_num_fetch_rows() = 2;
+
+        assert_eq!(vec![Vec::<u8>::new(), Vec::new()], values(&buffer));
+    }
+}
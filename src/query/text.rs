@@ -1,8 +1,10 @@
-use std::borrow::Cow;
-
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Context, Error};
+use encoding_rs::{Encoding, UTF_8};
 use log::warn;
-use odbc_api::buffers::{AnySlice, BufferDesc};
+use odbc_api::{
+    buffers::{AnySlice, BufferDesc, TextColumnView},
+    U16Str,
+};
 use parquet::{
     basic::{ConvertedType, Repetition, Type as PhysicalType},
     column::writer::{get_typed_column_writer_mut, ColumnWriter},
@@ -10,19 +12,41 @@ use parquet::{
     schema::types::Type,
 };
 
-use crate::parquet_buffer::ParquetBuffer;
+use crate::{enum_args::InvalidUtf8, parquet_buffer::ParquetBuffer};
 
 use super::column_strategy::ColumnStrategy;
 
+/// * `trim_padding`: Remove trailing blanks, like the ones padding the values of `CHAR(n)` columns.
+/// * `source_charset`: Character set of narrow text returned by the data source. `None` for UTF-8.
+/// * `invalid_utf8`: How to handle values which are not valid in the source character set. Only
+///   relevant if narrow characters are fetched, i.e. `use_utf16` is `false`.
+/// * `ignore_indicators`: Determine the length of values by their terminating zero, rather than
+///   the indicators reported by the driver. Empty values of nullable columns become NULL.
 pub fn text_strategy(
     use_utf16: bool,
     repetition: Repetition,
     length: usize,
+    trim_padding: bool,
+    source_charset: Option<&'static Encoding>,
+    invalid_utf8: InvalidUtf8,
+    ignore_indicators: bool,
 ) -> Box<dyn ColumnStrategy> {
     if use_utf16 {
-        Box::new(Utf16ToUtf8::new(repetition, length))
+        Box::new(Utf16ToUtf8::new(
+            repetition,
+            length,
+            trim_padding,
+            ignore_indicators,
+        ))
     } else {
-        Box::new(Utf8::with_bytes_length(repetition, length))
+        Box::new(Utf8::new(
+            repetition,
+            length,
+            trim_padding,
+            source_charset,
+            invalid_utf8,
+            ignore_indicators,
+        ))
     }
 }
 
@@ -30,11 +54,23 @@ struct Utf16ToUtf8 {
     repetition: Repetition,
     /// Length of the column elements in `u16` (as opposed to code points).
     length: usize,
+    trim_padding: bool,
+    ignore_indicators: bool,
 }
 
 impl Utf16ToUtf8 {
-    pub fn new(repetition: Repetition, length: usize) -> Self {
-        Self { repetition, length }
+    pub fn new(
+        repetition: Repetition,
+        length: usize,
+        trim_padding: bool,
+        ignore_indicators: bool,
+    ) -> Self {
+        Self {
+            repetition,
+            length,
+            trim_padding,
+            ignore_indicators,
+        }
     }
 }
 
@@ -59,55 +95,84 @@ impl ColumnStrategy for Utf16ToUtf8 {
         column_writer: &mut ColumnWriter,
         column_view: AnySlice,
     ) -> Result<(), Error> {
-        write_utf16_to_utf8(parquet_buffer, column_writer, column_view)
-    }
-}
+        let cw = get_typed_column_writer_mut::<ByteArrayType>(column_writer);
+        let view = column_view.as_w_text_view().unwrap();
+        let is_optional = self.repetition == Repetition::OPTIONAL;
 
-fn write_utf16_to_utf8(
-    pb: &mut ParquetBuffer,
-    column_writer: &mut ColumnWriter,
-    column_reader: AnySlice,
-) -> Result<(), Error> {
-    let cw = get_typed_column_writer_mut::<ByteArrayType>(column_writer);
-    let view = column_reader.as_w_text_view().unwrap();
-
-    pb.write_optional_falliable(
-        cw,
-        view.iter().map(|item| {
-            if let Some(ustr) = item {
-                let byte_array: ByteArray = ustr
-                    .to_string()
-                    .map_err(|_utf_16_error| {
-                        anyhow!("Data source must return valid UTF16 in wide character buffer")
-                    })?
-                    .into_bytes()
-                    .into();
-                Ok(Some(byte_array))
-            } else {
-                Ok(None)
-            }
-        }),
-    )?;
-    Ok(())
+        parquet_buffer.write_optional_falliable(
+            cw,
+            text_values(view, self.ignore_indicators, is_optional).map(|item| {
+                if let Some(chars) = item {
+                    let chars = if self.trim_padding {
+                        trim_trailing_blanks(chars, u16::from(b' '))
+                    } else {
+                        chars
+                    };
+                    let byte_array: ByteArray = U16Str::from_slice(chars)
+                        .to_string()
+                        .map_err(|_utf_16_error| {
+                            anyhow!("Data source must return valid UTF16 in wide character buffer")
+                        })?
+                        .into_bytes()
+                        .into();
+                    Ok(Some(byte_array))
+                } else {
+                    Ok(None)
+                }
+            }),
+        )?;
+        Ok(())
+    }
 }
 
 pub struct Utf8 {
     repetition: Repetition,
     // Maximum string length in bytes
     length: usize,
+    trim_padding: bool,
+    /// Character set of the text returned by the data source. `None` for UTF-8.
+    source_charset: Option<&'static Encoding>,
+    invalid_utf8: InvalidUtf8,
+    ignore_indicators: bool,
 }
 
 impl Utf8 {
+    /// See [`text_strategy`] for a description of the arguments.
+    pub fn new(
+        repetition: Repetition,
+        length: usize,
+        trim_padding: bool,
+        source_charset: Option<&'static Encoding>,
+        invalid_utf8: InvalidUtf8,
+        ignore_indicators: bool,
+    ) -> Self {
+        Self {
+            repetition,
+            length,
+            trim_padding,
+            source_charset,
+            invalid_utf8,
+            ignore_indicators,
+        }
+    }
+
+    /// UTF-8 text which is written as it is returned by the data source.
     pub fn with_bytes_length(repetition: Repetition, length: usize) -> Self {
-        Self { repetition, length }
+        Self::new(repetition, length, false, None, InvalidUtf8::Replace, false)
     }
 }
 
 impl ColumnStrategy for Utf8 {
     fn parquet_type(&self, name: &str) -> Type {
+        let (converted_type, repetition) = match self.invalid_utf8 {
+            InvalidUtf8::Binary => (ConvertedType::NONE, self.repetition),
+            // Invalid values are replaced with NULL, even if the column is not nullable.
+            InvalidUtf8::Null => (ConvertedType::UTF8, Repetition::OPTIONAL),
+            InvalidUtf8::Error | InvalidUtf8::Replace => (ConvertedType::UTF8, self.repetition),
+        };
         Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
-            .with_converted_type(ConvertedType::UTF8)
-            .with_repetition(self.repetition)
+            .with_converted_type(converted_type)
+            .with_repetition(repetition)
             .build()
             .unwrap()
     }
@@ -124,38 +189,277 @@ impl ColumnStrategy for Utf8 {
         column_writer: &mut ColumnWriter,
         column_view: AnySlice,
     ) -> Result<(), Error> {
-        write_to_utf8(parquet_buffer, column_writer, column_view)
+        let cw = get_typed_column_writer_mut::<ByteArrayType>(column_writer);
+        let view = column_view.as_text_view().unwrap();
+        let is_optional = self.repetition == Repetition::OPTIONAL;
+        let row_indices = parquet_buffer.row_indices();
+
+        parquet_buffer.write_optional_falliable(
+            cw,
+            text_values(view, self.ignore_indicators, is_optional)
+                .enumerate()
+                .map(|(row_index, item)| {
+                    let Some(bytes) = item else {
+                        return Ok(None);
+                    };
+                    let bytes = if self.trim_padding {
+                        trim_trailing_blanks(bytes, b' ')
+                    } else {
+                        bytes
+                    };
+                    narrow_text_to_byte_array(bytes, self.source_charset, self.invalid_utf8)
+                        .with_context(|| {
+                            format!("Row {} of the result set.", row_indices.get(row_index) + 1)
+                        })
+                }),
+        )?;
+
+        Ok(())
     }
 }
 
-fn write_to_utf8(
-    pb: &mut ParquetBuffer,
-    column_writer: &mut ColumnWriter,
-    column_reader: AnySlice,
-) -> Result<(), Error> {
-    let cw = get_typed_column_writer_mut::<ByteArrayType>(column_writer);
-    let view = column_reader.as_text_view().unwrap();
+/// Values of a text column. If `ignore_indicators` is `true`, the length of each value is
+/// determined by its terminating zero instead of its indicator. This is a workaround for drivers
+/// which return memory garbage instead of indicators (e.g. IBM DB2 on Linux). Since NULL can then
+/// not be told apart from an empty string, empty values of nullable columns are NULL.
+fn text_values<'a, C>(
+    view: TextColumnView<'a, C>,
+    ignore_indicators: bool,
+    is_optional: bool,
+) -> impl Iterator<Item = Option<&'a [C]>> + 'a
+where
+    C: Copy + Default + PartialEq,
+{
+    (0..view.len()).map(move |row_index| {
+        text_value(view, row_index, ignore_indicators)
+            .filter(|value| !(ignore_indicators && is_optional && value.is_empty()))
+    })
+}
+
+/// Value of a text column in the row with the specified index. If `ignore_indicators` is `true`,
+/// its length is determined by the terminating zero and it is never NULL. See [`text_values`].
+pub fn text_value<C>(
+    view: TextColumnView<'_, C>,
+    row_index: usize,
+    ignore_indicators: bool,
+) -> Option<&[C]>
+where
+    C: Copy + Default + PartialEq,
+{
+    if !ignore_indicators {
+        return view.get(row_index);
+    }
+    let element_len = view.max_len() + 1;
+    let element = &view.raw_value_buffer()[row_index * element_len..(row_index + 1) * element_len];
+    let length = element
+        .iter()
+        .position(|&c| c == C::default())
+        .unwrap_or(view.max_len());
+    Some(&element[..length])
+}
 
-    pb.write_optional(
-        cw,
-        view.iter().map(|item| item.map(utf8_bytes_to_byte_array)),
-    )?;
+/// Transcodes `bytes` from `source_charset` into a UTF-8 `ByteArray`. If no character set is
+/// specified, `bytes` are expected to be UTF-8 already. Invalid text is handled according to
+/// `invalid_utf8`.
+fn narrow_text_to_byte_array(
+    bytes: &[u8],
+    source_charset: Option<&'static Encoding>,
+    invalid_utf8: InvalidUtf8,
+) -> Result<Option<ByteArray>, Error> {
+    let charset = source_charset.unwrap_or(UTF_8);
+    if let Some(text) = charset.decode_without_bom_handling_and_without_replacement(bytes) {
+        return Ok(Some(text.into_owned().into_bytes().into()));
+    }
+    let charset_name = charset.name();
+    let replaced = charset.decode_without_bom_handling(bytes).0;
+    match invalid_utf8 {
+        InvalidUtf8::Error => bail!(
+            "Text is not valid {charset_name}. Try specifying the character set of the data source \
+            using `--source-charset`, specifying `--encoding Utf16` or choosing another policy \
+            using `--invalid-utf8`. Value: {replaced}"
+        ),
+        InvalidUtf8::Replace => {
+            warn!(
+                "Text is not valid {charset_name}. Try to execute odbc2parquet in a shell with \
+                UTF-8 locale, or try specifying `--encoding Utf16` or `--source-charset` on the \
+                command line. Value: {replaced}"
+            );
+            Ok(Some(replaced.into_owned().into_bytes().into()))
+        }
+        InvalidUtf8::Null => {
+            warn!("Text is not valid {charset_name}. Writing NULL instead. Value: {replaced}");
+            Ok(None)
+        }
+        InvalidUtf8::Binary => Ok(Some(bytes.to_vec().into())),
+    }
+}
 
-    Ok(())
+/// Strips trailing `blank` characters from `chars`.
+fn trim_trailing_blanks<C: Copy + PartialEq>(chars: &[C], blank: C) -> &[C] {
+    let end = chars
+        .iter()
+        .rposition(|&c| c != blank)
+        .map_or(0, |last| last + 1);
+    &chars[..end]
 }
 
-fn utf8_bytes_to_byte_array(bytes: &[u8]) -> ByteArray {
-    // Allocate string into a ByteArray and make sure it is all UTF-8 characters
-    let utf8_str = String::from_utf8_lossy(bytes);
-    // We need to allocate the string anyway to create a ByteArray (yikes!), yet if it already
-    // happened after the to_string_lossy method, it implies we had to use a replacement
-    // character!
-    if matches!(utf8_str, Cow::Owned(_)) {
-        warn!(
-            "Non UTF-8 characters found in string. Try to execute odbc2parquet in a shell with \
-            UTF-8 locale or try specifying `--encoding Utf16` on the command line. Value: {}",
-            utf8_str
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use encoding_rs::Encoding;
+    use odbc_api::{
+        buffers::{AnyBuffer, ColumnarAnyBuffer, TextColumn},
+        RowSetBuffer,
+    };
+    use parquet::{
+        basic::Repetition,
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader, writer::SerializedFileWriter,
+        },
+        record::Field,
+        schema::types::Type,
+    };
+
+    use crate::{enum_args::InvalidUtf8, parquet_buffer::ParquetBuffer};
+
+    use super::{narrow_text_to_byte_array, text_strategy, trim_trailing_blanks};
+
+    /// Writes the column using a text strategy ignoring indicators and reads the values back.
+    fn write_ignoring_indicators(
+        column: AnyBuffer,
+        num_rows: usize,
+        max_str_len: usize,
+    ) -> Vec<Option<String>> {
+        let use_utf16 = matches!(column, AnyBuffer::WText(_));
+        let strategy = text_strategy(
+            use_utf16,
+            Repetition::OPTIONAL,
+            max_str_len,
+            false,
+            None,
+            InvalidUtf8::Error,
+            true,
+        );
+        let mut buffer = ColumnarAnyBuffer::new(vec![(1, column)]);
+        *buffer.mut_num_fetch_rows() = num_rows;
+
+        let schema = Type::group_type_builder("schema")
+            .with_fields(vec![Arc::new(strategy.parquet_type("a"))])
+            .build()
+            .unwrap();
+        let mut file = SerializedFileWriter::new(
+            Vec::new(),
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+        let mut row_group = file.next_row_group().unwrap();
+        let mut column_writer = row_group.next_column().unwrap().unwrap();
+        let mut parquet_buffer = ParquetBuffer::new(num_rows);
+        parquet_buffer.set_num_rows_fetched(num_rows);
+        strategy
+            .copy_odbc_to_parquet(
+                &mut parquet_buffer,
+                column_writer.untyped(),
+                buffer.column(0),
+            )
+            .unwrap();
+        column_writer.close().unwrap();
+        row_group.close().unwrap();
+        let bytes = Bytes::from(file.into_inner().unwrap());
+
+        SerializedFileReader::new(bytes)
+            .unwrap()
+            .get_row_iter(None)
+            .unwrap()
+            .map(
+                |row| match row.unwrap().get_column_iter().next().unwrap().1 {
+                    Field::Str(text) => Some(text.clone()),
+                    Field::Null => None,
+                    other => panic!("Unexpected field {other:?}"),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn rely_on_terminating_zeroes_instead_of_garbage_indicators() {
+        let max_str_len = 5;
+        let mut narrow = TextColumn::new(3, max_str_len);
+        let mut wide = TextColumn::new(3, max_str_len);
+        // Mock a driver reporting the maximum length as indicator for every value, followed by
+        // garbage behind the terminating zero.
+        for (index, value) in ["ab\0xy", "hello", "\0zzzz"].iter().enumerate() {
+            narrow
+                .set_mut(index, max_str_len)
+                .copy_from_slice(value.as_bytes());
+            let value: Vec<u16> = value.encode_utf16().collect();
+            wide.set_mut(index, max_str_len).copy_from_slice(&value);
+        }
+        let expected = vec![Some("ab".to_owned()), Some("hello".to_owned()), None];
+
+        assert_eq!(
+            expected,
+            write_ignoring_indicators(AnyBuffer::Text(narrow), 3, max_str_len)
+        );
+        assert_eq!(
+            expected,
+            write_ignoring_indicators(AnyBuffer::WText(wide), 3, max_str_len)
         );
     }
-    utf8_str.into_owned().into_bytes().into()
+
+    #[test]
+    fn trim_char_padding() {
+        assert_eq!(b"ab", trim_trailing_blanks(b"ab   ", b' '));
+        assert_eq!(b" a b", trim_trailing_blanks(b" a b", b' '));
+        assert_eq!(b"", trim_trailing_blanks(b"   ", b' '));
+        assert_eq!(&[0x61u16], trim_trailing_blanks(&[0x61u16, 0x20], 0x20));
+    }
+
+    #[test]
+    fn invalid_utf8_policies() {
+        // "Größe" encoded as Latin-1
+        let latin1 = b"Gr\xf6\xdfe";
+
+        let to_bytes = |policy| {
+            narrow_text_to_byte_array(latin1, None, policy)
+                .map(|value| value.map(|byte_array| byte_array.data().to_vec()))
+        };
+
+        assert!(to_bytes(InvalidUtf8::Error).is_err());
+        assert_eq!(
+            Some("Gr\u{FFFD}\u{FFFD}e".as_bytes().to_vec()),
+            to_bytes(InvalidUtf8::Replace).unwrap()
+        );
+        assert_eq!(None, to_bytes(InvalidUtf8::Null).unwrap());
+        assert_eq!(
+            Some(latin1.to_vec()),
+            to_bytes(InvalidUtf8::Binary).unwrap()
+        );
+        // Valid UTF-8 is not affected by the policy
+        assert_eq!(
+            Some("Größe".as_bytes().to_vec()),
+            narrow_text_to_byte_array("Größe".as_bytes(), None, InvalidUtf8::Error)
+                .unwrap()
+                .map(|byte_array| byte_array.data().to_vec())
+        );
+    }
+
+    #[test]
+    fn transcode_from_source_charset() {
+        let to_text = |bytes: &[u8], label: &str| {
+            let charset = Encoding::for_label(label.as_bytes());
+            narrow_text_to_byte_array(bytes, charset, InvalidUtf8::Error)
+                .map(|value| value.unwrap().as_utf8().unwrap().to_owned())
+        };
+
+        assert_eq!("Größe", to_text(b"Gr\xf6\xdfe", "latin1").unwrap());
+        assert_eq!("5€", to_text(b"5\x80", "windows-1252").unwrap());
+        assert_eq!("日本", to_text(b"\x93\xfa\x96\x7b", "shift_jis").unwrap());
+        // Truncated multi byte character
+        assert!(to_text(b"\x93", "shift_jis").is_err());
+    }
 }
@@ -1,41 +1,43 @@
 use anyhow::Error;
+use chrono::{DateTime, NaiveDateTime, Offset, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
 use odbc_api::{
     buffers::{AnySlice, BufferDesc},
     sys::Timestamp,
 };
-use parquet::{
-    basic::{LogicalType, Repetition},
-    column::writer::ColumnWriter,
-    data_type::{DataType, Int64Type},
-    schema::types::Type,
-};
+use parquet::{basic::Repetition, column::writer::ColumnWriter, schema::types::Type};
 
 use crate::parquet_buffer::ParquetBuffer;
 
-use super::{column_strategy::ColumnStrategy, timestamp_precision::TimestampPrecision};
+use super::{
+    column_strategy::ColumnStrategy,
+    timestamp_precision::{naive_datetime, TimestampOutput},
+};
 
-pub fn timestamp_without_tz(repetition: Repetition, precision: u8) -> Box<dyn ColumnStrategy> {
+/// * `source_timezone`: Time zone of the wall clock time stored in the column. If specified, the
+///   timestamps are converted to UTC and written with instant semantics.
+pub fn timestamp_without_tz(
+    repetition: Repetition,
+    output: TimestampOutput,
+    source_timezone: Option<Tz>,
+) -> Box<dyn ColumnStrategy> {
     Box::new(TimestampToI64 {
         repetition,
-        precision: TimestampPrecision::new(precision),
+        output,
+        source_timezone,
     })
 }
 
 struct TimestampToI64 {
     repetition: Repetition,
-    precision: TimestampPrecision,
+    output: TimestampOutput,
+    source_timezone: Option<Tz>,
 }
 
 impl ColumnStrategy for TimestampToI64 {
     fn parquet_type(&self, name: &str) -> Type {
-        Type::primitive_type_builder(name, Int64Type::get_physical_type())
-            .with_logical_type(Some(LogicalType::Timestamp {
-                is_adjusted_to_u_t_c: false,
-                unit: self.precision.as_time_unit(),
-            }))
-            .with_repetition(self.repetition)
-            .build()
-            .unwrap()
+        self.output
+            .parquet_type(name, self.repetition, self.source_timezone.is_some())
     }
 
     fn buffer_desc(&self) -> BufferDesc {
@@ -48,19 +50,64 @@ impl ColumnStrategy for TimestampToI64 {
         column_writer: &mut ColumnWriter,
         column_view: AnySlice,
     ) -> Result<(), Error> {
-        write_timestamp_col(parquet_buffer, column_writer, column_view, self.precision)
+        let from = column_view.as_nullable_slice::<Timestamp>().unwrap();
+        let to_datetime = |ts: &Timestamp| match self.source_timezone {
+            // Without time zone, the wall clock time is written as if it were UTC.
+            None => naive_datetime(ts).and_utc(),
+            Some(time_zone) => local_to_utc(naive_datetime(ts), time_zone),
+        };
+        self.output.write(
+            parquet_buffer,
+            column_writer,
+            from.map(|option| Ok(option.map(to_datetime))),
+        )
+    }
+}
+
+/// Interprets `local` as wall clock time in `time_zone`. Wall clock times which occur twice, due to
+/// clocks being turned back at the end of daylight saving time, are mapped to the earlier of the
+/// two instants. Wall clock times which do not exist, due to clocks being turned forward, are
+/// interpreted using the offset in effect before the transition. I.e. they are shifted later by
+/// the length of the gap.
+fn local_to_utc(local: NaiveDateTime, time_zone: Tz) -> DateTime<Utc> {
+    if let Some(earliest) = time_zone.from_local_datetime(&local).earliest() {
+        return earliest.with_timezone(&Utc);
     }
+    // Search backwards for a wall clock time before the gap, to learn its offset.
+    let mut before = local;
+    let offset = loop {
+        before -= TimeDelta::minutes(15);
+        if let Some(earlier) = time_zone.from_local_datetime(&before).latest() {
+            break earlier.offset().fix();
+        }
+    };
+    offset
+        .from_local_datetime(&local)
+        .unwrap()
+        .with_timezone(&Utc)
 }
 
-fn write_timestamp_col(
-    pb: &mut ParquetBuffer,
-    column_writer: &mut ColumnWriter,
-    column_reader: AnySlice,
-    precision: TimestampPrecision,
-) -> Result<(), Error> {
-    let from = column_reader.as_nullable_slice::<Timestamp>().unwrap();
-    let into = Int64Type::get_column_writer_mut(column_writer).unwrap();
-    let from = from.map(|option| option.map(|ts| precision.timestamp_to_i64(ts)).transpose());
-    pb.write_optional_falliable(into, from)?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use chrono_tz::Europe::Berlin;
+
+    use super::local_to_utc;
+
+    fn datetime(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn wall_clock_time_to_utc() {
+        let utc = |text| local_to_utc(datetime(text), Berlin).naive_utc();
+
+        // Winter and summer time
+        assert_eq!(datetime("2024-01-15 11:00:00"), utc("2024-01-15 12:00:00"));
+        assert_eq!(datetime("2024-07-15 10:00:00"), utc("2024-07-15 12:00:00"));
+        // 02:30 does not exist on the 31st of March. It is interpreted as winter time.
+        assert_eq!(datetime("2024-03-31 01:30:00"), utc("2024-03-31 02:30:00"));
+        // 02:30 occurs twice on the 27th of October. The earlier (summer time) instant is chosen.
+        assert_eq!(datetime("2024-10-27 00:30:00"), utc("2024-10-27 02:30:00"));
+    }
 }
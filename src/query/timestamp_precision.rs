@@ -1,7 +1,15 @@
 use anyhow::{anyhow, Error};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike, Utc};
 use odbc_api::sys::Timestamp;
-use parquet::format::{MicroSeconds, MilliSeconds, NanoSeconds, TimeUnit};
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    column::writer::{get_typed_column_writer_mut, ColumnWriter},
+    data_type::{Int64Type, Int96, Int96Type},
+    format::{MicroSeconds, MilliSeconds, NanoSeconds, TimeUnit},
+    schema::types::Type,
+};
+
+use crate::parquet_buffer::ParquetBuffer;
 
 /// Relational types communicate the precision of timestamps in number of fraction digits, while
 /// parquet uses time units (milli, micro, nano). This enumartion stores the the decision which time
@@ -30,30 +38,6 @@ impl TimestampPrecision {
         }
     }
 
-    /// Convert an ODBC timestamp struct into nano, milli or microseconds based on precision.
-    pub fn timestamp_to_i64(self, ts: &Timestamp) -> Result<i64, Error> {
-        let datetime = NaiveDate::from_ymd_opt(ts.year as i32, ts.month as u32, ts.day as u32)
-            .unwrap()
-            .and_hms_nano_opt(
-                ts.hour as u32,
-                ts.minute as u32,
-                ts.second as u32,
-                ts.fraction,
-            )
-            .unwrap();
-
-        let ret = match self {
-            TimestampPrecision::Milliseconds => datetime.and_utc().timestamp_millis(),
-            TimestampPrecision::Microseconds => datetime.and_utc().timestamp_micros(),
-            TimestampPrecision::Nanoseconds => datetime
-                .and_utc()
-                .timestamp_nanos_opt()
-                .ok_or_else(|| nanoseconds_precision_error(&datetime))?,
-        };
-
-        Ok(ret)
-    }
-
     pub fn datetime_to_i64(self, datetime: &DateTime<Utc>) -> Result<i64, Error> {
         let ret = match self {
             TimestampPrecision::Milliseconds => datetime.timestamp_millis(),
@@ -66,6 +50,89 @@ impl TimestampPrecision {
     }
 }
 
+/// Physical representation of timestamps in parquet.
+#[derive(Clone, Copy)]
+pub enum TimestampOutput {
+    /// Number of time units since epoch, annotated with the logical type timestamp.
+    Int64(TimestampPrecision),
+    /// Legacy representation used by Apache Hive and Impala. Nanoseconds within the day, followed
+    /// by the julian day. Always in nanoseconds precision.
+    Int96,
+}
+
+impl TimestampOutput {
+    /// * `is_adjusted_to_utc`: Whether the timestamps are instants, or local wall clock time. Not
+    ///   captured by `Int96`, which has no logical type.
+    pub fn parquet_type(
+        self,
+        name: &str,
+        repetition: Repetition,
+        is_adjusted_to_utc: bool,
+    ) -> Type {
+        let builder = match self {
+            TimestampOutput::Int64(precision) => {
+                Type::primitive_type_builder(name, PhysicalType::INT64).with_logical_type(Some(
+                    LogicalType::Timestamp {
+                        is_adjusted_to_u_t_c: is_adjusted_to_utc,
+                        unit: precision.as_time_unit(),
+                    },
+                ))
+            }
+            TimestampOutput::Int96 => Type::primitive_type_builder(name, PhysicalType::INT96),
+        };
+        builder.with_repetition(repetition).build().unwrap()
+    }
+
+    /// Writes the timestamps into a column of the type returned by [`Self::parquet_type`].
+    pub fn write(
+        self,
+        pb: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        timestamps: impl Iterator<Item = Result<Option<DateTime<Utc>>, Error>>,
+    ) -> Result<(), Error> {
+        match self {
+            TimestampOutput::Int64(precision) => {
+                let cw = get_typed_column_writer_mut::<Int64Type>(column_writer);
+                pb.write_optional_falliable(
+                    cw,
+                    timestamps.map(|ts| ts?.map(|ts| precision.datetime_to_i64(&ts)).transpose()),
+                )
+            }
+            TimestampOutput::Int96 => {
+                let cw = get_typed_column_writer_mut::<Int96Type>(column_writer);
+                pb.write_optional_falliable(
+                    cw,
+                    timestamps.map(|ts| ts?.map(|ts| datetime_to_int96(&ts)).transpose()),
+                )
+            }
+        }
+    }
+}
+
+fn datetime_to_int96(datetime: &DateTime<Utc>) -> Result<Int96, Error> {
+    // Julian day of 0001-01-01 is 1721426 and `num_days_from_ce` is 1 for that day.
+    let julian_day = u32::try_from(datetime.num_days_from_ce() as i64 + 1_721_425)
+        .map_err(|_| anyhow!("Invalid timestamp: {datetime}. Can not be represented as INT96."))?;
+    let nanos = datetime.time().num_seconds_from_midnight() as u64 * 1_000_000_000
+        + datetime.time().nanosecond() as u64;
+    let mut int96 = Int96::new();
+    int96.set_data(nanos as u32, (nanos >> 32) as u32, julian_day);
+    Ok(int96)
+}
+
+/// Convert an ODBC timestamp struct into a chrono datetime without time zone.
+pub fn naive_datetime(ts: &Timestamp) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(ts.year as i32, ts.month as u32, ts.day as u32)
+        .unwrap()
+        .and_hms_nano_opt(
+            ts.hour as u32,
+            ts.minute as u32,
+            ts.second as u32,
+            ts.fraction,
+        )
+        .unwrap()
+}
+
 fn nanoseconds_precision_error(value: &NaiveDateTime) -> Error {
     // The valid time ranges for parquet and datetime align. Normally this could be considered
     // incidential and should not be relied upon. However both interfaces are shaped by what is
@@ -74,8 +141,28 @@ fn nanoseconds_precision_error(value: &NaiveDateTime) -> Error {
     anyhow!(
         "Invalid timestamp: {}. The valid range for timestamps with nano seconds precision is \
         between 1677-09-21 00:12:44 and 2262-04-11 23:47:16.854775807. Other timestamps can not be \
-        represented in parquet. To mitigate this you could downcast the precision in the query or \
-        convert the column to text.",
+        represented in parquet. To mitigate this you could downcast the precision in the query, \
+        use `--timestamp-unit micros`, write `--int96-timestamps` or convert the column to text.",
         value
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::datetime_to_int96;
+
+    #[test]
+    fn int96_timestamp() {
+        let datetime = NaiveDate::from_ymd_opt(2022, 9, 7)
+            .unwrap()
+            .and_hms_nano_opt(16, 4, 12, 123_456_789)
+            .unwrap()
+            .and_utc();
+
+        let int96 = datetime_to_int96(&datetime).unwrap();
+
+        assert_eq!(datetime.timestamp_nanos_opt().unwrap(), int96.to_nanos());
+    }
+}
@@ -1,63 +1,47 @@
-use anyhow::Error;
+use anyhow::{Context, Error};
 use chrono::{DateTime, Utc};
 use odbc_api::buffers::{AnySlice, BufferDesc};
-use parquet::{
-    basic::{LogicalType, Repetition, Type as PhysicalType},
-    column::writer::{get_typed_column_writer_mut, ColumnWriter},
-    data_type::Int64Type,
-    schema::types::Type,
-};
+use parquet::{basic::Repetition, column::writer::ColumnWriter, schema::types::Type};
 
 use crate::parquet_buffer::ParquetBuffer;
 
-use super::{column_strategy::ColumnStrategy, timestamp_precision::TimestampPrecision};
+use super::{column_strategy::ColumnStrategy, timestamp_precision::TimestampOutput};
 
-pub fn timestamp_tz(precision: u8, repetition: Repetition) -> Result<Box<TimestampTz>, Error> {
-    Ok(Box::new(TimestampTz::with_bytes_length(
-        repetition, precision,
-    )))
+/// Longest text representation we expect for a timestamp with time zone, e.g.
+/// `2022-09-07 16:04:12.123456789 +02:00`.
+///
+/// We do not derive the length from the precision reported by the driver. PostgreSQL prints as
+/// many fractional digits as the value has, and a buffer sized after the reported precision may
+/// truncate the fraction.
+const MAX_STR_LEN: usize = 36;
+
+pub fn timestamp_tz(
+    output: TimestampOutput,
+    repetition: Repetition,
+) -> Result<Box<TimestampTz>, Error> {
+    Ok(Box::new(TimestampTz::new(repetition, output)))
 }
 
 pub struct TimestampTz {
     repetition: Repetition,
-    // We store digit precision, rather than TimestampPrecision, in order to be able to adequatly
-    // calculate ODBC text buffer length.
-    precision: u8,
+    output: TimestampOutput,
 }
 
 impl TimestampTz {
-    pub fn with_bytes_length(repetition: Repetition, precision: u8) -> Self {
-        Self {
-            repetition,
-            precision,
-        }
+    pub fn new(repetition: Repetition, output: TimestampOutput) -> Self {
+        Self { repetition, output }
     }
 }
 
 impl ColumnStrategy for TimestampTz {
     fn parquet_type(&self, name: &str) -> Type {
-        Type::primitive_type_builder(name, PhysicalType::INT64)
-            .with_logical_type(Some(LogicalType::Timestamp {
-                is_adjusted_to_u_t_c: true,
-                unit: TimestampPrecision::new(self.precision).as_time_unit(),
-            }))
-            .with_repetition(self.repetition)
-            .build()
-            .unwrap()
+        self.output.parquet_type(name, self.repetition, true)
     }
 
     fn buffer_desc(&self) -> BufferDesc {
-        // Text representation looks like e.g. 2022-09-07 16:04:12 +02:00
-        // Text representation looks like e.g. 2022-09-07 16:04:12.123 +02:00
-
-        let max_str_len = 26
-            + if self.precision == 0 {
-                0
-            } else {
-                // Radix character `.` and precision.
-                1 + self.precision as usize
-            };
-        BufferDesc::Text { max_str_len }
+        BufferDesc::Text {
+            max_str_len: MAX_STR_LEN,
+        }
     }
 
     fn copy_odbc_to_parquet(
@@ -66,37 +50,65 @@ impl ColumnStrategy for TimestampTz {
         column_writer: &mut ColumnWriter,
         column_view: AnySlice,
     ) -> Result<(), Error> {
-        write_timestamp_tz(parquet_buffer, column_writer, column_view, self.precision)
+        let view = column_view.as_text_view().expect(
+            "Invalid Column view type. This is not supposed to happen. Please open a Bug at \
+            https://github.com/pacman82/odbc2parquet/issues.",
+        );
+        self.output.write(
+            parquet_buffer,
+            column_writer,
+            view.iter().map(|item| item.map(to_utc).transpose()),
+        )
     }
 }
 
-fn write_timestamp_tz(
-    pb: &mut ParquetBuffer,
-    column_writer: &mut ColumnWriter,
-    column_reader: AnySlice,
-    precision: u8,
-) -> Result<(), Error> {
-    let view = column_reader.as_text_view().expect(
-        "Invalid Column view type. This is not supposed to happen. Please open a Bug at \
-        https://github.com/pacman82/odbc2parquet/issues.",
-    );
-    let cw = get_typed_column_writer_mut::<Int64Type>(column_writer);
-    pb.write_optional_falliable(
-        cw,
-        view.iter()
-            .map(|item| item.map(|text| to_utc_epoch(text, precision)).transpose()),
-    )?;
-    Ok(())
+fn to_utc(bytes: &[u8]) -> Result<DateTime<Utc>, Error> {
+    // Text representation looks like e.g. 2022-09-07 16:04:12 +02:00 for Microsoft SQL Server and
+    // Oracle, or 2022-09-07 16:04:12.123+02 for PostgreSQL. Oracle keeps the radix character for
+    // columns without fractional seconds, e.g. 2022-09-07 16:04:12. +02:00.
+    let utf8 = String::from_utf8_lossy(bytes);
+    let utf8 = utf8.replacen(". ", " ", 1);
+
+    // Parse to datetime. The space before the offset is optional and the offset may omit minutes.
+    let date_time = DateTime::parse_from_str(&utf8, "%Y-%m-%d %H:%M:%S%.f %#z")
+        .with_context(|| format!("Invalid timestamp with time zone: '{utf8}'"))?;
+    Ok(date_time.with_timezone(&Utc))
 }
 
-fn to_utc_epoch(bytes: &[u8], precision: u8) -> Result<i64, Error> {
-    // Text representation looks like e.g. 2022-09-07 16:04:12 +02:00
-    let utf8 = String::from_utf8_lossy(bytes);
+#[cfg(test)]
+mod tests {
+    use super::{to_utc, MAX_STR_LEN};
+
+    #[test]
+    fn parse_timestamps_with_time_zone() {
+        let millis = |text: &str| to_utc(text.as_bytes()).unwrap().timestamp_millis();
 
-    // Parse to datetime
-    let date_time = DateTime::parse_from_str(&utf8, "%Y-%m-%d %H:%M:%S%.9f %:z")?;
-    // let utc = date_time.naive_utc();
-    let utc = date_time.with_timezone(&Utc);
-    let integer = TimestampPrecision::new(precision).datetime_to_i64(&utc)?;
-    Ok(integer)
+        // Microsoft SQL Server and Oracle
+        assert_eq!(1662559452000, millis("2022-09-07 16:04:12 +02:00"));
+        assert_eq!(1662559452123, millis("2022-09-07 16:04:12.123000 +02:00"));
+        assert_eq!(1662559452000, millis("2022-09-07 16:04:12. +02:00"));
+        // PostgreSQL
+        assert_eq!(1662559452123, millis("2022-09-07 16:04:12.123+02"));
+        assert_eq!(1662548652000, millis("2022-09-07 16:04:12+05:00"));
+        assert!(to_utc(b"2022-09-07 16:04:12").is_err());
+    }
+
+    #[test]
+    fn keep_fraction_of_longest_text_representation() {
+        let longest = "2022-09-07 16:04:12.123456789 +02:00";
+        assert_eq!(MAX_STR_LEN, longest.len());
+        assert_eq!(
+            1662559452123456789,
+            to_utc(longest.as_bytes())
+                .unwrap()
+                .timestamp_nanos_opt()
+                .unwrap()
+        );
+        // PostgreSQL prints microseconds without a space before the offset
+        let postgres = "2022-09-07 16:04:12.123456+02";
+        assert_eq!(
+            1662559452123456,
+            to_utc(postgres.as_bytes()).unwrap().timestamp_micros()
+        );
+    }
 }
@@ -0,0 +1,190 @@
+//! All-or-nothing output. Files are staged until the entire result set has been written, so a
+//! failed run does not leave a partial dataset behind, which looks valid.
+
+use std::{
+    fs::create_dir_all,
+    io::{self, Write},
+    mem::take,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Error};
+use log::info;
+use tempfile::{Builder, TempDir};
+
+use super::current_file::{LocalFile, OutputFile, Storage};
+
+/// Name of the empty file marking a complete output, following the convention of Hadoop and Spark.
+const SUCCESS_MARKER: &str = "_SUCCESS";
+
+/// Stages every output file until [`Self::commit`] is called. Local files are written into a
+/// hidden staging directory and moved into place once committed. Uploads to S3 are not completed
+/// until then, which keeps them invisible. Dropping the transaction without committing it discards
+/// all staged files.
+pub struct Transaction {
+    /// Where the files are persisted once the transaction is committed.
+    storage: Storage,
+    /// Directory containing all output files and the success marker. A key prefix in case of S3.
+    root: PathBuf,
+    /// Local files are written into this directory first. `None` for S3.
+    staging_dir: Option<TempDir>,
+    /// Files which are completely written, but not yet persisted.
+    staged: Mutex<Vec<Box<dyn OutputFile>>>,
+    /// Write an empty `_SUCCESS` file into `root` after committing the transaction.
+    success_marker: bool,
+}
+
+impl Transaction {
+    /// * `storage`: Either local file system or S3.
+    /// * `root`: Directory all the files of the output are placed in.
+    /// * `success_marker`: Write a `_SUCCESS` file into `root` once committed.
+    pub fn begin(storage: Storage, root: PathBuf, success_marker: bool) -> Result<Self, Error> {
+        let staging_dir = match storage {
+            Storage::FileSystem => {
+                // Within the output directory, so moving files into place is a cheap rename.
+                let dir = if root.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    root.as_path()
+                };
+                create_dir_all(dir).with_context(|| {
+                    format!(
+                        "Could not create output directory '{}'",
+                        dir.to_string_lossy()
+                    )
+                })?;
+                let staging_dir = Builder::new()
+                    .prefix(".odbc2parquet-staging-")
+                    .tempdir_in(dir)
+                    .context("Could not create staging directory")?;
+                info!(
+                    "Staging output files in '{}'.",
+                    staging_dir.path().to_string_lossy()
+                );
+                Some(staging_dir)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            storage,
+            root,
+            staging_dir,
+            staged: Mutex::new(Vec::new()),
+            success_marker,
+        })
+    }
+
+    pub fn create(self: &Arc<Self>, path: &Path) -> Result<Box<dyn OutputFile>, Error> {
+        let output: Box<dyn OutputFile> = match &self.staging_dir {
+            Some(staging_dir) => {
+                let relative = path.strip_prefix(&self.root).with_context(|| {
+                    format!(
+                        "Output file '{}' is not located in '{}'",
+                        path.to_string_lossy(),
+                        self.root.to_string_lossy()
+                    )
+                })?;
+                let staged_path = staging_dir.path().join(relative);
+                if let Some(dir) = staged_path.parent() {
+                    create_dir_all(dir)?;
+                }
+                Box::new(LocalFile::create(&staged_path, Some(path.to_owned()))?)
+            }
+            None => self.storage.create(path)?,
+        };
+        Ok(Box::new(Staged {
+            output,
+            transaction: self.clone(),
+        }))
+    }
+
+    /// Location of the file, once the transaction is committed.
+    pub fn location(&self, path: &Path) -> String {
+        self.storage.location(path)
+    }
+
+    /// Persists all staged files and writes the success marker, if requested. Should persisting an
+    /// individual file fail, the files persisted before it remain in place.
+    pub fn commit(&self) -> Result<(), Error> {
+        let staged = take(&mut *self.staged.lock().unwrap());
+        info!("Committing {} output files.", staged.len());
+        for output in staged {
+            output.persist()?;
+        }
+        if self.success_marker {
+            let path = self.root.join(SUCCESS_MARKER);
+            self.storage.create(&path)?.persist()?;
+            info!("Written success marker '{}'.", self.location(&path));
+        }
+        Ok(())
+    }
+}
+
+/// An output file, which is handed to the transaction instead of being persisted directly.
+struct Staged {
+    output: Box<dyn OutputFile>,
+    transaction: Arc<Transaction>,
+}
+
+impl Write for Staged {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl OutputFile for Staged {
+    fn persist(self: Box<Self>) -> Result<(), Error> {
+        let Staged {
+            mut output,
+            transaction,
+        } = *self;
+        output.stage()?;
+        transaction.staged.lock().unwrap().push(output);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Arc};
+
+    use tempfile::tempdir;
+
+    use crate::query::current_file::Storage;
+
+    use super::Transaction;
+
+    #[test]
+    fn files_only_appear_after_commit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("country=DE").join("part-01.parquet");
+        let begin = || {
+            Arc::new(Transaction::begin(Storage::FileSystem, dir.path().to_owned(), true).unwrap())
+        };
+
+        // Dropping the transaction discards the staged file.
+        let transaction = begin();
+        let mut output = transaction.create(&path).unwrap();
+        output.write_all(b"content").unwrap();
+        output.persist().unwrap();
+        drop(transaction);
+        assert_eq!(0, dir.path().read_dir().unwrap().count());
+
+        let transaction = begin();
+        let mut output = transaction.create(&path).unwrap();
+        output.write_all(b"content").unwrap();
+        output.persist().unwrap();
+        assert!(!path.exists());
+        transaction.commit().unwrap();
+        drop(transaction);
+        assert_eq!("content", std::fs::read_to_string(&path).unwrap());
+        assert!(dir.path().join("_SUCCESS").exists());
+        // Only the partition directory and the marker remain. The staging directory is gone.
+        assert_eq!(2, dir.path().read_dir().unwrap().count());
+    }
+}
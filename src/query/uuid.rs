@@ -0,0 +1,117 @@
+use anyhow::{bail, Error};
+use odbc_api::buffers::{AnySlice, BufferDesc};
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    column::writer::{get_typed_column_writer_mut, ColumnWriter},
+    data_type::{ByteArray, FixedLenByteArray, FixedLenByteArrayType},
+    schema::types::Type,
+};
+
+use crate::parquet_buffer::ParquetBuffer;
+
+use super::column_strategy::ColumnStrategy;
+
+/// Size of a GUID in bytes.
+const GUID_LENGTH: usize = 16;
+
+/// Strategy for GUID columns, e.g. `uniqueidentifier` on Microsoft SQL Server. Fetches the values
+/// as binary and writes them as parquet UUIDs.
+pub struct Uuid {
+    repetition: Repetition,
+}
+
+impl Uuid {
+    pub fn new(repetition: Repetition) -> Self {
+        Self { repetition }
+    }
+}
+
+impl ColumnStrategy for Uuid {
+    fn parquet_type(&self, name: &str) -> Type {
+        Type::primitive_type_builder(name, PhysicalType::FIXED_LEN_BYTE_ARRAY)
+            .with_repetition(self.repetition)
+            .with_length(GUID_LENGTH as i32)
+            .with_logical_type(Some(LogicalType::Uuid))
+            .build()
+            .unwrap()
+    }
+
+    fn buffer_desc(&self) -> BufferDesc {
+        BufferDesc::Binary {
+            length: GUID_LENGTH,
+        }
+    }
+
+    fn copy_odbc_to_parquet(
+        &self,
+        parquet_buffer: &mut ParquetBuffer,
+        column_writer: &mut ColumnWriter,
+        column_view: AnySlice,
+    ) -> Result<(), Error> {
+        let cw = get_typed_column_writer_mut::<FixedLenByteArrayType>(column_writer);
+        let view = column_view.as_bin_view().unwrap();
+        parquet_buffer.write_optional_falliable(
+            cw,
+            view.iter().map(|maybe_guid| {
+                maybe_guid
+                    .map(|guid| {
+                        let uuid = uuid_from_guid(guid)?;
+                        Ok(FixedLenByteArray::from(ByteArray::from(uuid.to_vec())))
+                    })
+                    .transpose()
+            }),
+        )
+    }
+}
+
+/// ODBC transfers GUIDs in the memory layout of the `SQLGUID` structure, whose first three fields
+/// are integers in native byte order. Parquet expects UUIDs in big endian byte order, i.e. the
+/// order of their canonical text representation.
+fn uuid_from_guid(guid: &[u8]) -> Result<[u8; GUID_LENGTH], Error> {
+    if guid.len() != GUID_LENGTH {
+        bail!(
+            "ODBC driver returned a GUID with a length of {} bytes. Expected {GUID_LENGTH} bytes. \
+            Try the `--uuid-as-text` flag.",
+            guid.len()
+        )
+    }
+    let mut uuid = [0; GUID_LENGTH];
+    let data1 = u32::from_ne_bytes(guid[0..4].try_into().unwrap());
+    let data2 = u16::from_ne_bytes(guid[4..6].try_into().unwrap());
+    let data3 = u16::from_ne_bytes(guid[6..8].try_into().unwrap());
+    uuid[0..4].copy_from_slice(&data1.to_be_bytes());
+    uuid[4..6].copy_from_slice(&data2.to_be_bytes());
+    uuid[6..8].copy_from_slice(&data3.to_be_bytes());
+    uuid[8..].copy_from_slice(&guid[8..]);
+    Ok(uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::uuid_from_guid;
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn guid_to_uuid_byte_order() {
+        // SQLGUID of `6F9619FF-8B86-D011-B42D-00C04FC964FF` on a little endian machine
+        let guid = [
+            0xFF, 0x19, 0x96, 0x6F, 0x86, 0x8B, 0x11, 0xD0, 0xB4, 0x2D, 0x00, 0xC0, 0x4F, 0xC9,
+            0x64, 0xFF,
+        ];
+
+        let uuid = uuid_from_guid(&guid).unwrap();
+
+        assert_eq!(
+            [
+                0x6F, 0x96, 0x19, 0xFF, 0x8B, 0x86, 0xD0, 0x11, 0xB4, 0x2D, 0x00, 0xC0, 0x4F, 0xC9,
+                0x64, 0xFF
+            ],
+            uuid
+        );
+    }
+
+    #[test]
+    fn reject_guid_with_invalid_length() {
+        assert!(uuid_from_guid(&[0; 36]).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::Parser;
+use log::{error, info};
+use odbc_api::Environment;
+use serde::Deserialize;
+use toml::Value;
+
+use crate::{open_connection, query::query_with_connection, QueryOpt, RunOpt};
+
+/// Content of the manifest file passed to the `run` subcommand.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Manifest {
+    /// Options applied to every job, unless the job specifies them itself.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    #[serde(rename = "job", default)]
+    jobs: Vec<Job>,
+}
+
+/// A single extraction described in the manifest.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Job {
+    /// Name used to identify the job in the summary.
+    name: String,
+    /// Query text executed against the data source. Mutually exclusive with `query_file`.
+    query: Option<String>,
+    /// Path to a file containing the query text. Mutually exclusive with `query`. Relative paths are
+    /// resolved against the directory containing the manifest.
+    query_file: Option<String>,
+    /// Path to the output parquet file.
+    output: String,
+    /// Positional parameters bound to the placeholders in the query.
+    #[serde(default)]
+    parameters: Vec<String>,
+    /// Overwrites for the options in the top level options table.
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+}
+
+/// Used to parse the options of a job with the same logic as the `query` subcommand. This way all
+/// defaults and validations are shared.
+#[derive(Parser)]
+struct JobArgs {
+    #[clap(flatten)]
+    query_opt: QueryOpt,
+}
+
+/// Options which only make sense once per manifest, since the connection is shared between all
+/// jobs.
+const CONNECTION_OPTIONS: &[&str] = &["connection-string", "dsn", "user", "password", "prompt"];
+
+/// Outcome of executing a single job.
+enum JobOutcome {
+    Succeeded,
+    Failed(Error),
+    Skipped,
+}
+
+/// Execute all jobs listed in the manifest over a single connection and print a summary.
+pub fn run(environment: &Environment, opt: &RunOpt) -> Result<(), Error> {
+    let manifest = read_manifest(&opt.manifest)?;
+    let manifest_dir = opt.manifest.parent().unwrap_or(Path::new(""));
+    // Parse all jobs upfront, so we do not fail halfway due to a typo in the manifest.
+    let jobs = manifest
+        .jobs
+        .iter()
+        .map(|job| {
+            job_query_opt(job, &manifest.options, manifest_dir)
+                .with_context(|| format!("Invalid job '{}' in manifest.", job.name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let odbc_conn = open_connection(environment, &opt.connect_opts)?;
+
+    let mut outcomes = Vec::new();
+    let mut abort = false;
+    for (job, mut query_opt) in manifest.jobs.iter().zip(jobs) {
+        if abort {
+            outcomes.push(JobOutcome::Skipped);
+            continue;
+        }
+        info!("Executing job '{}'.", job.name);
+        // Additional connections, e.g. for fetching partitions in parallel, are opened with the
+        // same options as the shared one.
+        query_opt.connect_opts = opt.connect_opts.clone();
+        match query_with_connection(environment, &odbc_conn, query_opt) {
+            Ok(()) => outcomes.push(JobOutcome::Succeeded),
+            Err(err) => {
+                error!("Job '{}' failed: {:#}", job.name, err);
+                abort = opt.fail_fast;
+                outcomes.push(JobOutcome::Failed(err));
+            }
+        }
+    }
+
+    let mut num_failed = 0;
+    for (job, outcome) in manifest.jobs.iter().zip(&outcomes) {
+        match outcome {
+            JobOutcome::Succeeded => println!("{}: succeeded", job.name),
+            JobOutcome::Failed(err) => {
+                num_failed += 1;
+                println!("{}: failed: {:#}", job.name, err)
+            }
+            JobOutcome::Skipped => println!("{}: skipped", job.name),
+        }
+    }
+
+    if num_failed != 0 {
+        bail!("{} of {} jobs failed.", num_failed, outcomes.len())
+    }
+    Ok(())
+}
+
+/// Reads the manifest at `path`. Files ending in `.yaml` or `.yml` are parsed as YAML, everything
+/// else as TOML.
+fn read_manifest(path: &Path) -> Result<Manifest, Error> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read manifest '{}'", path.to_string_lossy()))?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+    let manifest = if is_yaml {
+        parse_yaml_manifest(&text)
+    } else {
+        parse_toml_manifest(&text)
+    };
+    manifest.with_context(|| format!("Could not parse manifest '{}'", path.to_string_lossy()))
+}
+
+fn parse_toml_manifest(text: &str) -> Result<Manifest, Error> {
+    Ok(toml::from_str(text)?)
+}
+
+fn parse_yaml_manifest(text: &str) -> Result<Manifest, Error> {
+    Ok(serde_yaml::from_str(text)?)
+}
+
+/// Translate a job from the manifest into the same options the `query` subcommand would use.
+/// `manifest_dir` is the directory containing the manifest, relative query files are resolved
+/// against it.
+fn job_query_opt(
+    job: &Job,
+    defaults: &BTreeMap<String, Value>,
+    manifest_dir: &Path,
+) -> Result<QueryOpt, Error> {
+    let query = match (&job.query, &job.query_file) {
+        (Some(query), None) => query.clone(),
+        (None, Some(path)) => {
+            let path = manifest_dir.join(path);
+            fs::read_to_string(&path).with_context(|| {
+                format!("Could not read query file '{}'", path.to_string_lossy())
+            })?
+        }
+        (None, None) => bail!("Either 'query' or 'query-file' must be specified."),
+        (Some(_), Some(_)) => bail!("'query' and 'query-file' must not be specified both."),
+    };
+    if job.output == "-" {
+        bail!("Jobs can not write to standard out.")
+    }
+
+    // Options of the job take precedence over the ones specified for all jobs.
+    let mut options = defaults.clone();
+    options.extend(job.options.clone());
+
+    // First argument would be the binary name
+    let mut args = vec!["odbc2parquet run".to_owned()];
+    args.extend(options_to_args(&options)?);
+    // Everything after the `--` is positional. This way queries starting with a dash, e.g. due to
+    // a leading comment, are not mistaken for options.
+    args.push("--".to_owned());
+    args.push(job.output.clone());
+    args.push(query);
+    args.extend(job.parameters.iter().cloned());
+
+    let JobArgs { query_opt } = JobArgs::try_parse_from(args)?;
+    query_opt.perform_extra_validation()?;
+    Ok(query_opt)
+}
+
+/// Translates a table of options into command line arguments. Booleans are treated as flags and
+/// arrays as options which are specified multiple times.
+fn options_to_args(options: &BTreeMap<String, Value>) -> Result<Vec<String>, Error> {
+    let mut args = Vec::new();
+    for (name, value) in options {
+        if CONNECTION_OPTIONS.contains(&name.as_str()) {
+            bail!(
+                "Option '{name}' can not be specified in the manifest. Pass it to the run \
+                subcommand instead."
+            )
+        }
+        let flag = format!("--{name}");
+        match value {
+            Value::Boolean(true) => args.push(flag),
+            Value::Boolean(false) => (),
+            Value::Array(values) => {
+                for value in values {
+                    args.push(flag.clone());
+                    args.push(option_value_to_arg(name, value)?);
+                }
+            }
+            other => {
+                args.push(flag);
+                args.push(option_value_to_arg(name, other)?);
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn option_value_to_arg(name: &str, value: &Value) -> Result<String, Error> {
+    let arg = match value {
+        Value::String(text) => text.clone(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Float(float) => float.to_string(),
+        _ => return Err(anyhow!("Unsupported value for option '{name}': {value}")),
+    };
+    Ok(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{options_to_args, parse_toml_manifest, parse_yaml_manifest};
+
+    #[test]
+    fn translate_options_to_args() {
+        let options: BTreeMap<String, toml::Value> = toml::from_str(
+            r#"
+            batch-size-row = 100
+            column-compression-default = "snappy"
+            no-empty-file = true
+            prefer-varbinary = false
+            parquet-column-encoding = ["a:plain", "b:rle"]
+            "#,
+        )
+        .unwrap();
+
+        let args = options_to_args(&options).unwrap();
+
+        assert_eq!(
+            vec![
+                "--batch-size-row",
+                "100",
+                "--column-compression-default",
+                "snappy",
+                "--no-empty-file",
+                "--parquet-column-encoding",
+                "a:plain",
+                "--parquet-column-encoding",
+                "b:rle",
+            ],
+            args
+        );
+    }
+
+    #[test]
+    fn yaml_and_toml_manifests_are_equivalent() {
+        let toml = parse_toml_manifest(
+            r#"
+            [options]
+            batch-size-row = 100
+            no-empty-file = true
+
+            [[job]]
+            name = "orders"
+            query-file = "orders.sql"
+            output = "orders.par"
+            parameters = ["2020"]
+            [job.options]
+            parquet-column-encoding = ["a:plain", "b:rle"]
+            "#,
+        )
+        .unwrap();
+        let yaml = parse_yaml_manifest(
+            r#"
+            options:
+              batch-size-row: 100
+              no-empty-file: true
+            job:
+              - name: orders
+                query-file: orders.sql
+                output: orders.par
+                parameters: ["2020"]
+                options:
+                  parquet-column-encoding: ["a:plain", "b:rle"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            options_to_args(&toml.options).unwrap(),
+            options_to_args(&yaml.options).unwrap()
+        );
+        assert_eq!(toml.jobs.len(), yaml.jobs.len());
+        assert_eq!(toml.jobs[0].query_file, yaml.jobs[0].query_file);
+        assert_eq!(toml.jobs[0].parameters, yaml.jobs[0].parameters);
+        assert_eq!(
+            options_to_args(&toml.jobs[0].options).unwrap(),
+            options_to_args(&yaml.jobs[0].options).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_connection_options_in_manifest() {
+        let options: BTreeMap<String, toml::Value> = toml::from_str(r#"dsn = "my_db""#).unwrap();
+
+        assert!(options_to_args(&options).is_err());
+    }
+}
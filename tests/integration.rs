@@ -1,9 +1,10 @@
 use std::{
     fs::File,
     io::{ErrorKind, Write},
-    path::Path,
+    path::{Path, PathBuf},
     str,
     sync::Arc,
+    time::Duration,
 };
 
 use assert_cmd::{assert::Assert, Command};
@@ -15,15 +16,18 @@ use odbc_api::{
     Connection, ConnectionOptions, Cursor, Environment, IntoParameter,
 };
 use parquet::{
+    basic::{Compression, LogicalType},
     column::writer::ColumnWriter,
     data_type::{ByteArray, FixedLenByteArray},
     file::{
         properties::WriterProperties, reader::FileReader, serialized_reader::SerializedFileReader,
         writer::SerializedFileWriter,
     },
+    record::RowAccessor,
     schema::parser::parse_message_type,
 };
 use predicates::{ord::eq, str::contains};
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
 use tempfile::{tempdir, NamedTempFile};
 
 const MSSQL: &str = "Driver={ODBC Driver 17 for SQL Server};\
@@ -38,6 +42,12 @@ const POSTGRES: &str = "Driver={PostgreSQL UNICODE};\
     Uid=test;\
     Pwd=test;";
 
+/// S3 compatible object storage used to test uploading the output.
+const MINIO_ENDPOINT: &str = "http://localhost:9000";
+const MINIO_ACCESS_KEY: &str = "minio";
+const MINIO_SECRET_KEY: &str = "minio-password";
+const MINIO_BUCKET: &str = "odbc2parquet-test";
+
 // Rust by default executes tests in parallel. Yet only one environment is allowed at a time.
 lazy_static! {
     static ref ENV: Environment = {
@@ -987,7 +997,7 @@ fn query_timestamp_with_timezone_postgres() {
     let expected_values = "{a: 2022-09-07 14:04:12 +00:00}\n";
     parquet_read_out(out_str).stdout(eq(expected_values));
 
-    parquet_schema_out(out_str).stdout(contains("OPTIONAL INT64 a (TIMESTAMP(MICROS,false));"));
+    parquet_schema_out(out_str).stdout(contains("OPTIONAL INT64 a (TIMESTAMP(MICROS,true));"));
 }
 
 #[test]
@@ -4085,186 +4095,282 @@ fn query_4097_bits() {
         .success();
 }
 
-/// Writes a parquet file with one row group and one column.
-fn write_values_to_file<T>(
-    message_type: &str,
-    input_path: &Path,
-    values: &[T],
-    def_levels: Option<&[i16]>,
-) where
-    T: WriteToCw,
-{
-    let schema = Arc::new(parse_message_type(message_type).unwrap());
-    let props = Arc::new(WriterProperties::builder().build());
-    let file = File::create(input_path).unwrap();
-    let mut writer = SerializedFileWriter::new(file, schema, props).unwrap();
-    let mut row_group_writer = writer.next_row_group().unwrap();
-    let mut col_writer = row_group_writer.next_column().unwrap().unwrap();
-    T::write_batch(col_writer.untyped(), values, def_levels);
-    col_writer.close().unwrap();
-    row_group_writer.close().unwrap();
-    writer.close().unwrap();
-}
+/// Execute two jobs from a manifest file over a single connection.
+#[test]
+fn run_jobs_from_manifest() {
+    // Given
+    let table_name = "RunJobsFromManifest";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[["1"], ["2"], ["3"]]);
+    let out_dir = tempdir().unwrap();
+    let first_path = out_dir.path().join("first.par");
+    let second_path = out_dir.path().join("second.par");
+    let query_path = out_dir.path().join("second.sql");
+    std::fs::write(
+        &query_path,
+        format!("SELECT a FROM {table_name} WHERE a > ? ORDER BY id"),
+    )
+    .unwrap();
+    let manifest = format!(
+        r#"
+        [options]
+        batch-size-row = 2
+
+        [[job]]
+        name = "first"
+        query = "SELECT a FROM {table_name} ORDER BY id"
+        output = '{}'
+
+        [[job]]
+        name = "second"
+        query-file = '{}'
+        output = '{}'
+        parameters = ["2"]
+        "#,
+        first_path.to_str().unwrap(),
+        query_path.to_str().unwrap(),
+        second_path.to_str().unwrap(),
+    );
+    let manifest_path = out_dir.path().join("manifest.toml");
+    std::fs::write(&manifest_path, manifest).unwrap();
 
-const COLUMN_NAMES: &[&str] = &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "run",
+            "--connection-string",
+            MSSQL,
+            manifest_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(eq("first: succeeded\nsecond: succeeded\n"));
 
-/// Sets up a table in the mssql database and allows us to fill it with data. Column names are given
-/// automatically a,b,c, etc.
-pub struct TableMssql<'a, const NUM_COLUMNS: usize> {
-    pub name: &'a str,
-    pub conn: Connection<'a>,
+    // Then
+    parquet_read_out(first_path.to_str().unwrap()).stdout(eq("{a: 1}\n{a: 2}\n{a: 3}\n"));
+    parquet_read_out(second_path.to_str().unwrap()).stdout(eq("{a: 3}\n"));
 }
 
-impl<'a, const NUM_COLUMNS: usize> TableMssql<'a, NUM_COLUMNS> {
-    pub fn new(name: &'a str, column_types: &'a [&'a str; NUM_COLUMNS]) -> Self {
-        let conn = ENV
-            .connect_with_connection_string(
-                MSSQL,
-                ConnectionOptions {
-                    login_timeout_sec: Some(5),
-                    ..Default::default()
-                },
-            )
-            .expect("Must be able to connect to MSSQL database.");
-        setup_empty_table_mssql(&conn, name, column_types)
-            .expect("Must be able to setup empty table.");
-        TableMssql { name, conn }
-    }
-
-    pub fn insert_rows_as_text<'b, C>(&mut self, content: &[[C; NUM_COLUMNS]])
-    where
-        C: Into<Option<&'b str>> + Copy,
-    {
-        let statement = self.insert_statement(NUM_COLUMNS);
-        // Insert everything in one go => capacity == length of array
-        let capacity = content.len();
-        let max_str_len = (0..NUM_COLUMNS)
-            .map(|col_index| {
-                (0..content.len())
-                    .map(|row_index| {
-                        let opt: Option<&str> = content[row_index][col_index].into();
-                        opt.map(|s| s.len()).unwrap_or(0)
-                    })
-                    .max()
-                    .unwrap_or(0)
-            })
-            .collect::<Vec<_>>();
-        let mut inserter = self
-            .conn
-            .prepare(&statement)
-            .unwrap()
-            .into_text_inserter(capacity, max_str_len)
-            .unwrap();
-
-        for (row_index, row) in content.iter().enumerate() {
-            for (column_index, element) in row.iter().enumerate() {
-                let element: Option<&str> = (*element).into();
-                let element = element.map(|s| s.as_bytes());
-                inserter
-                    .column_mut(column_index)
-                    .set_cell(row_index, element);
-            }
-        }
+/// A failing job must not prevent the other jobs in the manifest from being executed, yet the
+/// command as a whole is reported as failed.
+#[test]
+fn run_reports_failed_jobs() {
+    // Given
+    let table_name = "RunReportsFailedJobs";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[["42"]]);
+    let out_dir = tempdir().unwrap();
+    let failing_path = out_dir.path().join("failing.par");
+    let succeeding_path = out_dir.path().join("succeeding.par");
+    let manifest = format!(
+        r#"
+        [[job]]
+        name = "failing"
+        query = "SELECT a FROM NonExistingTableName"
+        output = '{}'
+
+        [[job]]
+        name = "succeeding"
+        query = "SELECT a FROM {table_name}"
+        output = '{}'
+        "#,
+        failing_path.to_str().unwrap(),
+        succeeding_path.to_str().unwrap(),
+    );
+    let manifest_path = out_dir.path().join("manifest.toml");
+    std::fs::write(&manifest_path, manifest).unwrap();
 
-        inserter.set_num_rows(content.len());
-        inserter.execute().unwrap();
-    }
+    // When
+    let assert = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "run",
+            "--connection-string",
+            MSSQL,
+            manifest_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("1 of 2 jobs failed."));
 
-    fn insert_statement(&self, number_of_columns: usize) -> String {
-        // A string like e.g. "a,b,c"
-        let columns = COLUMN_NAMES[..number_of_columns].join(",");
-        let placeholders = vec!["?"; number_of_columns].join(",");
-        format!(
-            "INSERT INTO {} ({}) VALUES ({})",
-            self.name, columns, placeholders
-        )
-    }
+    // Then
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(stdout.starts_with("failing: failed: "));
+    assert!(stdout.ends_with("succeeding: succeeded\n"));
+    assert!(!failing_path.exists());
+    parquet_read_out(succeeding_path.to_str().unwrap()).stdout(eq("{a: 42}\n"));
 }
 
-/// Creates the table and assures it is empty. Columns are named a,b,c, etc.
-pub fn setup_empty_table_mssql(
-    conn: &Connection,
-    table_name: &str,
-    column_types: &[&str],
-) -> Result<(), odbc_api::Error> {
-    let identity = "int IDENTITY(1,1)";
-    setup_empty_table(table_name, column_types, conn, identity)
-}
+/// Split the result set into two partitions, which are fetched in parallel.
+#[test]
+fn partitioned_query() {
+    // Given
+    let table_name = "PartitionedQuery";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[
+        [Some("1")],
+        [Some("2")],
+        [Some("3")],
+        [Some("4")],
+        [Some("5")],
+        [Some("6")],
+        [None],
+    ]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a FROM {table_name}");
 
-fn setup_empty_table(
-    table_name: &str,
-    column_types: &[&str],
-    conn: &Connection,
-    identity: &str,
-) -> Result<(), odbc_api::Error> {
-    let drop_table = &format!("DROP TABLE IF EXISTS {table_name}");
-    let cols = column_types
-        .iter()
-        .zip(COLUMN_NAMES)
-        .map(|(ty, name)| format!("{name} {ty}"))
-        .collect::<Vec<_>>()
-        .join(", ");
-    let create_table = format!("CREATE TABLE {table_name} (id {identity},{cols});");
-    conn.execute(drop_table, ())?;
-    conn.execute(&create_table, ())?;
-    Ok(())
-}
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--partition-column",
+            "a",
+            "--partition-count",
+            "2",
+            out_str,
+            &query,
+        ])
+        .assert()
+        .success();
 
-/// Creates the table and assures it is empty (adapted for PostgreSQL). Columns are named a,b,c, etc.
-pub fn setup_empty_table_pg(
-    conn: &Connection,
-    table_name: &str,
-    column_types: &[&str],
-) -> Result<(), odbc_api::Error> {
-    let identity = "SERIAL PRIMARY KEY";
-    setup_empty_table(table_name, column_types, conn, identity)
+    // Then
+    // Rows within a partition are not ordered, since the query is executed as a subquery.
+    let sorted_rows = |file_name: &str| {
+        let path = out_dir.path().join(file_name);
+        let output = parquet_read_out(path.to_str().unwrap())
+            .get_output()
+            .stdout
+            .clone();
+        let mut rows: Vec<_> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        rows.sort();
+        rows
+    };
+    // NULL values are part of the first partition
+    assert_eq!(
+        vec!["{a: 1}", "{a: 2}", "{a: 3}", "{a: null}"],
+        sorted_rows("out_part_01.par")
+    );
+    assert_eq!(
+        vec!["{a: 4}", "{a: 5}", "{a: 6}"],
+        sorted_rows("out_part_02.par")
+    );
 }
 
-/// Test helper using two commands to roundtrip parquet to and from a data source.
-///
-/// # Parameters
-///
-/// * `file`: File used in the roundtrip. Table schema is currently hardcoded.
-/// * `table_name`: Each test must use its unique table name, to avoid race conditions with other
-///   tests.
-fn roundtrip(file: &'static str, table_name: &str) -> Assert {
-    // Setup table for test. We use the table name only in this test.
-    let conn = ENV
-        .connect_with_connection_string(MSSQL, ConnectionOptions::default())
-        .unwrap();
-    conn.execute(&format!("DROP TABLE IF EXISTS {table_name}"), ())
-        .unwrap();
-    conn.execute(
-        &format!("CREATE TABLE {table_name} (country VARCHAR(255), population BIGINT);"),
-        (),
-    )
-    .unwrap();
-
-    // A temporary directory, to be removed at the end of the test.
+#[test]
+fn incremental_query() {
+    // Given
+    let table_name = "IncrementalQuery";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")]]);
     let out_dir = tempdir().unwrap();
-    // The name of the output parquet file we are going to write. Since it is in a temporary
-    // directory it will not outlive the end of the test.
     let out_path = out_dir.path().join("out.par");
-    // We need to pass the output path as a string argument.
     let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let state_path = out_dir.path().join("state.json");
+    let state_str = state_path
+        .to_str()
+        .expect("Temporary file path must be utf8");
+    let query = format!("SELECT a FROM {table_name}");
+    let run = || {
+        Command::cargo_bin("odbc2parquet")
+            .unwrap()
+            .args([
+                "-vvvv",
+                "query",
+                "--connection-string",
+                MSSQL,
+                "--incremental-column",
+                "a",
+                "--state-file",
+                state_str,
+                out_str,
+                &query,
+            ])
+            .assert()
+            .success();
+    };
 
-    let in_path = format!("tests/{file}");
+    // When
+    run();
+    table.insert_rows_as_text(&[[Some("3")]]);
+    run();
 
-    // Insert parquet
+    // Then
+    // Second run only fetches the row inserted after the first one.
+    parquet_read_out(out_str).stdout("{a: 3}\n");
+    let state = std::fs::read_to_string(&state_path).unwrap();
+    let state: serde_json::Value = serde_json::from_str(&state).unwrap();
+    assert_eq!(
+        serde_json::json!({"column": "a", "watermark": {"type": "integer", "value": 3}}),
+        state
+    );
+}
+
+#[test]
+fn hive_partitioned_output() {
+    // Given
+    let table_name = "HivePartitionedOutput";
+    let mut table = TableMssql::new(table_name, &["VARCHAR(10)", "INTEGER"]);
+    table.insert_rows_as_text(&[
+        [Some("DE"), Some("1")],
+        [Some("FR"), Some("2")],
+        [Some("DE"), Some("3")],
+        [None, Some("4")],
+    ]);
+    let out_dir = tempdir().unwrap();
+    let out_str = out_dir
+        .path()
+        .to_str()
+        .expect("Temporary file path must be utf8");
+    let query = format!("SELECT a, b FROM {table_name} ORDER BY id");
+
+    // When
     Command::cargo_bin("odbc2parquet")
         .unwrap()
         .args([
             "-vvvv",
-            "insert",
+            "query",
             "--connection-string",
             MSSQL,
-            &in_path,
-            table_name,
+            "--partition-by",
+            "a",
+            out_str,
+            &query,
         ])
         .assert()
         .success();
 
-    // Query as parquet
+    // Then
+    let read_partition = |partition: &str| {
+        let path = out_dir.path().join(partition).join("part-01.parquet");
+        parquet_read_out(path.to_str().unwrap())
+    };
+    read_partition("a=DE").stdout("{b: 1}\n{b: 3}\n");
+    read_partition("a=FR").stdout("{b: 2}\n");
+    read_partition("a=__HIVE_DEFAULT_PARTITION__").stdout("{b: 4}\n");
+}
+
+#[test]
+fn upload_to_s3() {
+    // Given
+    let table_name = "UploadToS3";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")]]);
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+    let minio = Minio::new();
+
+    // When
     Command::cargo_bin("odbc2parquet")
         .unwrap()
         .args([
@@ -4272,13 +4378,1038 @@ fn roundtrip(file: &'static str, table_name: &str) -> Assert {
             "query",
             "--connection-string",
             MSSQL,
-            out_str,
-            &format!("SELECT country, population FROM {table_name} ORDER BY population;"),
+            "--s3-endpoint",
+            MINIO_ENDPOINT,
+            "--s3-path-style",
+            "--s3-access-key-id",
+            MINIO_ACCESS_KEY,
+            "--s3-secret-access-key",
+            MINIO_SECRET_KEY,
+            "--batch-size-row",
+            "1",
+            "--row-groups-per-file",
+            "1",
+            &format!("s3://{MINIO_BUCKET}/upload_to_s3/out.par"),
+            &query,
         ])
-        .assert();
+        .assert()
+        .success();
 
-    let expectation = String::from_utf8(
-        std::process::Command::new("parquet-read")
+    // Then
+    // Splitting files appends a suffix to the object key, just like for local files.
+    let out_dir = tempdir().unwrap();
+    let first = minio.download("upload_to_s3/out_01.par", out_dir.path());
+    parquet_read_out(first.to_str().unwrap()).stdout("{a: 1}\n");
+    let second = minio.download("upload_to_s3/out_02.par", out_dir.path());
+    parquet_read_out(second.to_str().unwrap()).stdout("{a: 2}\n");
+}
+
+#[test]
+fn write_manifest() {
+    // Given
+    let table_name = "WriteManifest";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")], [Some("3")]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let manifest_path = out_dir.path().join("manifest.json");
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "2",
+            "--manifest",
+            manifest_path.to_str().unwrap(),
+            out_path.to_str().unwrap(),
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert_eq!(query, manifest["query"]);
+    assert_eq!(3, manifest["total_num_rows"]);
+    let files = manifest["files"].as_array().unwrap();
+    assert_eq!(1, files.len());
+    assert_eq!(out_path.to_str().unwrap(), files[0]["path"]);
+    assert_eq!(3, files[0]["num_rows"]);
+    assert_eq!(2, files[0]["num_row_groups"]);
+    assert_eq!(
+        std::fs::metadata(&out_path).unwrap().len(),
+        files[0]["size_bytes"]
+    );
+    assert_eq!(64, files[0]["sha256"].as_str().unwrap().len());
+    assert!(manifest["schema"].as_str().unwrap().contains("a"));
+}
+
+#[test]
+fn transactional_output_with_success_marker() {
+    // Given
+    let table_name = "TransactionalOutputWithSuccessMarker";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "1",
+            "--row-groups-per-file",
+            "1",
+            "--transactional",
+            "--success-marker",
+            out_path.to_str().unwrap(),
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let mut entries: Vec<_> = out_dir
+        .path()
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    assert_eq!(vec!["_SUCCESS", "out_01.par", "out_02.par"], entries);
+    parquet_read_out(out_dir.path().join("out_02.par").to_str().unwrap()).stdout("{a: 2}\n");
+}
+
+#[test]
+fn transactional_output_is_discarded_on_failure() {
+    // Given
+    let table_name = "TransactionalOutputIsDiscardedOnFailure";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")], [Some("3")]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    // Fetching the last row fails with a division by zero, after the first files are complete.
+    let query = format!("SELECT 6 / (3 - a) AS b FROM {table_name} ORDER BY id");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "1",
+            "--row-groups-per-file",
+            "1",
+            "--transactional",
+            "--success-marker",
+            out_path.to_str().unwrap(),
+            &query,
+        ])
+        .assert()
+        .failure();
+
+    // Then
+    assert_eq!(0, out_dir.path().read_dir().unwrap().count());
+}
+
+#[test]
+fn describe_columns_as_json() {
+    // Given
+    let table_name = "DescribeColumnsAsJson";
+    TableMssql::new(table_name, &["INTEGER NOT NULL", "VARCHAR(10)"]);
+    let query = format!("SELECT a, b FROM {table_name}");
+
+    // When
+    let output = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "describe",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "100",
+            "--format",
+            "json",
+            &query,
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // Then
+    let report: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    let columns = report["columns"].as_array().unwrap();
+    assert_eq!(2, columns.len());
+    assert_eq!("a", columns[0]["name"]);
+    assert_eq!("Integer", columns[0]["odbc_data_type"]);
+    assert_eq!("IdenticalRequired<Int32Type>", columns[0]["strategy"]);
+    assert_eq!(4, columns[0]["buffer_bytes_per_row"]);
+    assert_eq!("b", columns[1]["name"]);
+    assert_eq!("Utf8", columns[1]["strategy"]);
+    assert!(columns[1]["parquet_type"]
+        .as_str()
+        .unwrap()
+        .contains("BYTE_ARRAY"));
+    assert_eq!(100, report["batch_size_rows"]);
+}
+
+#[test]
+fn override_column_types() {
+    // Given
+    let table_name = "OverrideColumnTypes";
+    let mut table = TableMssql::new(table_name, &["VARCHAR(20)", "VARCHAR(20)"]);
+    table.insert_rows_as_text(&[[Some("42"), Some("12.34")], [Some("-7"), None]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let query = format!("SELECT a, b FROM {table_name} ORDER BY id");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--column-type",
+            "a:int64",
+            "--column-type",
+            "B:decimal(10,2)",
+            out_path.to_str().unwrap(),
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    parquet_schema_out(out_path.to_str().unwrap())
+        .stdout(contains("OPTIONAL INT64 a (INTEGER(64,true));"))
+        .stdout(contains("OPTIONAL INT64 b (DECIMAL(10,2));"));
+    parquet_read_out(out_path.to_str().unwrap()).stdout("{a: 42, b: 12.34}\n{a: -7, b: null}\n");
+}
+
+#[test]
+fn reject_impossible_column_type_override() {
+    // Given
+    let table_name = "RejectImpossibleColumnTypeOverride";
+    TableMssql::new(table_name, &["VARBINARY(10)"]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let query = format!("SELECT a FROM {table_name}");
+
+    // Then
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--column-type",
+            "a:date",
+            out_path.to_str().unwrap(),
+            &query,
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Column 'a' can not be mapped to 'date'"));
+}
+
+#[test]
+fn compression_per_column() {
+    // Given
+    let table_name = "CompressionPerColumn";
+    let mut table = TableMssql::new(table_name, &["VARCHAR(50)", "INTEGER"]);
+    table.insert_rows_as_text(&[[Some("some text"), Some("42")]]);
+    let query = format!("SELECT a, b FROM {table_name}");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--column-compression",
+            "a:zstd:19",
+            "--column-compression",
+            "b:snappy",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let row_group = reader.metadata().row_group(0);
+    // Compression levels are not stored in the file.
+    assert_eq!(
+        Compression::ZSTD(Default::default()),
+        row_group.column(0).compression()
+    );
+    assert_eq!(Compression::SNAPPY, row_group.column(1).compression());
+}
+
+#[test]
+fn bloom_filter_for_selected_columns() {
+    // Given
+    let table_name = "BloomFilterForSelectedColumns";
+    let mut table = TableMssql::new(table_name, &["INTEGER", "INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1"), Some("2")]]);
+    let query = format!("SELECT a, b FROM {table_name}");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--bloom-filter",
+            "a:0.01:1000",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let row_group = reader.metadata().row_group(0);
+    assert!(row_group.column(0).bloom_filter_offset().is_some());
+    assert!(row_group.column(1).bloom_filter_offset().is_none());
+}
+
+#[test]
+fn bloom_filter_for_unknown_column() {
+    // Given
+    let table_name = "BloomFilterForUnknownColumn";
+    TableMssql::new(table_name, &["INTEGER"]);
+    let query = format!("SELECT a FROM {table_name}");
+
+    // Then
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--bloom-filter",
+            "customer_id",
+            "-",
+            &query,
+        ])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Can not write bloom filter for column 'customer_id'",
+        ));
+}
+
+#[test]
+fn accumulate_batches_into_row_groups() {
+    // Given
+    let table_name = "AccumulateBatchesIntoRowGroups";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")], [Some("3")]]);
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "1",
+            "--row-group-size-rows",
+            "2",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let row_group_sizes: Vec<i64> = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows())
+        .collect();
+    assert_eq!(vec![2, 1], row_group_sizes);
+}
+
+#[test]
+fn split_batch_into_row_groups() {
+    // Given
+    let table_name = "SplitBatchIntoRowGroups";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("1")], [Some("2")], [Some("3")]]);
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--batch-size-row",
+            "3",
+            "--row-group-size-rows",
+            "1",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    assert_eq!(3, reader.metadata().num_row_groups());
+}
+
+#[test]
+fn provenance_metadata() {
+    // Given
+    let table_name = "ProvenanceMetadata";
+    let mut table = TableMssql::new(table_name, &["INTEGER"]);
+    table.insert_rows_as_text(&[[Some("42")]]);
+    let query = format!("SELECT a FROM {table_name} WHERE a > ?");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--metadata",
+            "owner=data-team",
+            "-",
+            &query,
+            "1",
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let key_value_metadata = reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .unwrap();
+    let value = |key: &str| {
+        key_value_metadata
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.clone())
+            .unwrap()
+    };
+    assert_eq!(query, value("odbc2parquet.query"));
+    assert_eq!(r#"["1"]"#, value("odbc2parquet.parameters"));
+    assert_eq!("Microsoft SQL Server", value("odbc2parquet.dbms_name"));
+    assert_eq!(
+        r#"[{"name":"a","data_type":"Integer","nullability":"Nullable"}]"#,
+        value("odbc2parquet.odbc_column_types")
+    );
+    assert!(value("odbc2parquet.extraction_start") <= value("odbc2parquet.file_written_at"));
+    assert_eq!("data-team", value("owner"));
+}
+
+#[test]
+fn uniqueidentifier_to_uuid() {
+    // Given
+    let table_name = "UniqueidentifierToUuid";
+    let mut table = TableMssql::new(table_name, &["UNIQUEIDENTIFIER"]);
+    table.insert_rows_as_text(&[[Some("6F9619FF-8B86-D011-B42D-00C04FC964FF")]]);
+    let query = format!("SELECT a FROM {table_name}");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args(["query", "--connection-string", MSSQL, "-", &query])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let column = reader.metadata().file_metadata().schema_descr().column(0);
+    assert_eq!(Some(LogicalType::Uuid), column.logical_type());
+    let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+    assert_eq!(
+        &[
+            0x6F, 0x96, 0x19, 0xFF, 0x8B, 0x86, 0xD0, 0x11, 0xB4, 0x2D, 0x00, 0xC0, 0x4F, 0xC9,
+            0x64, 0xFF
+        ],
+        row.get_bytes(0).unwrap().data()
+    );
+}
+
+#[test]
+fn uniqueidentifier_as_text() {
+    // Given
+    let table_name = "UniqueidentifierAsText";
+    let mut table = TableMssql::new(table_name, &["UNIQUEIDENTIFIER"]);
+    table.insert_rows_as_text(&[[Some("6F9619FF-8B86-D011-B42D-00C04FC964FF")]]);
+    let query = format!("SELECT a FROM {table_name}");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--uuid-as-text",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+    assert_eq!(
+        "6F9619FF-8B86-D011-B42D-00C04FC964FF",
+        row.get_string(0).unwrap()
+    );
+}
+
+#[test]
+fn query_arrays_postgres() {
+    // Setup table for test
+    let table_name = "QueryArrays";
+    let conn = ENV
+        .connect_with_connection_string(POSTGRES, ConnectionOptions::default())
+        .unwrap();
+    setup_empty_table_pg(&conn, table_name, &["INTEGER[]", "TEXT[]"]).unwrap();
+    let insert = format!(
+        "INSERT INTO {table_name}
+        (a, b)
+        VALUES
+        ('{{1,NULL,3}}', '{{\"x y\",NULL}}'),
+        (NULL, '{{}}');"
+    );
+    conn.execute(&insert, ()).unwrap();
+    // A temporary directory, to be removed at the end of the test.
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a, b FROM {table_name} ORDER BY id;");
+
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            out_str,
+            "--connection-string",
+            POSTGRES,
+            &query,
+        ])
+        .assert()
+        .success();
+
+    let expected_values = "{a: [1, null, 3], b: [\"x y\", null]}\n{a: null, b: []}\n";
+    parquet_read_out(out_str).stdout(eq(expected_values));
+    parquet_schema_out(out_str).stdout(contains("OPTIONAL INT32 element (INTEGER(32,true));"));
+}
+
+#[test]
+fn json_column_as_nested_struct() {
+    // Given
+    let table_name = "JsonColumnAsNestedStruct";
+    let mut table = TableMssql::new(table_name, &["VARCHAR(100)"]);
+    table.insert_rows_as_text(&[
+        [Some(r#"{"id": 1, "tags": ["a", "b"], "extra": true}"#)],
+        [Some("not json")],
+        [None],
+    ]);
+    let out_dir = tempdir().unwrap();
+    let schema_path = out_dir.path().join("schema.json");
+    std::fs::write(
+        &schema_path,
+        r#"{"struct": [
+            {"name": "id", "type": "int64"},
+            {"name": "tags", "type": {"list": "string"}}
+        ]}"#,
+    )
+    .unwrap();
+    let json_column = format!("a:{}", schema_path.to_str().unwrap());
+    let query = format!("SELECT a FROM {table_name} ORDER BY id");
+
+    // When
+    let command = Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            "--connection-string",
+            MSSQL,
+            "--json-column",
+            &json_column,
+            "--json-invalid",
+            "null",
+            "-",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let bytes = Bytes::from(command.get_output().stdout.clone());
+    let reader = SerializedFileReader::new(bytes).unwrap();
+    let rows: Vec<String> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            r#"{a: {id: 1, tags: ["a", "b"]}}"#,
+            "{a: null}",
+            "{a: null}"
+        ],
+        rows
+    );
+}
+
+#[test]
+fn convert_naive_timestamps_from_source_timezone() {
+    // Given
+    let table_name = "ConvertNaiveTimestampsFromSourceTimezone";
+    let mut table = TableMssql::new(table_name, &["DATETIME2(3)"]);
+    table.insert_rows_as_text(&[["2024-01-15 12:00:00"], ["2024-07-15 12:00:00"]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a FROM {table_name} ORDER BY id;");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            out_str,
+            "--connection-string",
+            MSSQL,
+            "--source-timezone",
+            "Europe/Berlin",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let expected_values = "{a: 2024-01-15 11:00:00 +00:00}\n{a: 2024-07-15 10:00:00 +00:00}\n";
+    parquet_read_out(out_str).stdout(eq(expected_values));
+    parquet_schema_out(out_str).stdout(contains("OPTIONAL INT64 a (TIMESTAMP(MILLIS,true));"));
+}
+
+#[test]
+fn query_timestamptz_postgres() {
+    // Setup table for test
+    let table_name = "QueryTimestamptz";
+    let conn = ENV
+        .connect_with_connection_string(POSTGRES, ConnectionOptions::default())
+        .unwrap();
+    setup_empty_table_pg(&conn, table_name, &["TIMESTAMPTZ"]).unwrap();
+    let insert = format!(
+        "INSERT INTO {table_name}
+        (a)
+        VALUES
+        ('2022-09-07 16:04:12.123+02');"
+    );
+    conn.execute(&insert, ()).unwrap();
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a FROM {table_name};");
+
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            out_str,
+            "--connection-string",
+            POSTGRES,
+            &query,
+        ])
+        .assert()
+        .success();
+
+    let expected_values = "{a: 2022-09-07 14:04:12.123 +00:00}\n";
+    parquet_read_out(out_str).stdout(eq(expected_values));
+    parquet_schema_out(out_str).stdout(contains("OPTIONAL INT64 a (TIMESTAMP(MICROS,true));"));
+    let reader = SerializedFileReader::new(File::open(&out_path).unwrap()).unwrap();
+    let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+    assert_eq!(1662559452123000, row.get_timestamp_micros(0).unwrap());
+}
+
+#[test]
+fn force_timestamp_unit_and_int96() {
+    // Given
+    let table_name = "ForceTimestampUnitAndInt96";
+    let mut table = TableMssql::new(table_name, &["DATETIME2(7)"]);
+    table.insert_rows_as_text(&[["2022-09-07 16:04:12.1234567"]]);
+    let out_dir = tempdir().unwrap();
+    let micros_path = out_dir.path().join("micros.par");
+    let micros_str = micros_path
+        .to_str()
+        .expect("Temporary file path must be utf8");
+    let int96_path = out_dir.path().join("int96.par");
+    let int96_str = int96_path
+        .to_str()
+        .expect("Temporary file path must be utf8");
+    let query = format!("SELECT a FROM {table_name};");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            micros_str,
+            "--connection-string",
+            MSSQL,
+            "--timestamp-unit",
+            "micros",
+            &query,
+        ])
+        .assert()
+        .success();
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            int96_str,
+            "--connection-string",
+            MSSQL,
+            "--int96-timestamps",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let expected_values = "{a: 2022-09-07 16:04:12 +00:00}\n";
+    parquet_read_out(micros_str).stdout(eq(expected_values));
+    parquet_schema_out(micros_str).stdout(contains("OPTIONAL INT64 a (TIMESTAMP(MICROS,false));"));
+    parquet_read_out(int96_str).stdout(eq(expected_values));
+    parquet_schema_out(int96_str).stdout(contains("OPTIONAL INT96 a;"));
+}
+
+#[test]
+fn compat_hive() {
+    // Given
+    let table_name = "CompatHive";
+    let mut table = TableMssql::new(table_name, &["DECIMAL(5,2)"]);
+    table.insert_rows_as_text(&[["123.45"]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a AS [unit price] FROM {table_name};");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            out_str,
+            "--connection-string",
+            MSSQL,
+            "--compat",
+            "hive",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    parquet_read_out(out_str).stdout(eq("{unit_price: 123.45}\n"));
+    parquet_schema_out(out_str).stdout(contains(
+        "OPTIONAL FIXED_LEN_BYTE_ARRAY (3) unit_price (DECIMAL(5,2));",
+    ));
+}
+
+#[test]
+fn trim_char_padding() {
+    // Given
+    let table_name = "TrimCharPadding";
+    let mut table = TableMssql::new(table_name, &["CHAR(5)", "NCHAR(5)", "VARCHAR(5)"]);
+    table.insert_rows_as_text(&[["ab", "cd", "ef  "]]);
+    let out_dir = tempdir().unwrap();
+    let out_path = out_dir.path().join("out.par");
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+    let query = format!("SELECT a, b, c FROM {table_name};");
+
+    // When
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "query",
+            out_str,
+            "--connection-string",
+            MSSQL,
+            "--trim-char-padding-column",
+            "a",
+            &query,
+        ])
+        .assert()
+        .success();
+
+    // Then
+    let expected_values = "{a: \"ab\", b: \"cd   \", c: \"ef  \"}\n";
+    parquet_read_out(out_str).stdout(eq(expected_values));
+}
+
+#[test]
+fn list_quirks() {
+    let out_dir = tempdir().unwrap();
+    let quirks_path = out_dir.path().join("quirks.toml");
+    std::fs::write(
+        &quirks_path,
+        "[[quirk]]\ndbms-name = \"Informix*\"\ndriver-does-not-support-64bit-integers = true\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "list-quirks",
+            "--quirks-file",
+            quirks_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(contains("dbms-name = \"Informix*\""))
+        .stdout(contains("dbms-name = \"Oracle\""));
+}
+
+/// Writes a parquet file with one row group and one column.
+fn write_values_to_file<T>(
+    message_type: &str,
+    input_path: &Path,
+    values: &[T],
+    def_levels: Option<&[i16]>,
+) where
+    T: WriteToCw,
+{
+    let schema = Arc::new(parse_message_type(message_type).unwrap());
+    let props = Arc::new(WriterProperties::builder().build());
+    let file = File::create(input_path).unwrap();
+    let mut writer = SerializedFileWriter::new(file, schema, props).unwrap();
+    let mut row_group_writer = writer.next_row_group().unwrap();
+    let mut col_writer = row_group_writer.next_column().unwrap().unwrap();
+    T::write_batch(col_writer.untyped(), values, def_levels);
+    col_writer.close().unwrap();
+    row_group_writer.close().unwrap();
+    writer.close().unwrap();
+}
+
+const COLUMN_NAMES: &[&str] = &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l"];
+
+/// Sets up a table in the mssql database and allows us to fill it with data. Column names are given
+/// automatically a,b,c, etc.
+pub struct TableMssql<'a, const NUM_COLUMNS: usize> {
+    pub name: &'a str,
+    pub conn: Connection<'a>,
+}
+
+impl<'a, const NUM_COLUMNS: usize> TableMssql<'a, NUM_COLUMNS> {
+    pub fn new(name: &'a str, column_types: &'a [&'a str; NUM_COLUMNS]) -> Self {
+        let conn = ENV
+            .connect_with_connection_string(
+                MSSQL,
+                ConnectionOptions {
+                    login_timeout_sec: Some(5),
+                    ..Default::default()
+                },
+            )
+            .expect("Must be able to connect to MSSQL database.");
+        setup_empty_table_mssql(&conn, name, column_types)
+            .expect("Must be able to setup empty table.");
+        TableMssql { name, conn }
+    }
+
+    pub fn insert_rows_as_text<'b, C>(&mut self, content: &[[C; NUM_COLUMNS]])
+    where
+        C: Into<Option<&'b str>> + Copy,
+    {
+        let statement = self.insert_statement(NUM_COLUMNS);
+        // Insert everything in one go => capacity == length of array
+        let capacity = content.len();
+        let max_str_len = (0..NUM_COLUMNS)
+            .map(|col_index| {
+                (0..content.len())
+                    .map(|row_index| {
+                        let opt: Option<&str> = content[row_index][col_index].into();
+                        opt.map(|s| s.len()).unwrap_or(0)
+                    })
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let mut inserter = self
+            .conn
+            .prepare(&statement)
+            .unwrap()
+            .into_text_inserter(capacity, max_str_len)
+            .unwrap();
+
+        for (row_index, row) in content.iter().enumerate() {
+            for (column_index, element) in row.iter().enumerate() {
+                let element: Option<&str> = (*element).into();
+                let element = element.map(|s| s.as_bytes());
+                inserter
+                    .column_mut(column_index)
+                    .set_cell(row_index, element);
+            }
+        }
+
+        inserter.set_num_rows(content.len());
+        inserter.execute().unwrap();
+    }
+
+    fn insert_statement(&self, number_of_columns: usize) -> String {
+        // A string like e.g. "a,b,c"
+        let columns = COLUMN_NAMES[..number_of_columns].join(",");
+        let placeholders = vec!["?"; number_of_columns].join(",");
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.name, columns, placeholders
+        )
+    }
+}
+
+/// Creates the table and assures it is empty. Columns are named a,b,c, etc.
+pub fn setup_empty_table_mssql(
+    conn: &Connection,
+    table_name: &str,
+    column_types: &[&str],
+) -> Result<(), odbc_api::Error> {
+    let identity = "int IDENTITY(1,1)";
+    setup_empty_table(table_name, column_types, conn, identity)
+}
+
+fn setup_empty_table(
+    table_name: &str,
+    column_types: &[&str],
+    conn: &Connection,
+    identity: &str,
+) -> Result<(), odbc_api::Error> {
+    let drop_table = &format!("DROP TABLE IF EXISTS {table_name}");
+    let cols = column_types
+        .iter()
+        .zip(COLUMN_NAMES)
+        .map(|(ty, name)| format!("{name} {ty}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let create_table = format!("CREATE TABLE {table_name} (id {identity},{cols});");
+    conn.execute(drop_table, ())?;
+    conn.execute(&create_table, ())?;
+    Ok(())
+}
+
+/// Creates the table and assures it is empty (adapted for PostgreSQL). Columns are named a,b,c, etc.
+pub fn setup_empty_table_pg(
+    conn: &Connection,
+    table_name: &str,
+    column_types: &[&str],
+) -> Result<(), odbc_api::Error> {
+    let identity = "SERIAL PRIMARY KEY";
+    setup_empty_table(table_name, column_types, conn, identity)
+}
+
+/// Test helper using two commands to roundtrip parquet to and from a data source.
+///
+/// # Parameters
+///
+/// * `file`: File used in the roundtrip. Table schema is currently hardcoded.
+/// * `table_name`: Each test must use its unique table name, to avoid race conditions with other
+///   tests.
+fn roundtrip(file: &'static str, table_name: &str) -> Assert {
+    // Setup table for test. We use the table name only in this test.
+    let conn = ENV
+        .connect_with_connection_string(MSSQL, ConnectionOptions::default())
+        .unwrap();
+    conn.execute(&format!("DROP TABLE IF EXISTS {table_name}"), ())
+        .unwrap();
+    conn.execute(
+        &format!("CREATE TABLE {table_name} (country VARCHAR(255), population BIGINT);"),
+        (),
+    )
+    .unwrap();
+
+    // A temporary directory, to be removed at the end of the test.
+    let out_dir = tempdir().unwrap();
+    // The name of the output parquet file we are going to write. Since it is in a temporary
+    // directory it will not outlive the end of the test.
+    let out_path = out_dir.path().join("out.par");
+    // We need to pass the output path as a string argument.
+    let out_str = out_path.to_str().expect("Temporary file path must be utf8");
+
+    let in_path = format!("tests/{file}");
+
+    // Insert parquet
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "insert",
+            "--connection-string",
+            MSSQL,
+            &in_path,
+            table_name,
+        ])
+        .assert()
+        .success();
+
+    // Query as parquet
+    Command::cargo_bin("odbc2parquet")
+        .unwrap()
+        .args([
+            "-vvvv",
+            "query",
+            "--connection-string",
+            MSSQL,
+            out_str,
+            &format!("SELECT country, population FROM {table_name} ORDER BY population;"),
+        ])
+        .assert();
+
+    let expectation = String::from_utf8(
+        std::process::Command::new("parquet-read")
             .args(&[&in_path][..])
             .output()
             .unwrap()
@@ -4349,3 +5480,53 @@ impl_write_to_cw!(f32, FloatColumnWriter);
 impl_write_to_cw!(f64, DoubleColumnWriter);
 impl_write_to_cw!(ByteArray, ByteArrayColumnWriter);
 impl_write_to_cw!(FixedLenByteArray, FixedLenByteArrayColumnWriter);
+
+/// Access to the bucket in the MinIO instance used to test uploading output into S3 compatible
+/// object storage.
+struct Minio {
+    bucket: Bucket,
+    credentials: Credentials,
+}
+
+impl Minio {
+    /// Creates the test bucket, should it not exist yet.
+    fn new() -> Self {
+        let bucket = Bucket::new(
+            MINIO_ENDPOINT.parse().unwrap(),
+            UrlStyle::Path,
+            MINIO_BUCKET,
+            "us-east-1",
+        )
+        .unwrap();
+        let credentials = Credentials::new(MINIO_ACCESS_KEY, MINIO_SECRET_KEY);
+        let url = bucket
+            .create_bucket(&credentials)
+            .sign(Duration::from_secs(60));
+        match ureq::put(url.as_str()).send_empty() {
+            // 409 Conflict: Bucket already exists
+            Ok(_) | Err(ureq::Error::StatusCode(409)) => (),
+            Err(err) => panic!("Must be able to create bucket in MinIO: {err}"),
+        }
+        Minio {
+            bucket,
+            credentials,
+        }
+    }
+
+    /// Downloads the object into the directory and returns the path of the downloaded file.
+    fn download(&self, key: &str, dir: &Path) -> PathBuf {
+        let url = self
+            .bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(Duration::from_secs(60));
+        let content = ureq::get(url.as_str())
+            .call()
+            .expect("Object must exist in MinIO")
+            .body_mut()
+            .read_to_vec()
+            .unwrap();
+        let path = dir.join(key.replace('/', "_"));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}